@@ -0,0 +1,129 @@
+#!/usr/bin/env python3
+"""Writes CjkMetrics.ttf, a metric-only font laid out like a proportional
+Japanese Gothic: half-width ASCII, full-width ideographs, CJK punctuation
+and full-width forms, proportional kana and half-width katakana.
+
+Only the tables text measurement reads are written (cmap, head, hhea, hmtx,
+maxp, name). There are no outlines. Run from the repository root:
+
+    python3 fixtures/make_cjk_metrics.py
+"""
+
+import struct
+
+UNITS_PER_EM = 1000
+FAMILY = "CJK Metrics"
+
+# Proportional Latin: rough Gothic advances, grouped by shape.
+NARROW = set("!'(),-./:;I[]`fijlrt|")
+WIDE = set("%&@MWmw")
+
+
+def ascii_advance(ch):
+    if ch == " ":
+        return 280
+    if ch in NARROW:
+        return 300
+    if ch in WIDE:
+        return 820
+    if ch.isupper() or ch.isdigit():
+        return 620
+    return 540
+
+
+SMALL_KANA = set("ぁぃぅぇぉっゃゅょゎゕゖァィゥェォッャュョヮヵヶ")
+
+
+def kana_advance(ch):
+    # proportional kana: small kana narrow, the rest spread over 820-980
+    # by a fixed hash of the code point so the widths are reproducible
+    if ch in SMALL_KANA:
+        return 640
+    if ch == "ー":
+        return 1000
+    return 820 + (ord(ch) * 37 % 9) * 20
+
+
+def ranges():
+    """(first, last, advance function) in glyph order."""
+    return [
+        (0x0020, 0x007E, lambda c: ascii_advance(chr(c))),
+        (0x3000, 0x303F, lambda c: UNITS_PER_EM),  # CJK symbols and punctuation
+        (0x3041, 0x3096, lambda c: kana_advance(chr(c))),  # hiragana
+        (0x30A1, 0x30FA, lambda c: kana_advance(chr(c))),  # katakana
+        (0x30FC, 0x30FC, lambda c: kana_advance(chr(c))),
+        (0xFF01, 0xFF5E, lambda c: UNITS_PER_EM),  # full-width ASCII forms
+        (0xFF61, 0xFF9F, lambda c: UNITS_PER_EM // 2),  # half-width katakana
+        # ideographs last: all one em, so they share the final hmtx entry
+        (0x4E00, 0x9FFF, lambda c: UNITS_PER_EM),
+    ]
+
+
+def build():
+    advances = [UNITS_PER_EM]  # .notdef
+    groups = []
+    for first, last, advance in ranges():
+        groups.append((first, last, len(advances)))
+        advances.extend(advance(c) for c in range(first, last + 1))
+    num_glyphs = len(advances)
+    # trailing glyphs of equal advance repeat the last long metric
+    num_metrics = num_glyphs
+    while num_metrics > 1 and advances[num_metrics - 2] == advances[-1]:
+        num_metrics -= 1
+
+    groups.sort()
+    cmap12 = struct.pack(">HHIII", 12, 0, 16 + 12 * len(groups), 0, len(groups))
+    cmap12 += b"".join(struct.pack(">III", *g) for g in groups)
+    cmap = struct.pack(">HHHHI", 0, 1, 3, 10, 12) + cmap12
+
+    head = struct.pack(
+        ">IIIIHHqqhhhhHHhhh",
+        0x00010000, 0x00010000, 0, 0x5F0F3CF5, 0x000B, UNITS_PER_EM,
+        0, 0, 0, -120, max(advances), 880, 0, 8, 2, 0, 0,
+    )
+    hhea = struct.pack(
+        ">IhhhHhhhhhhhhhhhH",
+        0x00010000, 880, -120, 0, max(advances), 0, 0, max(advances),
+        1, 0, 0, 0, 0, 0, 0, 0, num_metrics,
+    )
+    maxp = struct.pack(">IH", 0x00005000, num_glyphs)
+    hmtx = b"".join(struct.pack(">Hh", a, 0) for a in advances[:num_metrics])
+    hmtx += b"\0\0" * (num_glyphs - num_metrics)
+
+    names = [(1, FAMILY), (2, "Regular"), (4, FAMILY)]
+    strings = b""
+    records = b""
+    for name_id, text in names:
+        data = text.encode("utf-16-be")
+        records += struct.pack(">HHHHHH", 3, 1, 0x409, name_id, len(data), len(strings))
+        strings += data
+    name = struct.pack(">HHH", 0, len(names), 6 + len(records)) + records + strings
+
+    tables = sorted({
+        b"cmap": cmap, b"head": head, b"hhea": hhea,
+        b"hmtx": hmtx, b"maxp": maxp, b"name": name,
+    }.items())
+
+    def checksum(data):
+        data += b"\0" * (-len(data) % 4)
+        return sum(struct.unpack(">%dI" % (len(data) // 4), data)) & 0xFFFFFFFF
+
+    count = len(tables)
+    search = 1 << (count.bit_length() - 1)
+    out = struct.pack(">IHHHH", 0x00010000, count, search * 16, count.bit_length() - 1, count * 16 - search * 16)
+    offset = 12 + 16 * count
+    directory, body = b"", b""
+    for tag, data in tables:
+        directory += struct.pack(">4sIII", tag, checksum(data), offset + len(body), len(data))
+        body += data + b"\0" * (-len(data) % 4)
+    font = bytearray(out + directory + body)
+
+    # head.checkSumAdjustment over the whole font
+    head_offset = offset + sum(len(d) + (-len(d) % 4) for t, d in tables if t < b"head")
+    struct.pack_into(">I", font, head_offset + 8, (0xB1B0AFBA - checksum(bytes(font))) & 0xFFFFFFFF)
+    return bytes(font)
+
+
+if __name__ == "__main__":
+    with open("fixtures/CjkMetrics.ttf", "wb") as f:
+        f.write(build())
@@ -0,0 +1,278 @@
+// ============================================
+// PRODUCER FINGERPRINTS FOR ATTRIBUTION
+// ============================================
+
+use crate::crossformat::ExportLine;
+use crate::measure_text_kerning;
+use crate::output::{csv_field, OutputFormat};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fs;
+use std::io;
+use ttf_parser::Face;
+
+/// One visible (unredacted) run: the width found in the document and the
+/// width of the same text measured with the reference font.
+#[derive(Clone, Debug)]
+pub struct SpacingSample {
+    pub text: String,
+    pub observed_width: f32,
+    pub measured_width: f32,
+}
+
+impl SpacingSample {
+    /// Samples for the runs of an export (see `load_export_lines`), each
+    /// measured with `face` at `px_size`. Runs without text are skipped.
+    pub fn from_runs(
+        runs: &[ExportLine],
+        face: &Face,
+        glyphs: &HashMap<char, f32>,
+        px_size: f32,
+    ) -> Vec<Self> {
+        runs.iter()
+            .filter(|r| !r.text.trim().is_empty())
+            .map(|r| SpacingSample {
+                text: r.text.clone(),
+                observed_width: r.width,
+                measured_width: measure_text_kerning(&r.text, face, glyphs, px_size),
+            })
+            .collect()
+    }
+}
+
+/// Spacing micro-pattern of a producing tool. `quantum_px` is the layout
+/// grid the tool rounds positions to (None for effectively continuous
+/// layout), `scale` the systematic width ratio against the reference font
+/// and `residual_sd` the spread left after removing that ratio.
+#[derive(Clone, Debug)]
+pub struct ProducerFingerprint {
+    pub name: String,
+    pub quantum_px: Option<f32>,
+    pub scale: f32,
+    pub residual_sd: f32,
+}
+
+/// Layout grids at 96 dpi: twips (1/20 pt), 1/100 mm and whole points.
+pub const TWIP_PX: f32 = 96.0 / 1440.0;
+pub const HUNDREDTH_MM_PX: f32 = 96.0 / 2540.0;
+pub const POINT_PX: f32 = 96.0 / 72.0;
+
+/// Rough starting points only. Build real fingerprints from reference
+/// documents with `ProducerFingerprint::from_samples` before trusting a
+/// report.
+pub fn default_fingerprints() -> Vec<ProducerFingerprint> {
+    vec![
+        ProducerFingerprint {
+            name: "Word".to_string(),
+            quantum_px: Some(TWIP_PX),
+            scale: 1.0,
+            residual_sd: 0.03,
+        },
+        ProducerFingerprint {
+            name: "LibreOffice".to_string(),
+            quantum_px: Some(HUNDREDTH_MM_PX),
+            scale: 1.0,
+            residual_sd: 0.02,
+        },
+        ProducerFingerprint {
+            name: "LaTeX".to_string(),
+            quantum_px: None,
+            scale: 1.0,
+            residual_sd: 0.15, // glue stretch on justified lines
+        },
+    ]
+}
+
+/// How strongly the widths sit on a grid of step `quantum`: 1.0 when every
+/// width is an exact multiple, near 0.0 for uniformly spread values.
+pub fn grid_coherence(widths: &[f32], quantum: f32) -> f32 {
+    if widths.is_empty() || quantum <= 0.0 {
+        return 0.0;
+    }
+
+    let (re, im) = widths.iter().fold((0.0, 0.0), |(re, im), &w| {
+        let phase = 2.0 * PI * w / quantum;
+        (re + phase.cos(), im + phase.sin())
+    });
+
+    (re * re + im * im).sqrt() / widths.len() as f32
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    values[values.len() / 2]
+}
+
+/// Scale and residual spread of a sample set.
+fn scale_and_spread(samples: &[SpacingSample]) -> (f32, f32) {
+    let mut ratios: Vec<f32> = samples
+        .iter()
+        .filter(|s| s.measured_width > 0.0)
+        .map(|s| s.observed_width / s.measured_width)
+        .collect();
+    let scale = median(&mut ratios);
+
+    let residuals: Vec<f32> = samples
+        .iter()
+        .map(|s| s.observed_width - s.measured_width * scale)
+        .collect();
+    let n = residuals.len().max(1) as f32;
+    let sd = (residuals.iter().map(|r| r * r).sum::<f32>() / n).sqrt();
+
+    (scale, sd)
+}
+
+impl ProducerFingerprint {
+    /// Learns a fingerprint from runs of a document known to come from
+    /// `name`. The grid is picked among the known layout units.
+    pub fn from_samples(name: &str, samples: &[SpacingSample]) -> Self {
+        let (scale, residual_sd) = scale_and_spread(samples);
+        let widths: Vec<f32> = samples.iter().map(|s| s.observed_width).collect();
+
+        let quantum_px = [TWIP_PX, HUNDREDTH_MM_PX, POINT_PX]
+            .into_iter()
+            .map(|q| (q, grid_coherence(&widths, q)))
+            .filter(|&(_, c)| c > 0.8)
+            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(q, _)| q);
+
+        ProducerFingerprint {
+            name: name.to_string(),
+            quantum_px,
+            scale,
+            residual_sd,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AttributionScore {
+    pub producer: String,
+    pub distance: f32,
+    pub probability: f32,
+}
+
+/// Ranks producers by how well their fingerprint explains the samples,
+/// most likely first. Probabilities are a softmax over negative distances.
+pub fn attribute_producer(
+    samples: &[SpacingSample],
+    fingerprints: &[ProducerFingerprint],
+) -> Vec<AttributionScore> {
+    let (scale, spread) = scale_and_spread(samples);
+    let widths: Vec<f32> = samples.iter().map(|s| s.observed_width).collect();
+
+    let mut scores: Vec<AttributionScore> = fingerprints
+        .iter()
+        .map(|fp| {
+            let grid_term = match fp.quantum_px {
+                Some(q) => 1.0 - grid_coherence(&widths, q),
+                // continuous layout: penalize any grid the samples lock onto
+                None => [TWIP_PX, HUNDREDTH_MM_PX]
+                    .iter()
+                    .map(|&q| grid_coherence(&widths, q))
+                    .fold(0.0, f32::max),
+            };
+            let scale_term = (scale - fp.scale).abs() / 0.01;
+            let spread_term = (spread - fp.residual_sd).abs() / fp.residual_sd.max(0.01);
+
+            AttributionScore {
+                producer: fp.name.clone(),
+                distance: 4.0 * grid_term + scale_term + spread_term,
+                probability: 0.0,
+            }
+        })
+        .collect();
+
+    let norm: f32 = scores.iter().map(|s| (-s.distance).exp()).sum();
+    for s in &mut scores {
+        s.probability = if norm > 0.0 {
+            (-s.distance).exp() / norm
+        } else {
+            0.0
+        };
+    }
+
+    scores.sort_by(|a, b| {
+        a.distance
+            .partial_cmp(&b.distance)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    scores
+}
+
+/// Likely origin of a document: the producers ranked by
+/// `attribute_producer` over its visible runs.
+#[derive(Clone, Debug, Serialize)]
+pub struct AttributionReport {
+    pub runs: usize,
+    /// Characters over all runs; a few short runs cannot pin the grid.
+    pub chars: usize,
+    pub ranking: Vec<AttributionScore>,
+}
+
+impl AttributionReport {
+    pub fn new(samples: &[SpacingSample], fingerprints: &[ProducerFingerprint]) -> Self {
+        AttributionReport {
+            runs: samples.len(),
+            chars: samples.iter().map(|s| s.text.chars().count()).sum(),
+            ranking: attribute_producer(samples, fingerprints),
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = format!(
+            "{:<20} {:>10} {:>12}\n{:-<44}\n",
+            "Producer", "Distance", "Probability", ""
+        );
+        for s in &self.ranking {
+            out.push_str(&format!(
+                "{:<20} {:>10.3} {:>11.1}%\n",
+                s.producer,
+                s.distance,
+                s.probability * 100.0
+            ));
+        }
+        out.push_str(&format!(
+            "{:-<44}\nFrom {} visible runs, {} characters\n",
+            "", self.runs, self.chars
+        ));
+        out
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("producer,distance,probability\n");
+        for s in &self.ranking {
+            out.push_str(&format!(
+                "{},{:.4},{:.4}\n",
+                csv_field(&s.producer),
+                s.distance,
+                s.probability
+            ));
+        }
+        out
+    }
+
+    pub fn render(&self, format: OutputFormat) -> io::Result<String> {
+        match format {
+            OutputFormat::Text => Ok(self.to_text()),
+            OutputFormat::Json => serde_json::to_string_pretty(self).map_err(io::Error::other),
+            OutputFormat::Csv => Ok(self.to_csv()),
+        }
+    }
+
+    /// Writes to `path`, or to stdout when no path is given.
+    pub fn write(&self, format: OutputFormat, path: Option<&str>) -> io::Result<()> {
+        let rendered = self.render(format)?;
+        match path {
+            Some(p) => fs::write(p, rendered),
+            None => {
+                print!("{}", rendered);
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+// ============================================
+// AUDIT LOG OF AUTOMATIC DECISIONS
+// ============================================
+
+use crate::ensemble::Strategy;
+use serde::Serialize;
+use std::fs;
+use std::io;
+
+/// Something the pipeline decided on its own while restoring a line, kept
+/// so a reviewer can tell how a result came about.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// The best beam's confidence was below the restart threshold, so the
+    /// line was searched again with `added` in the alphabet.
+    AlphabetEscalation {
+        stage: usize,
+        added: String,
+        confidence_before: f32,
+        confidence_after: f32,
+    },
+    /// Several search strategies ran on the line and `strategy` ranked the
+    /// fused winner highest; `agreeing` also proposed it.
+    EnsembleWinner {
+        text: String,
+        strategy: Strategy,
+        agreeing: Vec<Strategy>,
+    },
+    /// Rescoring against the neighbouring lines' text changed the line's
+    /// best candidate from `from` to `to`.
+    ContextPromotion { from: String, to: String },
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AuditEntry {
+    /// Line index in the document after the hooks ran, 1-based.
+    pub line: usize,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+}
+
+/// Entries in the order they happened.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct AuditLog {
+    pub entries: Vec<AuditEntry>,
+}
+
+impl AuditLog {
+    pub fn record(&mut self, line: usize, event: AuditEvent) {
+        self.entries.push(AuditEntry { line, event });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// One JSON object per line.
+    pub fn to_json_lines(&self) -> io::Result<String> {
+        let mut out = String::new();
+        for entry in &self.entries {
+            out.push_str(&serde_json::to_string(entry).map_err(io::Error::other)?);
+            out.push('\n');
+        }
+        Ok(out)
+    }
+
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        fs::write(path, self.to_json_lines()?)
+    }
+}
@@ -0,0 +1,326 @@
+// ============================================
+// WIDTH CALIBRATION AND NOISE MODEL
+// ============================================
+
+use crate::{find_candidates_par, glyph_sum, Document};
+use std::collections::HashMap;
+
+/// Bonus given to a beam that agrees with an anchor of exactly the same
+/// width, same as `anchor_bonus`.
+const ANCHOR_BONUS: f32 = 5.0;
+
+/// Floor on the residual noise, so a couple of anchors that happen to fit
+/// perfectly do not collapse every tolerance to zero.
+const MIN_NOISE_PX: f32 = 0.05;
+
+/// Widths are treated as matching within this many standard deviations.
+pub const TOLERANCE_SIGMAS: f32 = 3.0;
+
+/// Renders a character must appear in before it gets its own variance;
+/// rarer ones use the pooled value.
+const MIN_CHAR_RENDERS: usize = 3;
+
+/// Gauss-Seidel sweeps of the non-negative variance fit.
+const VARIANCE_SWEEPS: usize = 200;
+
+/// Width variance each character adds to a line measured off pixels, in
+/// px² of the glyph table. Anti-aliasing blurs round letters (o, e, c) over
+/// more partial pixels than stems (i, l), so their edges, and so the line's
+/// extent, wander more from render to render.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CharVariance {
+    pub variances: HashMap<char, f32>,
+    /// Per-character variance over all renders, for characters not in
+    /// `variances`.
+    pub pooled: f32,
+    /// Mean advance of the characters rendered, to guess a line's length
+    /// from its width.
+    pub mean_advance: f32,
+    pub samples: usize,
+}
+
+impl CharVariance {
+    /// Fits the table from calibration renders (`(text, observed_width)`,
+    /// the same text rendered and measured several times): the squared
+    /// residual of each render against `calibration` is modelled as the sum
+    /// of its characters' variances and solved for non-negative variances.
+    pub fn fit(
+        renders: &[(&str, f32)],
+        glyphs: &HashMap<char, f32>,
+        calibration: &Calibration,
+    ) -> Self {
+        let mut counts: Vec<HashMap<char, f32>> = vec![];
+        let mut squared = vec![];
+        let mut seen: HashMap<char, usize> = HashMap::new();
+        let (mut chars, mut advance) = (0.0, 0.0);
+        for &(text, observed) in renders {
+            let measured = glyph_sum(text, glyphs);
+            if measured <= 0.0 {
+                continue;
+            }
+            let mut n: HashMap<char, f32> = HashMap::new();
+            for ch in text.chars().filter(|c| glyphs.contains_key(c)) {
+                *n.entry(ch).or_default() += 1.0;
+            }
+            for &ch in n.keys() {
+                *seen.entry(ch).or_default() += 1;
+            }
+            chars += n.values().sum::<f32>();
+            advance += measured;
+            squared.push(
+                (observed - calibration.scale * measured).powi(2) / calibration.scale.powi(2),
+            );
+            counts.push(n);
+        }
+        if counts.is_empty() {
+            return CharVariance::default();
+        }
+
+        // normal equations of squared ~ Σ n_c v_c, solved coordinate-wise
+        // with v_c >= 0
+        let mut keys: Vec<char> = seen
+            .iter()
+            .filter(|(_, &k)| k >= MIN_CHAR_RENDERS)
+            .map(|(&c, _)| c)
+            .collect();
+        keys.sort();
+        let pooled = squared.iter().sum::<f32>() / chars;
+        let mut v: HashMap<char, f32> = keys.iter().map(|&c| (c, pooled)).collect();
+        for _ in 0..VARIANCE_SWEEPS {
+            for &c in &keys {
+                let (mut num, mut den) = (0.0, 0.0);
+                for (n, r2) in counts.iter().zip(&squared) {
+                    let Some(&nc) = n.get(&c) else { continue };
+                    let others: f32 = n
+                        .iter()
+                        .filter(|(&d, _)| d != c)
+                        .map(|(d, nd)| nd * v.get(d).copied().unwrap_or(pooled))
+                        .sum();
+                    num += nc * (r2 - others);
+                    den += nc * nc;
+                }
+                if den > 0.0 {
+                    v.insert(c, (num / den).max(0.0));
+                }
+            }
+        }
+
+        CharVariance {
+            variances: v,
+            pooled,
+            mean_advance: advance / chars,
+            samples: counts.len(),
+        }
+    }
+
+    pub fn variance(&self, ch: char) -> f32 {
+        self.variances.get(&ch).copied().unwrap_or(self.pooled)
+    }
+
+    /// Width variance of `text`, characters independent.
+    pub fn text_variance(&self, text: &str) -> f32 {
+        text.chars().map(|c| self.variance(c)).sum()
+    }
+
+    /// The largest variance a line `measured` px wide can be expected to
+    /// have: as many characters as fit at the mean advance, all of the
+    /// noisiest kind.
+    fn worst_variance(&self, measured: f32) -> f32 {
+        let noisiest = self.variances.values().copied().fold(self.pooled, f32::max);
+        let chars = if self.mean_advance > 0.0 {
+            measured / self.mean_advance
+        } else {
+            0.0
+        };
+        chars.ceil() * noisiest
+    }
+}
+
+/// Relation between widths measured with the glyph table and widths found in
+/// the document: `observed = scale * measured + noise`, with noise of
+/// standard deviation `noise_sd` plus the uncertainty of `scale` itself,
+/// which grows with the width of the line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Calibration {
+    pub scale: f32,
+    pub scale_sd: f32,
+    pub noise_sd: f32,
+    pub samples: usize,
+    /// For widths measured off pixels: noise that depends on the characters
+    /// rather than one `noise_sd` for every line.
+    pub char_variance: Option<CharVariance>,
+}
+
+impl Default for Calibration {
+    /// Uncalibrated: same renderer as the glyph table, 0.1 px rounding.
+    fn default() -> Self {
+        Calibration {
+            scale: 1.0,
+            scale_sd: 0.0,
+            noise_sd: 0.1,
+            samples: 0,
+            char_variance: None,
+        }
+    }
+}
+
+impl Calibration {
+    /// Fits the scale through the origin from lines whose text is known
+    /// (`(text, observed_width)`) and takes the residual spread as noise.
+    /// With fewer than two usable anchors the noise keeps its default.
+    pub fn estimate(known: &[(&str, f32)], glyphs: &HashMap<char, f32>) -> Self {
+        let pairs: Vec<(f32, f32)> = known
+            .iter()
+            .map(|&(text, observed)| (glyph_sum(text, glyphs), observed))
+            .filter(|&(measured, _)| measured > 0.0)
+            .collect();
+
+        let smm: f32 = pairs.iter().map(|p| p.0 * p.0).sum();
+        if pairs.is_empty() || smm <= 0.0 {
+            return Calibration::default();
+        }
+
+        let scale = pairs.iter().map(|p| p.0 * p.1).sum::<f32>() / smm;
+        let n = pairs.len();
+
+        if n < 2 {
+            return Calibration {
+                scale,
+                samples: n,
+                ..Calibration::default()
+            };
+        }
+
+        let ss: f32 = pairs.iter().map(|&(m, o)| (o - scale * m).powi(2)).sum();
+        let noise_sd = (ss / (n - 1) as f32).sqrt().max(MIN_NOISE_PX);
+
+        Calibration {
+            scale,
+            scale_sd: noise_sd / smm.sqrt(),
+            noise_sd,
+            samples: n,
+            char_variance: None,
+        }
+    }
+
+    /// Uses `table` for the noise of each candidate instead of `noise_sd`.
+    pub fn with_char_variance(mut self, table: CharVariance) -> Self {
+        self.char_variance = Some(table);
+        self
+    }
+
+    /// Observed width expressed in glyph-table units.
+    pub fn normalize_width(&self, observed: f32) -> f32 {
+        if self.scale > 0.0 {
+            observed / self.scale
+        } else {
+            observed
+        }
+    }
+
+    /// Standard deviation of an observed width of this size, in observed px.
+    /// With a character variance table, the widest spread a line of this
+    /// size can have, since its text is not known yet.
+    pub fn line_sigma(&self, observed: f32) -> f32 {
+        let measured = self.normalize_width(observed);
+        let noise = match &self.char_variance {
+            Some(table) => {
+                MIN_NOISE_PX.powi(2) + table.worst_variance(measured) * self.scale.powi(2)
+            }
+            None => self.noise_sd.powi(2),
+        };
+        (noise + (self.scale_sd * measured).powi(2)).sqrt()
+    }
+
+    /// Standard deviation of the observed width of a line holding `text`,
+    /// in observed px. Same as `line_sigma` without a character variance
+    /// table.
+    pub fn text_sigma(&self, text: &str, observed: f32) -> f32 {
+        let Some(table) = &self.char_variance else {
+            return self.line_sigma(observed);
+        };
+        let measured = self.normalize_width(observed);
+        (MIN_NOISE_PX.powi(2)
+            + table.text_variance(text) * self.scale.powi(2)
+            + (self.scale_sd * measured).powi(2))
+        .sqrt()
+    }
+
+    /// Tolerance for the line in glyph-table units, ready for
+    /// `find_candidates` or `restore_width`.
+    pub fn tolerance(&self, observed: f32) -> f32 {
+        self.normalize_width(TOLERANCE_SIGMAS * self.line_sigma(observed))
+    }
+
+    /// Gaussian log-likelihood (up to a constant) of a candidate measured at
+    /// `measured` px in the glyph table being the line observed at
+    /// `observed` px. Replaces the hard cutoff when ranking candidates.
+    /// The spread is `text`'s own (`text_sigma`).
+    pub fn width_log_likelihood(&self, text: &str, measured: f32, observed: f32) -> f32 {
+        let sigma = self.text_sigma(text, observed);
+        let z = (self.scale * measured - observed) / sigma;
+        -0.5 * z * z - sigma.ln()
+    }
+}
+
+/// Dictionary search with the line's own tolerance, ranked by width
+/// likelihood (best first) instead of raw distance.
+pub fn find_candidates_calibrated(
+    observed_width: f32,
+    glyphs: &HashMap<char, f32>,
+    dictionary: &[&str],
+    calibration: &Calibration,
+) -> Vec<(String, f32)> {
+    let target = calibration.normalize_width(observed_width);
+    let tolerance = calibration.tolerance(observed_width);
+
+    let mut out: Vec<(String, f32)> = find_candidates_par(target, glyphs, dictionary, tolerance)
+        .into_iter()
+        .map(|(word, _)| {
+            let ll =
+                calibration.width_log_likelihood(&word, glyph_sum(&word, glyphs), observed_width);
+            (word, ll)
+        })
+        .collect();
+
+    out.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    out
+}
+
+/// `stabilize_document` without fixed 0.1 px quantization: every line's best
+/// beam votes for its text on all lines (itself included), weighted by how
+/// likely the two widths are to hold the same text under the noise model,
+/// so a text that is best on most lines of a width wins on the others too.
+/// Widths are in observed px. Returns the anchors that were used.
+pub fn stabilize_document_calibrated(
+    doc: &mut Document,
+    calibration: &Calibration,
+) -> Vec<(f32, String)> {
+    let anchors: Vec<(f32, String)> = doc
+        .lines
+        .iter()
+        .filter_map(|l| l.beams.first().map(|b| (l.observed_width, b.text.clone())))
+        .collect();
+
+    for line in &mut doc.lines {
+        let sigma = calibration.line_sigma(line.observed_width);
+
+        for beam in &mut line.beams {
+            let bonus = anchors
+                .iter()
+                .filter(|(_, text)| *text == beam.text)
+                .map(|&(w, _)| {
+                    let pair_sigma = (sigma.powi(2) + calibration.line_sigma(w).powi(2)).sqrt();
+                    let z = (line.observed_width - w) / pair_sigma;
+                    ANCHOR_BONUS * (-0.5 * z * z).exp()
+                })
+                .sum::<f32>();
+            beam.score += bonus;
+        }
+
+        line.beams
+            .sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+    }
+
+    anchors
+}
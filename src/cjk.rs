@@ -0,0 +1,306 @@
+// ============================================
+// CJK AND OTHER LARGE ALPHABETS
+// ============================================
+
+use crate::{
+    combined_score, Beam, BeamHeap, NGramModel, ScoreWeights, SearchStats, BEAM_OVERSHOOT,
+};
+use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
+use ttf_parser::Face;
+
+/// How wide a character is set in East Asian typography, as far as
+/// advances go (after Unicode's East Asian Width property).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WidthClass {
+    /// Ideographs, kana, hangul, CJK punctuation and full-width forms: one
+    /// em in practically every font.
+    Full,
+    /// Half-width katakana and forms: half an em.
+    Half,
+    /// Everything else, proportional: only the font knows.
+    Proportional,
+}
+
+impl WidthClass {
+    pub fn of(ch: char) -> Self {
+        match ch as u32 {
+            0x1100..=0x115F
+            | 0x2E80..=0x303E
+            | 0x3041..=0x33FF
+            | 0x3400..=0x4DBF
+            | 0x4E00..=0x9FFF
+            | 0xA000..=0xA4CF
+            | 0xAC00..=0xD7A3
+            | 0xF900..=0xFAFF
+            | 0xFE30..=0xFE4F
+            | 0xFF00..=0xFF60
+            | 0xFFE0..=0xFFE6 => WidthClass::Full,
+            0xFF61..=0xFFDC | 0xFFE8..=0xFFEE => WidthClass::Half,
+            _ => WidthClass::Proportional,
+        }
+    }
+}
+
+/// Advance of a full- or half-width character in a font that lacks it:
+/// one em or half an em at `px_size`. `None` for proportional characters.
+pub fn conventional_advance(ch: char, px_size: f32) -> Option<f32> {
+    match WidthClass::of(ch) {
+        WidthClass::Full => Some(px_size),
+        WidthClass::Half => Some(px_size / 2.0),
+        WidthClass::Proportional => None,
+    }
+}
+
+/// `text` with printable ASCII set in full-width forms (U+FF01..U+FF5E) and
+/// spaces as ideographic spaces, as Latin words and numbers often are in
+/// Japanese and Chinese documents.
+pub fn to_fullwidth(text: &str) -> String {
+    text.chars()
+        .map(|ch| match ch {
+            ' ' => '\u{3000}',
+            '!'..='~' => char::from_u32(ch as u32 - 0x21 + 0xFF01).unwrap_or(ch),
+            _ => ch,
+        })
+        .collect()
+}
+
+/// Reverse of `to_fullwidth`: full-width forms and ideographic spaces back
+/// to ASCII.
+pub fn to_halfwidth(text: &str) -> String {
+    text.chars()
+        .map(|ch| match ch {
+            '\u{3000}' => ' ',
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(ch as u32 - 0xFF01 + 0x21).unwrap_or(ch),
+            _ => ch,
+        })
+        .collect()
+}
+
+/// Alphabets larger than this are too big to extend every beam with every
+/// character (thousands of ideographs per step) and should go through a
+/// `PrunedAlphabet`.
+pub const LARGE_ALPHABET: usize = 256;
+
+/// Characters tried after every prefix, by default.
+pub const DEFAULT_FREQUENT: usize = 64;
+
+/// Characters the model most often saw after a prefix's context that are
+/// tried in addition, by default.
+pub const DEFAULT_FOLLOWERS: usize = 24;
+
+/// A large alphabet cut down to what a prefix can plausibly continue with:
+/// the corpus's most frequent characters, plus the characters that most
+/// often followed the prefix's last `n - 1` characters in the model's
+/// training text. A step costs beams × (`frequent` + followers) scorings
+/// instead of beams × alphabet.
+#[derive(Clone, Debug, Default)]
+pub struct PrunedAlphabet {
+    /// Most frequent first.
+    pub frequent: Vec<char>,
+    /// Context -> its most frequent next characters not in `frequent`,
+    /// most frequent first.
+    followers: HashMap<String, Vec<char>>,
+    context_len: usize,
+    /// Distinct characters seen in the corpus.
+    pub corpus_chars: usize,
+}
+
+impl PrunedAlphabet {
+    /// From the gram counts of `model`: the `frequent` most common
+    /// characters (each gram counts toward its last character, so every
+    /// corpus position is counted once) and, per context, up to `followers`
+    /// next characters.
+    pub fn from_model(model: &NGramModel, frequent: usize, followers: usize) -> Self {
+        let mut totals: HashMap<char, usize> = HashMap::new();
+        let mut by_context: HashMap<String, Vec<(char, usize)>> = HashMap::new();
+        for (gram, &count) in &model.counts {
+            let mut chars = gram.chars();
+            let Some(last) = chars.next_back() else {
+                continue;
+            };
+            *totals.entry(last).or_default() += count;
+            by_context
+                .entry(chars.collect())
+                .or_default()
+                .push((last, count));
+        }
+
+        let mut ranked: Vec<(char, usize)> = totals.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        let corpus_chars = ranked.len();
+        let frequent: Vec<char> = ranked
+            .into_iter()
+            .take(frequent)
+            .map(|(ch, _)| ch)
+            .collect();
+        let common: HashSet<char> = frequent.iter().copied().collect();
+
+        let followers = by_context
+            .into_iter()
+            .map(|(context, mut next)| {
+                next.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+                let next = next
+                    .into_iter()
+                    .map(|(ch, _)| ch)
+                    .filter(|ch| !common.contains(ch))
+                    .take(followers);
+                (context, next.collect())
+            })
+            .collect();
+
+        PrunedAlphabet {
+            frequent,
+            followers,
+            context_len: model.n.saturating_sub(1),
+            corpus_chars,
+        }
+    }
+
+    /// Characters that may follow `prefix`. Prefixes shorter than the
+    /// model's context only get the frequent ones.
+    pub fn candidates<'s>(&'s self, prefix: &str) -> impl Iterator<Item = char> + 's {
+        let start = prefix
+            .char_indices()
+            .rev()
+            .nth(self.context_len.saturating_sub(1))
+            .map(|(i, _)| i);
+        let context = match (self.context_len, start) {
+            (0, _) => Some(""),
+            (_, Some(i)) => Some(&prefix[i..]),
+            (_, None) => None,
+        };
+        let followers = context
+            .and_then(|c| self.followers.get(c))
+            .map_or(&[][..], Vec::as_slice);
+        self.frequent.iter().chain(followers).copied()
+    }
+
+    /// Every character some prefix can be extended with.
+    pub fn reachable(&self) -> HashSet<char> {
+        self.frequent
+            .iter()
+            .chain(self.followers.values().flatten())
+            .copied()
+            .collect()
+    }
+}
+
+/// Advance of `ch` as `measure_text_kerning` measures it.
+fn advance(face: &Face, glyphs: &HashMap<char, f32>, ch: char, px_size: f32) -> Option<f32> {
+    let scale = px_size / face.units_per_em() as f32;
+    match face.glyph_index(ch) {
+        Some(g) => face.glyph_hor_advance(g).map(|a| a as f32 * scale),
+        None => glyphs
+            .get(&ch)
+            .copied()
+            .or_else(|| conventional_advance(ch, px_size)),
+    }
+}
+
+/// `restore_width` for large alphabets: beam search where each beam is
+/// extended with `pruned.candidates` of its text plus `alphabet` (the
+/// small alphabet the line would otherwise use, e.g. Latin letters of a
+/// mixed line). Characters without a width are skipped. Runs `steps`
+/// steps, by default as many as the narrowest usable character fits.
+/// Beams that came within `tolerance` of the target at any step are
+/// returned, best first; the last step's beams if none did, since
+/// full-width and half-width characters reach the width at different
+/// depths.
+#[allow(clippy::too_many_arguments)]
+pub fn pruned_beam_search(
+    face: &Face,
+    glyphs: &HashMap<char, f32>,
+    px_size: f32,
+    target_width: f32,
+    tolerance: f32,
+    pruned: &PrunedAlphabet,
+    alphabet: &[char],
+    weights: &ScoreWeights,
+    model: Option<&NGramModel>,
+    beam_width: usize,
+    steps: Option<usize>,
+    stats: &SearchStats,
+) -> Vec<Beam> {
+    let advances: HashMap<char, f32> = pruned
+        .reachable()
+        .into_iter()
+        .chain(alphabet.iter().copied())
+        .filter_map(|ch| {
+            advance(face, glyphs, ch, px_size)
+                .filter(|&a| a > 0.0)
+                .map(|a| (ch, a))
+        })
+        .collect();
+    let narrowest = advances.values().copied().fold(f32::INFINITY, f32::min);
+    let steps = steps.unwrap_or(match narrowest.is_finite() {
+        true => ((target_width + tolerance) / narrowest).floor() as usize,
+        false => 0,
+    });
+
+    let mut beams = vec![Beam {
+        text: String::new(),
+        width: 0.0,
+        score: 0.0,
+    }];
+    let mut done = BeamHeap::new(beam_width);
+    for _ in 0..steps {
+        stats.add_expanded(beams.len());
+
+        let next = beams
+            .par_iter()
+            .fold(
+                || (BeamHeap::new(beam_width), String::new(), HashSet::new()),
+                |(mut heap, mut scratch, mut tried), beam| {
+                    tried.clear();
+                    let mut evaluated = 0;
+                    for ch in pruned
+                        .candidates(&beam.text)
+                        .chain(alphabet.iter().copied())
+                    {
+                        let Some(&adv) = advances.get(&ch) else {
+                            continue;
+                        };
+                        let width = beam.width + adv;
+                        if width > target_width + BEAM_OVERSHOOT || !tried.insert(ch) {
+                            continue;
+                        }
+
+                        scratch.clear();
+                        scratch.push_str(&beam.text);
+                        scratch.push(ch);
+                        let score = combined_score(&scratch, width, target_width, weights, model);
+                        evaluated += 1;
+                        if heap.accepts(score) {
+                            heap.push(Beam {
+                                text: scratch.clone(),
+                                width,
+                                score,
+                            });
+                        }
+                    }
+                    stats.add_evaluated(evaluated);
+                    (heap, scratch, tried)
+                },
+            )
+            .map(|(heap, _, _)| heap)
+            .reduce(|| BeamHeap::new(beam_width), BeamHeap::merge);
+
+        // every extension overshoots: keep the beams we already have
+        if next.is_empty() {
+            break;
+        }
+        beams = next.into_sorted_vec();
+        for b in beams
+            .iter()
+            .filter(|b| (b.width - target_width).abs() <= tolerance)
+        {
+            done.push(b.clone());
+        }
+    }
+
+    match done.is_empty() {
+        true => beams,
+        false => done.into_sorted_vec(),
+    }
+}
@@ -0,0 +1,115 @@
+// ============================================
+// TWO-SIDED CONTEXT FROM NEIGHBOURING LINES
+// ============================================
+
+use crate::{ngram_log_prob, Beam, Document, NGramModel};
+
+/// Forward-backward rounds at most; the passes stop earlier once a round
+/// leaves every line's best text unchanged.
+const MAX_CONTEXT_ROUNDS: usize = 4;
+
+/// A line whose best text changed because of its neighbours.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContextChange {
+    /// 0-based.
+    pub line: usize,
+    pub from: String,
+    pub to: String,
+}
+
+/// Log-probability of the grams that cross from `left` into `text` and from
+/// `text` into `right`, lines joined by a space as `train_from_reader`
+/// joins them. Only the last (first) `n - 1` characters of a neighbour can
+/// reach across, so only those are read.
+pub fn boundary_log_prob(
+    left: Option<&str>,
+    text: &str,
+    right: Option<&str>,
+    model: &NGramModel,
+) -> f32 {
+    let k = model.n.saturating_sub(1);
+    if k == 0 || text.is_empty() {
+        return 0.0;
+    }
+    let left: String = left.map_or(String::new(), |l| {
+        let tail: Vec<char> = l.chars().rev().take(k).collect();
+        tail.into_iter().rev().chain(std::iter::once(' ')).collect()
+    });
+    let right: String = right.map_or(String::new(), |r| {
+        std::iter::once(' ').chain(r.chars().take(k)).collect()
+    });
+    let joined = format!("{}{}{}", left, text, right);
+
+    // what is left after taking out the grams inside each piece
+    ngram_log_prob(&joined, model)
+        - ngram_log_prob(&left, model)
+        - ngram_log_prob(text, model)
+        - ngram_log_prob(&right, model)
+}
+
+/// Rescores every line's beams with `weight * boundary_log_prob` against
+/// the best text of the previous and next line, sweeping forward then
+/// backward so that a well-determined line helps the lines on both sides
+/// of it and the improvements carry on from there. Scores are recomputed
+/// from the ones the beams came in with each time, never accumulated.
+/// Returns the lines whose best text ended up different.
+pub fn rescore_with_neighbors(
+    doc: &mut Document,
+    model: &NGramModel,
+    weight: f32,
+) -> Vec<ContextChange> {
+    let base: Vec<Vec<Beam>> = doc.lines.iter().map(|l| l.beams.clone()).collect();
+    let best = |doc: &Document, i: usize| {
+        doc.lines
+            .get(i)
+            .and_then(|l| l.beams.first())
+            .map(|b| b.text.clone())
+    };
+    let before: Vec<Option<String>> = (0..doc.lines.len()).map(|i| best(doc, i)).collect();
+
+    let rescore = |doc: &mut Document, i: usize| {
+        let left = i.checked_sub(1).and_then(|j| best(doc, j));
+        let right = best(doc, i + 1);
+        if left.is_none() && right.is_none() {
+            return false;
+        }
+        let previous = best(doc, i);
+        let line = &mut doc.lines[i];
+        line.beams = base[i]
+            .iter()
+            .map(|b| Beam {
+                score: b.score
+                    + weight * boundary_log_prob(left.as_deref(), &b.text, right.as_deref(), model),
+                ..b.clone()
+            })
+            .collect();
+        line.beams.sort_by(|a, b| b.score.total_cmp(&a.score));
+        line.beams.first().map(|b| &b.text) != previous.as_ref()
+    };
+
+    for _ in 0..MAX_CONTEXT_ROUNDS {
+        let mut changed = false;
+        for i in 0..doc.lines.len() {
+            changed |= rescore(doc, i);
+        }
+        for i in (0..doc.lines.len()).rev() {
+            changed |= rescore(doc, i);
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    before
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, from)| {
+            let to = best(doc, i)?;
+            (from.as_ref() != Some(&to)).then(|| ContextChange {
+                line: i,
+                from: from.unwrap_or_default(),
+                to,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,256 @@
+// ============================================
+// CROSS-FORMAT WIDTH CONSISTENCY
+// ============================================
+
+use crate::Document;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+
+/// A line as seen in one export of the document. `text` is the visible
+/// text, empty when the line is redacted in that export.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ExportLine {
+    #[serde(default)]
+    pub text: String,
+    pub width: f32,
+}
+
+/// Reads the lines of one export, a JSON array of
+/// `{"text": "...", "width": N}` in document order; redacted lines leave
+/// out `text`.
+pub fn load_export_lines(path: &str) -> io::Result<Vec<ExportLine>> {
+    serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path, e)))
+}
+
+/// Systematic width transform of an export pipeline:
+/// `target = scale * source + offset`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PipelineBias {
+    pub scale: f32,
+    pub offset: f32,
+}
+
+impl Default for PipelineBias {
+    fn default() -> Self {
+        PipelineBias {
+            scale: 1.0,
+            offset: 0.0,
+        }
+    }
+}
+
+impl PipelineBias {
+    /// Source-format width expressed in the target format.
+    pub fn apply(&self, width: f32) -> f32 {
+        self.scale * width + self.offset
+    }
+
+    /// Target-format width expressed in the source format.
+    pub fn invert(&self, width: f32) -> f32 {
+        if self.scale.abs() < 1e-6 {
+            width
+        } else {
+            (width - self.offset) / self.scale
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LinePair {
+    pub source: usize,
+    pub target: usize,
+    pub residual: f32, // target width minus bias-corrected source width
+}
+
+#[derive(Clone, Debug)]
+pub struct ConsistencyReport {
+    pub bias: PipelineBias,
+    pub pairs: Vec<LinePair>,
+    pub mean_abs_residual: f32,
+    /// Pairs whose residual exceeds three robust standard deviations (or
+    /// `MIN_OUTLIER_PX`), usually lines that reflowed or were edited between
+    /// the two exports.
+    pub inconsistent: Vec<LinePair>,
+}
+
+const GAP_COST: f32 = 1.0;
+const MIN_OUTLIER_PX: f32 = 0.25;
+const TEXT_MISMATCH_COST: f32 = 10.0;
+
+fn pair_cost(a: &ExportLine, b: &ExportLine) -> f32 {
+    if !a.text.is_empty() && !b.text.is_empty() {
+        if a.text == b.text {
+            0.0
+        } else {
+            TEXT_MISMATCH_COST
+        }
+    } else {
+        // redacted on at least one side: only the widths can be compared
+        (a.width - b.width).abs() / a.width.max(b.width).max(1.0)
+    }
+}
+
+/// Order-preserving alignment of the two line lists (Needleman–Wunsch).
+/// Lines with identical visible text pair for free; lines redacted on
+/// either side pair by relative width difference.
+pub fn align_lines(source: &[ExportLine], target: &[ExportLine]) -> Vec<(usize, usize)> {
+    let (n, m) = (source.len(), target.len());
+    let mut cost = vec![vec![0.0f32; m + 1]; n + 1];
+
+    for (i, row) in cost.iter_mut().enumerate() {
+        row[0] = i as f32 * GAP_COST;
+    }
+    for (j, c) in cost[0].iter_mut().enumerate() {
+        *c = j as f32 * GAP_COST;
+    }
+    for i in 1..=n {
+        for j in 1..=m {
+            let pair = cost[i - 1][j - 1] + pair_cost(&source[i - 1], &target[j - 1]);
+            let skip = (cost[i - 1][j] + GAP_COST).min(cost[i][j - 1] + GAP_COST);
+            cost[i][j] = pair.min(skip);
+        }
+    }
+
+    let mut pairs = vec![];
+    let (mut i, mut j) = (n, m);
+    while i > 0 && j > 0 {
+        let pair = cost[i - 1][j - 1] + pair_cost(&source[i - 1], &target[j - 1]);
+        if (cost[i][j] - pair).abs() < 1e-4 {
+            pairs.push((i - 1, j - 1));
+            i -= 1;
+            j -= 1;
+        } else if (cost[i][j] - (cost[i - 1][j] + GAP_COST)).abs() < 1e-4 {
+            i -= 1;
+        } else {
+            j -= 1;
+        }
+    }
+
+    pairs.reverse();
+    pairs
+}
+
+/// Least-squares fit of `target = scale * source + offset` over the given
+/// width pairs. Falls back to a pure ratio for a single pair.
+pub fn fit_bias(pairs: &[(f32, f32)]) -> PipelineBias {
+    let n = pairs.len() as f32;
+    if pairs.is_empty() {
+        return PipelineBias::default();
+    }
+
+    let mean_x = pairs.iter().map(|p| p.0).sum::<f32>() / n;
+    let mean_y = pairs.iter().map(|p| p.1).sum::<f32>() / n;
+    let sxx: f32 = pairs.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    let sxy: f32 = pairs.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+
+    if sxx < 1e-6 {
+        return PipelineBias {
+            scale: if mean_x > 0.0 { mean_y / mean_x } else { 1.0 },
+            offset: 0.0,
+        };
+    }
+
+    let scale = sxy / sxx;
+    PipelineBias {
+        scale,
+        offset: mean_y - scale * mean_x,
+    }
+}
+
+/// Three robust standard deviations (median absolute deviation based), so
+/// a few reflowed lines cannot hide themselves by inflating the spread.
+fn outlier_threshold(mut abs_residuals: Vec<f32>) -> f32 {
+    abs_residuals.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let mad = abs_residuals
+        .get(abs_residuals.len() / 2)
+        .copied()
+        .unwrap_or(0.0)
+        * 1.4826;
+    (3.0 * mad).max(MIN_OUTLIER_PX)
+}
+
+/// Drops, one at a time, the anchor furthest from the fit of the others
+/// while it is an outlier to them. With few anchors an edited line bends a
+/// fit over all of them enough to hide inside the spread it caused.
+fn trim_anchors(mut anchors: Vec<(f32, f32)>) -> Vec<(f32, f32)> {
+    while anchors.len() > 2 {
+        let worst = (0..anchors.len())
+            .filter_map(|i| {
+                let mut rest = anchors.clone();
+                let (x, y) = rest.remove(i);
+                let bias = fit_bias(&rest);
+                let residual = (y - bias.apply(x)).abs();
+                let threshold = outlier_threshold(
+                    rest.iter()
+                        .map(|&(x, y)| (y - bias.apply(x)).abs())
+                        .collect(),
+                );
+                (residual > threshold).then_some((i, residual))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        match worst {
+            Some((i, _)) => {
+                anchors.remove(i);
+            }
+            None => break,
+        }
+    }
+    anchors
+}
+
+/// Maps lines between two exports of the same document (e.g. the DOCX and
+/// the PDF made from it), estimates the export bias from lines that are
+/// visible in both, and reports lines that do not follow it.
+pub fn check_consistency(source: &[ExportLine], target: &[ExportLine]) -> ConsistencyReport {
+    let aligned = align_lines(source, target);
+
+    let anchors: Vec<(f32, f32)> = aligned
+        .iter()
+        .filter(|&&(i, j)| !source[i].text.is_empty() && source[i].text == target[j].text)
+        .map(|&(i, j)| (source[i].width, target[j].width))
+        .collect();
+
+    // fit without anchors the others cannot explain (edited lines)
+    let bias = fit_bias(&trim_anchors(anchors));
+
+    let pairs: Vec<LinePair> = aligned
+        .iter()
+        .map(|&(i, j)| LinePair {
+            source: i,
+            target: j,
+            residual: target[j].width - bias.apply(source[i].width),
+        })
+        .collect();
+
+    let n = pairs.len().max(1) as f32;
+    let mean_abs_residual = pairs.iter().map(|p| p.residual.abs()).sum::<f32>() / n;
+
+    let threshold = outlier_threshold(pairs.iter().map(|p| p.residual.abs()).collect());
+    let inconsistent = pairs
+        .iter()
+        .filter(|p| p.residual.abs() > threshold)
+        .cloned()
+        .collect();
+
+    ConsistencyReport {
+        bias,
+        pairs,
+        mean_abs_residual,
+        inconsistent,
+    }
+}
+
+/// Rewrites observed widths measured in the target format into the source
+/// format's scale (or the other way round with `to_target`), so a restore
+/// against fonts measured for one pipeline can use lines from the other.
+pub fn correct_document_widths(doc: &mut Document, bias: &PipelineBias, to_target: bool) {
+    for line in &mut doc.lines {
+        line.observed_width = if to_target {
+            bias.apply(line.observed_width)
+        } else {
+            bias.invert(line.observed_width)
+        };
+    }
+}
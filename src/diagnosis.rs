@@ -0,0 +1,178 @@
+// ============================================
+// FAILURE DIAGNOSIS FOR UNRESOLVED LINES
+// ============================================
+
+use crate::output::softmax_confidence;
+use crate::{glyph_sum, Line};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+/// A line counts as ambiguous when its best beam gets less than this share
+/// of the confidence.
+const AMBIGUOUS_CONFIDENCE: f32 = 0.5;
+
+/// Beams scoring within this much of the best one are counted as ties.
+const TIE_MARGIN: f32 = 0.5;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FailureMode {
+    /// Characters used by the dictionary or the hints have no entry in the
+    /// glyph table, so they measure 0 px and their words never fit.
+    MissingGlyphs { chars: String },
+    /// Nothing the search produced comes within tolerance of the width:
+    /// the font, size or spacing model does not describe the document.
+    /// `residual` is the best beam's width error, `None` without beams.
+    MeasurementMismatch { residual: Option<f32> },
+    /// The width is reachable but no dictionary entry fits it, so the best
+    /// beams are free-form strings.
+    NoDictionaryCoverage,
+    /// Several candidates fit equally well.
+    WidthAmbiguity { ties: usize },
+    /// Text recovered verbatim does not fit the observed width, so it likely
+    /// comes from another revision of the document.
+    ExactMismatch { residual: f32 },
+}
+
+impl FailureMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FailureMode::MissingGlyphs { .. } => "missing glyphs",
+            FailureMode::MeasurementMismatch { .. } => "measurement mismatch",
+            FailureMode::NoDictionaryCoverage => "no dictionary coverage",
+            FailureMode::WidthAmbiguity { .. } => "width ambiguity",
+            FailureMode::ExactMismatch { .. } => "exact text mismatch",
+        }
+    }
+
+    pub fn remediation(&self) -> String {
+        match self {
+            FailureMode::MissingGlyphs { chars } => format!(
+                "use a font that covers {:?} or remove those characters from the wordlist",
+                chars
+            ),
+            FailureMode::MeasurementMismatch { residual } => format!(
+                "{}: check font and px size, or calibrate with known lines",
+                residual.map_or("no candidate reaches the width".to_string(), |r| {
+                    format!("closest candidate is {:+.2} px off", r)
+                })
+            ),
+            FailureMode::NoDictionaryCoverage => {
+                "extend the wordlist or enable punctuation variants".to_string()
+            }
+            FailureMode::WidthAmbiguity { ties } => format!(
+                "{} candidates tie: add char/word count hints, word gaps or a language model",
+                ties
+            ),
+            FailureMode::ExactMismatch { residual } => format!(
+                "recovered text is {:+.2} px off the layout: check which revision it comes from, \
+                 or drop it and restore by width",
+                residual
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LineDiagnosis {
+    #[serde(flatten)]
+    pub mode: FailureMode,
+    pub remediation: String,
+}
+
+impl From<FailureMode> for LineDiagnosis {
+    fn from(mode: FailureMode) -> Self {
+        LineDiagnosis {
+            remediation: mode.remediation(),
+            mode,
+        }
+    }
+}
+
+/// Characters of `texts` that the glyph table cannot measure, spaces aside.
+fn missing_chars<'t>(
+    texts: impl IntoIterator<Item = &'t str>,
+    glyphs: &HashMap<char, f32>,
+) -> String {
+    texts
+        .into_iter()
+        .flat_map(str::chars)
+        .filter(|c| *c != ' ' && !glyphs.contains_key(c))
+        .collect::<BTreeSet<char>>()
+        .into_iter()
+        .collect()
+}
+
+/// Whether every word of a candidate, punctuation stripped, is a
+/// dictionary entry; phrases and punctuated variants count as covered.
+fn in_dictionary(text: &str, dictionary: &[&str]) -> bool {
+    text.split(' ')
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()))
+        .all(|w| w.is_empty() || dictionary.contains(&w))
+}
+
+/// Why a restored line did not resolve, or `None` when its best beam fits
+/// within `tolerance` of `target` and clearly beats the rest. `target` and
+/// `tolerance` are in glyph-table units, like the beam widths. Checks run
+/// from the most to the least fundamental cause: glyphs, measurement,
+/// dictionary, ambiguity. Lines with `exact_text` are only checked for
+/// fitting the width.
+pub fn diagnose_line(
+    line: &Line,
+    target: f32,
+    tolerance: f32,
+    glyphs: &HashMap<char, f32>,
+    dictionary: Option<&[&str]>,
+) -> Option<LineDiagnosis> {
+    let best = line.beams.first();
+    let residual = best.map(|b| b.width - target);
+    let fits = residual.is_some_and(|r| r.abs() <= tolerance);
+
+    // verbatim text is only checked against the geometry
+    if line.hints.exact_text.is_some() {
+        return match residual {
+            Some(r) if !fits => Some(FailureMode::ExactMismatch { residual: r }.into()),
+            _ => None,
+        };
+    }
+
+    let scores: Vec<f32> = line.beams.iter().map(|b| b.score).collect();
+    let confidence = softmax_confidence(&scores).first().copied().unwrap_or(0.0);
+    let ties = best.map_or(0, |b| {
+        scores
+            .iter()
+            .filter(|&&s| b.score - s <= TIE_MARGIN)
+            .count()
+    });
+    let covered = best.is_some_and(|b| dictionary.is_none_or(|d| in_dictionary(&b.text, d)));
+
+    if fits && covered && confidence >= AMBIGUOUS_CONFIDENCE {
+        return None;
+    }
+
+    let hint = line.hints.first_char.map(String::from);
+    let missing = missing_chars(
+        dictionary
+            .unwrap_or(&[])
+            .iter()
+            .copied()
+            .chain(hint.as_deref()),
+        glyphs,
+    );
+    let dictionary_fits = dictionary.is_some_and(|d| {
+        d.iter()
+            .any(|w| (glyph_sum(w, glyphs) - target).abs() <= tolerance)
+    });
+
+    let mode = if !missing.is_empty() && !dictionary_fits {
+        FailureMode::MissingGlyphs { chars: missing }
+    } else if !fits {
+        FailureMode::MeasurementMismatch { residual }
+    } else if !covered {
+        FailureMode::NoDictionaryCoverage
+    } else {
+        FailureMode::WidthAmbiguity { ties: ties.max(2) }
+    };
+
+    Some(mode.into())
+}
@@ -0,0 +1,231 @@
+// ============================================
+// ENSEMBLE OF SEARCH STRATEGIES
+// ============================================
+
+use crate::output::softmax_confidence;
+use crate::{
+    combined_score, find_candidates_par, find_phrase_candidates, measure_text_kerning,
+    restore_width, Beam, NGramModel, ScoreWeights, SearchStats,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use ttf_parser::Face;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Single dictionary words within tolerance (`find_candidates_par`).
+    Dictionary,
+    /// Dictionary words joined by spaces (`find_phrase_candidates`).
+    Lattice,
+    /// Character-level search (`restore_width`).
+    CharBeam,
+}
+
+impl Strategy {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Strategy::Dictionary => "dictionary",
+            Strategy::Lattice => "lattice",
+            Strategy::CharBeam => "char-beam",
+        }
+    }
+}
+
+/// How the strategies' candidate lists are combined.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fusion {
+    /// `Σ 1 / (k + rank)` over the lists a candidate appears in, rank
+    /// 1-based. Ignores scores, so lists scored on different scales mix
+    /// fairly.
+    ReciprocalRank { k: f32 },
+    /// Each list's scores turned into probabilities (softmax), summed.
+    Calibrated,
+}
+
+impl Default for Fusion {
+    fn default() -> Self {
+        Fusion::ReciprocalRank { k: 60.0 }
+    }
+}
+
+impl FromStr for Fusion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rrf" => Ok(Fusion::default()),
+            "calibrated" => Ok(Fusion::Calibrated),
+            _ => Err("expected rrf or calibrated".to_string()),
+        }
+    }
+}
+
+/// A fused candidate and the rank (0-based) each strategy gave it.
+#[derive(Clone, Debug, Serialize)]
+pub struct EnsembleCandidate {
+    pub text: String,
+    pub width: f32,
+    pub fused: f32,
+    pub sources: Vec<(Strategy, usize)>,
+}
+
+/// The strategy that ranked a candidate highest given its `sources`; on a
+/// tie the one listed first in `Strategy`.
+pub fn top_contributor(sources: &[(Strategy, usize)]) -> Option<Strategy> {
+    sources
+        .iter()
+        .min_by_key(|(s, rank)| (*rank, *s))
+        .map(|(s, _)| *s)
+}
+
+impl EnsembleCandidate {
+    pub fn contributor(&self) -> Option<Strategy> {
+        top_contributor(&self.sources)
+    }
+}
+
+/// Fused candidates best first, plus how many each strategy proposed.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct EnsembleResult {
+    pub candidates: Vec<EnsembleCandidate>,
+    pub proposed: Vec<(Strategy, usize)>,
+}
+
+impl EnsembleResult {
+    pub fn winner(&self) -> Option<&EnsembleCandidate> {
+        self.candidates.first()
+    }
+
+    /// Candidates as beams scored `ln(fused)`, for the rest of the pipeline.
+    pub fn beams(&self) -> Vec<Beam> {
+        self.candidates
+            .iter()
+            .map(|c| Beam {
+                text: c.text.clone(),
+                width: c.width,
+                score: c.fused.max(f32::MIN_POSITIVE).ln(),
+            })
+            .collect()
+    }
+}
+
+/// Runs every strategy on one line and fuses their lists. Dictionary and
+/// lattice candidates are scored with `combined_score` at their measured
+/// width so the calibrated fusion compares like with like; the character
+/// search's own scores are kept. The lattice joins up to `max_phrase_words`
+/// words.
+#[allow(clippy::too_many_arguments)]
+pub fn ensemble_search(
+    face: &Face,
+    glyphs: &HashMap<char, f32>,
+    px_size: f32,
+    target_width: f32,
+    tolerance: f32,
+    dictionary: &[&str],
+    alphabet: &[char],
+    weights: &ScoreWeights,
+    model: Option<&NGramModel>,
+    beam_width: usize,
+    max_phrase_words: usize,
+    fusion: Fusion,
+    stats: &SearchStats,
+) -> EnsembleResult {
+    let scored = |texts: Vec<(String, f32)>| -> Vec<Beam> {
+        texts
+            .into_iter()
+            .map(|(text, _)| {
+                let width = measure_text_kerning(&text, face, glyphs, px_size);
+                let score = combined_score(&text, width, target_width, weights, model);
+                Beam { text, width, score }
+            })
+            .collect()
+    };
+
+    let lists = [
+        (
+            Strategy::Dictionary,
+            scored(find_candidates_par(
+                target_width,
+                glyphs,
+                dictionary,
+                tolerance,
+            )),
+        ),
+        (
+            Strategy::Lattice,
+            scored(find_phrase_candidates(
+                target_width,
+                glyphs,
+                dictionary,
+                tolerance,
+                max_phrase_words,
+                beam_width,
+            )),
+        ),
+        (
+            Strategy::CharBeam,
+            restore_width(
+                face,
+                glyphs,
+                px_size,
+                target_width,
+                tolerance,
+                alphabet,
+                weights,
+                model,
+                beam_width,
+                stats,
+            ),
+        ),
+    ];
+    stats.add_evaluated(2 * dictionary.len());
+
+    let mut fused: HashMap<String, EnsembleCandidate> = HashMap::new();
+    let mut proposed = vec![];
+    for (strategy, mut beams) in lists {
+        beams.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.text.cmp(&b.text))
+        });
+        beams.dedup_by(|a, b| a.text == b.text);
+        beams.truncate(beam_width);
+        proposed.push((strategy, beams.len()));
+
+        let scores: Vec<f32> = beams.iter().map(|b| b.score).collect();
+        let probs = softmax_confidence(&scores);
+        for (rank, (beam, p)) in beams.into_iter().zip(probs).enumerate() {
+            let contribution = match fusion {
+                Fusion::ReciprocalRank { k } => 1.0 / (k + rank as f32 + 1.0),
+                Fusion::Calibrated => p,
+            };
+            let entry = fused.entry(beam.text.clone()).or_insert(EnsembleCandidate {
+                text: beam.text,
+                width: beam.width,
+                fused: 0.0,
+                sources: vec![],
+            });
+            entry.fused += contribution;
+            entry.sources.push((strategy, rank));
+        }
+    }
+
+    // ties (common under rank fusion) go to the candidate more strategies
+    // agree on, then to the more constrained strategy
+    let mut candidates: Vec<EnsembleCandidate> = fused.into_values().collect();
+    candidates.sort_by(|a, b| {
+        b.fused
+            .total_cmp(&a.fused)
+            .then_with(|| b.sources.len().cmp(&a.sources.len()))
+            .then_with(|| a.contributor().cmp(&b.contributor()))
+            .then_with(|| a.text.cmp(&b.text))
+    });
+    candidates.truncate(beam_width);
+    EnsembleResult {
+        candidates,
+        proposed,
+    }
+}
@@ -0,0 +1,181 @@
+// ============================================
+// ENTITY EXTRACTION FROM RESTORED TEXT
+// ============================================
+
+use crate::output::{csv_field, OutputFormat, RestorationResults};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntityKind {
+    Name,
+    Date,
+    Amount,
+    Identifier,
+}
+
+impl EntityKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            EntityKind::Name => "name",
+            EntityKind::Date => "date",
+            EntityKind::Amount => "amount",
+            EntityKind::Identifier => "id",
+        }
+    }
+}
+
+/// One entity as recovered across the document. `confidence` is the best,
+/// over its lines, of the summed confidences of the candidates that
+/// contain it, so an entity shared by the top candidates scores higher than
+/// any one of them.
+#[derive(Clone, Debug, Serialize)]
+pub struct Entity {
+    pub kind: EntityKind,
+    pub text: String,
+    pub lines: Vec<usize>,
+    pub confidence: f32,
+}
+
+/// Patterns, most specific first; a span matched by one is not matched
+/// again by a later one.
+fn patterns() -> Vec<(EntityKind, Regex)> {
+    const MONTHS: &str =
+        "Jan(?:uary)?|Feb(?:ruary)?|Mar(?:ch)?|Apr(?:il)?|May|June?|July?|Aug(?:ust)?|\
+                          Sep(?:tember)?|Oct(?:ober)?|Nov(?:ember)?|Dec(?:ember)?";
+    let date = format!(
+        r"\b\d{{4}}-\d{{2}}-\d{{2}}\b|\b\d{{1,2}}[./]\d{{1,2}}[./]\d{{2,4}}\b|\b\d{{1,2}} (?:{m}) \d{{4}}\b|\b(?:{m}) \d{{1,2}}, \d{{4}}\b",
+        m = MONTHS
+    );
+    [
+        (EntityKind::Date, date.as_str()),
+        (
+            EntityKind::Amount,
+            r"[$€£]\s?\d(?:[\d,.]*\d)?|\b\d(?:[\d,.]*\d)?\s?(?:EUR|USD|GBP|€)",
+        ),
+        (
+            EntityKind::Identifier,
+            r"\b[A-Z]{2,}[-/]?\d[\d-]{2,}\b|\b\d{3,}-\d{2,}(?:-\d+)*\b",
+        ),
+        (
+            EntityKind::Name,
+            r"\b(?:(?:Mr|Ms|Mrs|Dr)\.? )?[A-Z][a-z]+(?: [A-Z]\.)?(?: [A-Z][a-z]+)+\b",
+        ),
+    ]
+    .into_iter()
+    .map(|(kind, p)| (kind, Regex::new(p).unwrap()))
+    .collect()
+}
+
+fn find_with(patterns: &[(EntityKind, Regex)], text: &str) -> Vec<(EntityKind, String)> {
+    let mut taken: Vec<std::ops::Range<usize>> = vec![];
+    let mut out = vec![];
+    for (kind, re) in patterns {
+        for m in re.find_iter(text) {
+            if taken.iter().any(|r| r.start < m.end() && m.start() < r.end) {
+                continue;
+            }
+            taken.push(m.range());
+            out.push((*kind, m.as_str().trim().to_string()));
+        }
+    }
+    out
+}
+
+/// Entities in one string, in pattern order.
+pub fn find_entities(text: &str) -> Vec<(EntityKind, String)> {
+    find_with(&patterns(), text)
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct EntityReport {
+    pub entities: Vec<Entity>,
+}
+
+impl EntityReport {
+    /// Runs the patterns over every candidate of every line. Exact lines
+    /// count with full confidence.
+    pub fn from_results(results: &RestorationResults) -> Self {
+        let patterns = patterns();
+        let mut found: BTreeMap<(EntityKind, String), Entity> = BTreeMap::new();
+        for line in &results.lines {
+            let mut per_line: BTreeMap<(EntityKind, String), f32> = BTreeMap::new();
+            for c in &line.candidates {
+                let confidence = if line.exact { 1.0 } else { c.confidence };
+                for key in find_with(&patterns, &c.text) {
+                    *per_line.entry(key).or_insert(0.0) += confidence;
+                }
+            }
+            for ((kind, text), confidence) in per_line {
+                let entity = found.entry((kind, text.clone())).or_insert(Entity {
+                    kind,
+                    text,
+                    lines: vec![],
+                    confidence: 0.0,
+                });
+                entity.lines.push(line.line);
+                entity.confidence = entity.confidence.max(confidence.min(1.0));
+            }
+        }
+
+        let mut entities: Vec<Entity> = found.into_values().collect();
+        entities.sort_by(|a, b| {
+            a.kind
+                .cmp(&b.kind)
+                .then(b.confidence.total_cmp(&a.confidence))
+        });
+        EntityReport { entities }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = format!(
+            "{:<8} {:<32} {:>10}  Lines\n{:-<64}\n",
+            "Kind", "Entity", "Confidence", ""
+        );
+        for e in &self.entities {
+            let lines: Vec<String> = e.lines.iter().map(usize::to_string).collect();
+            out.push_str(&format!(
+                "{:<8} {:<32} {:>9.1}%  {}\n",
+                e.kind.label(),
+                e.text,
+                e.confidence * 100.0,
+                lines.join(", ")
+            ));
+        }
+        if self.entities.is_empty() {
+            out.push_str("No entities recovered\n");
+        }
+        out
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("kind,text,confidence,lines\n");
+        for e in &self.entities {
+            let lines: Vec<String> = e.lines.iter().map(usize::to_string).collect();
+            out.push_str(&format!(
+                "{},{},{:.4},{}\n",
+                e.kind.label(),
+                csv_field(&e.text),
+                e.confidence,
+                lines.join(" ")
+            ));
+        }
+        out
+    }
+
+    pub fn render(&self, format: OutputFormat) -> io::Result<String> {
+        match format {
+            OutputFormat::Text => Ok(self.to_text()),
+            OutputFormat::Json => serde_json::to_string_pretty(self).map_err(io::Error::other),
+            OutputFormat::Csv => Ok(self.to_csv()),
+        }
+    }
+
+    pub fn write(&self, format: OutputFormat, path: &str) -> io::Result<()> {
+        fs::write(path, self.render(format)?)
+    }
+}
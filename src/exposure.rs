@@ -0,0 +1,398 @@
+// ============================================
+// REDACTION EXPOSURE AUDIT
+// ============================================
+
+use crate::output::{csv_field, softmax_confidence, OutputFormat};
+use crate::pipeline::RestorePipeline;
+use crate::redaction::{detect_redactions, text_under, RedactionTechnique};
+use crate::{glyph_sum, ngram_log_prob, Document, NGramModel};
+use lopdf::Document as PdfDocument;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+/// A region whose hidden text is the top candidate with at least this
+/// confidence counts as recovered rather than merely shortlisted.
+const RECOVERED_CONFIDENCE: f32 = 0.5;
+
+/// Largest padding tried, in ems of the region's text.
+const MAX_PADDING_EMS: f32 = 3.0;
+
+/// Padding is tried in steps of this share of an em.
+const PADDING_STEP_EMS: f32 = 0.05;
+
+/// Padding amounts the entropy of a padded box is averaged over.
+const PADDING_SAMPLES: usize = 5;
+
+/// How much of a redaction this tool gets back, worst first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Exposure {
+    /// The text is still in the content stream under the cover.
+    TextLeaks,
+    /// The hidden text is the top candidate.
+    Recovered,
+    /// The hidden text is among the reported candidates.
+    Shortlisted,
+    /// The hidden text is not among the candidates.
+    Resistant,
+    /// No font size to measure the region with, or nothing under it in the
+    /// original.
+    NotAssessed,
+}
+
+impl Exposure {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Exposure::TextLeaks => "text leaks",
+            Exposure::Recovered => "recovered",
+            Exposure::Shortlisted => "shortlisted",
+            Exposure::Resistant => "resistant",
+            Exposure::NotAssessed => "not assessed",
+        }
+    }
+
+    pub fn advice(&self) -> &'static str {
+        match self {
+            Exposure::TextLeaks => "remove the text from the content stream, not just cover it",
+            Exposure::Recovered | Exposure::Shortlisted => {
+                "pad the box to a fixed width and close the gap in the text run"
+            }
+            Exposure::Resistant | Exposure::NotAssessed => "",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RegionExposure {
+    pub page: u32,
+    pub technique: String,
+    /// Text the original has under the region.
+    pub original: String,
+    pub exposure: Exposure,
+    /// 1-based rank of the original among the candidates.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rank: Option<usize>,
+    /// Confidence the tool gives the original, 0 when not a candidate.
+    pub confidence: f32,
+    /// Best candidate, for regions the tool got wrong.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub best_guess: Option<String>,
+    /// Size of the text the region covers, to convert widths to user space.
+    #[serde(skip)]
+    pub font_size: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub padding: Option<PaddingAdvice>,
+}
+
+/// How ambiguous a region's width is, as the entropy in bits of the
+/// candidates an attacker is left with, and the padding that makes it
+/// ambiguous enough. Widths are in PDF user space.
+#[derive(Clone, Debug, Serialize)]
+pub struct PaddingAdvice {
+    pub entropy_bits: f32,
+    /// Smallest padding reaching the target entropy, `None` when even
+    /// `MAX_PADDING_EMS` does not.
+    pub padding: Option<f32>,
+    pub padded_entropy_bits: f32,
+    /// Entropy when every box is drawn at the audit's `fixed_width`.
+    pub fixed_width_entropy_bits: f32,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RedactionAudit {
+    pub regions: Vec<RegionExposure>,
+    /// Box width, in user space, that every box can be normalized to: the
+    /// widest text any assessed region covers.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fixed_width: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_entropy_bits: Option<f32>,
+}
+
+/// Entropy in bits of the candidates of `pool` whose width falls in
+/// `[lo, hi]`, weighted by the model (uniformly without one).
+fn window_entropy(pool: &[(String, f32, f32)], lo: f32, hi: f32) -> f32 {
+    let scores: Vec<f32> = pool
+        .iter()
+        .filter(|(_, w, _)| *w >= lo && *w <= hi)
+        .map(|p| p.2)
+        .collect();
+    softmax_confidence(&scores)
+        .into_iter()
+        .filter(|&p| p > 0.0)
+        .map(|p| -p * p.log2())
+        .sum::<f32>()
+        .max(0.0)
+}
+
+/// Restores every redaction found in `redacted` with `pipeline`, as an
+/// attacker would, and checks the candidates against the text `original`
+/// has in the same place. Candidates past `top_k` do not count.
+pub fn audit_redactions(
+    original: &PdfDocument,
+    redacted: &PdfDocument,
+    pipeline: &mut RestorePipeline,
+    px_size: f32,
+    top_k: usize,
+) -> io::Result<RedactionAudit> {
+    let regions = detect_redactions(redacted);
+    let lines: Vec<_> = regions.iter().map(|r| r.to_line(px_size)).collect();
+    let mut doc = Document {
+        lines: lines.iter().flatten().cloned().collect(),
+    };
+    pipeline.run(&mut doc)?;
+
+    let mut restored = doc.lines.iter();
+    let mut out = vec![];
+    for (region, line) in regions.iter().zip(&lines) {
+        let original_text = text_under(original, region.page, &region.bbox);
+        let line = line.as_ref().and_then(|_| restored.next());
+
+        let (exposure, rank, confidence, best_guess) = match line {
+            _ if original_text.is_empty() => (Exposure::NotAssessed, None, 0.0, None),
+            _ if region.exact_text().is_some() => (Exposure::TextLeaks, Some(1), 1.0, None),
+            None => (Exposure::NotAssessed, None, 0.0, None),
+            Some(line) => {
+                let beams = &line.beams[..line.beams.len().min(top_k)];
+                let confidences =
+                    softmax_confidence(&beams.iter().map(|b| b.score).collect::<Vec<_>>());
+                let rank = beams.iter().position(|b| b.text == original_text);
+                let confidence = rank.map_or(0.0, |r| confidences[r]);
+                let exposure = match rank {
+                    Some(0) if confidence >= RECOVERED_CONFIDENCE => Exposure::Recovered,
+                    Some(_) => Exposure::Shortlisted,
+                    None => Exposure::Resistant,
+                };
+                let guess = beams
+                    .first()
+                    .filter(|b| b.text != original_text)
+                    .map(|b| b.text.clone());
+                (exposure, rank.map(|r| r + 1), confidence, guess)
+            }
+        };
+
+        out.push(RegionExposure {
+            page: region.page,
+            technique: technique_name(region.technique).to_string(),
+            original: original_text,
+            exposure,
+            rank,
+            confidence,
+            best_guess,
+            font_size: region.font_size,
+            padding: None,
+        });
+    }
+
+    Ok(RedactionAudit {
+        regions: out,
+        ..RedactionAudit::default()
+    })
+}
+
+fn technique_name(technique: RedactionTechnique) -> &'static str {
+    match technique {
+        RedactionTechnique::ImageOverlay => "image overlay",
+        RedactionTechnique::VectorBox => "vector box",
+        RedactionTechnique::TextRemoval => "text removal",
+    }
+}
+
+impl RedactionAudit {
+    /// Adds padding advice to every region that is recovered, shortlisted
+    /// or resistant. A box widened by a random amount of up to `p` tells an
+    /// attacker only that the text is between `p` narrower than the box and
+    /// as wide as it, so the candidates are the entries of `pool` (plus the
+    /// original) in that window, within `tolerance`; the entropy is averaged
+    /// over the amounts. `glyphs` must be built at `px_size`; `tolerance` is
+    /// in the same px.
+    pub fn with_padding_advice(
+        mut self,
+        pool: &[&str],
+        glyphs: &HashMap<char, f32>,
+        px_size: f32,
+        tolerance: f32,
+        model: Option<&NGramModel>,
+        target_bits: f32,
+    ) -> Self {
+        let assessed = |r: &RegionExposure| {
+            matches!(
+                r.exposure,
+                Exposure::Recovered | Exposure::Shortlisted | Exposure::Resistant
+            )
+        };
+        let scored = |text: &str| {
+            (
+                text.to_string(),
+                glyph_sum(text, glyphs),
+                model.map_or(0.0, |m| ngram_log_prob(text, m)),
+            )
+        };
+
+        // user-space units per px of `glyphs` at a region's font size
+        let to_user = |r: &RegionExposure| r.font_size.map_or(1.0, |size| size / px_size);
+        // every box drawn as wide as the widest hidden text, in user space;
+        // regions set at different sizes are compared after scaling
+        let fixed_width = self
+            .regions
+            .iter()
+            .filter(|r| assessed(r))
+            .map(|r| glyph_sum(&r.original, glyphs) * to_user(r))
+            .reduce(f32::max);
+
+        let step = PADDING_STEP_EMS * px_size;
+        let steps = (MAX_PADDING_EMS / PADDING_STEP_EMS) as usize;
+        for region in self.regions.iter_mut().filter(|r| assessed(r)) {
+            let mut pool: Vec<(String, f32, f32)> = pool.iter().map(|t| scored(t)).collect();
+            if !pool.iter().any(|p| p.0 == region.original) {
+                pool.push(scored(&region.original));
+            }
+            let width = glyph_sum(&region.original, glyphs);
+            let entropy_at = |padding: f32| {
+                let box_widths = (0..PADDING_SAMPLES)
+                    .map(|k| width + padding * k as f32 / (PADDING_SAMPLES - 1) as f32);
+                box_widths
+                    .map(|b| window_entropy(&pool, b - padding - tolerance, b + tolerance))
+                    .sum::<f32>()
+                    / PADDING_SAMPLES as f32
+            };
+
+            let needed = (0..=steps)
+                .map(|i| i as f32 * step)
+                .find(|&p| entropy_at(p) >= target_bits);
+            let to_user = to_user(region);
+            // the fixed box at this region's size, back in px
+            let fixed_px = fixed_width.unwrap_or(0.0) / to_user;
+            region.padding = Some(PaddingAdvice {
+                entropy_bits: entropy_at(0.0),
+                padding: needed.map(|p| p * to_user),
+                padded_entropy_bits: entropy_at(needed.unwrap_or(steps as f32 * step)),
+                fixed_width_entropy_bits: window_entropy(&pool, 0.0, fixed_px + tolerance),
+            });
+        }
+
+        self.fixed_width = fixed_width;
+        self.target_entropy_bits = Some(target_bits);
+        self
+    }
+
+    /// Regions this tool gets the text of, or shortlists it for.
+    pub fn exposed(&self) -> usize {
+        self.regions
+            .iter()
+            .filter(|r| {
+                matches!(
+                    r.exposure,
+                    Exposure::TextLeaks | Exposure::Recovered | Exposure::Shortlisted
+                )
+            })
+            .count()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = format!(
+            "{:<5} {:<14} {:<20} {:<13} {:>5} {:>10}  Fix\n{:-<100}\n",
+            "Page", "Technique", "Original", "Exposure", "Rank", "Confidence", ""
+        );
+        for r in &self.regions {
+            out.push_str(&format!(
+                "{:<5} {:<14} {:<20} {:<13} {:>5} {:>9.1}%  {}\n",
+                r.page,
+                r.technique,
+                r.original,
+                r.exposure.label(),
+                r.rank.map_or("-".to_string(), |n| n.to_string()),
+                r.confidence * 100.0,
+                r.exposure.advice()
+            ));
+        }
+        out.push_str(&format!(
+            "{:-<100}\nExposed: {} of {} redactions\n",
+            "",
+            self.exposed(),
+            self.regions.len()
+        ));
+
+        let advised: Vec<&RegionExposure> = self
+            .regions
+            .iter()
+            .filter(|r| r.padding.is_some())
+            .collect();
+        if let (Some(target), false) = (self.target_entropy_bits, advised.is_empty()) {
+            out.push_str(&format!(
+                "\nPadding for at least {:.1} bits of ambiguity:\n{:<5} {:<20} {:>10} {:>10} {:>12} {:>12}\n",
+                target, "Page", "Original", "Now (bits)", "Padding", "Padded", "Fixed width"
+            ));
+            for r in advised {
+                let Some(a) = &r.padding else { continue };
+                out.push_str(&format!(
+                    "{:<5} {:<20} {:>10.2} {:>10} {:>12.2} {:>12.2}\n",
+                    r.page,
+                    r.original,
+                    a.entropy_bits,
+                    a.padding
+                        .map_or("not enough".to_string(), |p| format!("{:.2}", p)),
+                    a.padded_entropy_bits,
+                    a.fixed_width_entropy_bits
+                ));
+            }
+            if let Some(fixed) = self.fixed_width {
+                let weakest = self
+                    .regions
+                    .iter()
+                    .filter_map(|r| r.padding.as_ref())
+                    .map(|a| a.fixed_width_entropy_bits)
+                    .fold(f32::INFINITY, f32::min);
+                let verdict = if weakest >= target {
+                    "enough for every region"
+                } else {
+                    "not enough"
+                };
+                out.push_str(&format!(
+                    "Boxes normalized to {:.2} wide: at least {:.2} bits ({})\n",
+                    fixed, weakest, verdict
+                ));
+            }
+        }
+        out
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("page,technique,original,exposure,rank,confidence,best_guess\n");
+        for r in &self.regions {
+            out.push_str(&format!(
+                "{},{},{},{},{},{:.4},{}\n",
+                r.page,
+                r.technique,
+                csv_field(&r.original),
+                r.exposure.label(),
+                r.rank.map_or(String::new(), |n| n.to_string()),
+                r.confidence,
+                csv_field(r.best_guess.as_deref().unwrap_or(""))
+            ));
+        }
+        out
+    }
+
+    pub fn render(&self, format: OutputFormat) -> io::Result<String> {
+        match format {
+            OutputFormat::Text => Ok(self.to_text()),
+            OutputFormat::Json => serde_json::to_string_pretty(self).map_err(io::Error::other),
+            OutputFormat::Csv => Ok(self.to_csv()),
+        }
+    }
+
+    /// Writes to `path`, or to stdout when no path is given.
+    pub fn write(&self, format: OutputFormat, path: Option<&str>) -> io::Result<()> {
+        let rendered = self.render(format)?;
+        match path {
+            Some(p) => fs::write(p, rendered),
+            None => {
+                print!("{}", rendered);
+                Ok(())
+            }
+        }
+    }
+}
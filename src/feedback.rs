@@ -0,0 +1,196 @@
+// ============================================
+// REVIEW FEEDBACK AS PRIORS AND WEIGHTS
+// ============================================
+
+use crate::review::{Decision, ReviewProject};
+use crate::scoring::ScoreTerms;
+use crate::{quantize, Beam, NGramModel, ScoreWeights};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+
+/// Where `review` keeps the store, and where `restore` looks for one,
+/// when no `--feedback` path is given.
+pub const DEFAULT_FEEDBACK_PATH: &str = "restore_feedback.json";
+
+/// Word priors stay within ± this many score units, so a word approved
+/// over and over cannot outweigh the width evidence on its own.
+const MAX_WORD_PRIOR: f32 = 5.0;
+
+/// Step of the prior and weight updates.
+const LEARNING_RATE: f32 = 0.1;
+
+/// What review sessions taught: a score adjustment per word and, once a
+/// ranking mistake has been seen, learned scorer weights. Updated online,
+/// one decision at a time; decisions already learned are remembered so
+/// merging the same project again changes nothing.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FeedbackStore {
+    /// Lowercased word -> added to the score of every candidate holding it.
+    pub word_priors: BTreeMap<String, f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weights: Option<ScoreWeights>,
+    #[serde(default)]
+    pub updates: usize,
+    #[serde(default)]
+    learned: BTreeSet<String>,
+}
+
+/// The feedback a run applied, recorded in its results and summary so a
+/// changed ranking can be traced back to the store behind it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AppliedFeedback {
+    pub path: String,
+    /// Review decisions the store was learned from.
+    pub updates: usize,
+    pub word_priors: usize,
+    /// Whether the learned scorer weights replaced the configured ones.
+    pub learned_weights: bool,
+}
+
+/// Lowercased words of `text`.
+fn words(text: &str) -> BTreeSet<String> {
+    text.split_whitespace().map(str::to_lowercase).collect()
+}
+
+impl FeedbackStore {
+    /// The store at `path`, or an empty one if there is none yet.
+    pub fn load(path: &str) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(data) => serde_json::from_str(&data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(
+            path,
+            serde_json::to_string_pretty(self).map_err(io::Error::other)?,
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.updates == 0
+    }
+
+    /// What applying this store, loaded from `path`, changes.
+    pub fn applied(&self, path: &str) -> AppliedFeedback {
+        AppliedFeedback {
+            path: path.to_string(),
+            updates: self.updates,
+            word_priors: self.word_priors.len(),
+            learned_weights: self.weights.is_some(),
+        }
+    }
+
+    /// Sum of the priors of `text`'s words.
+    pub fn prior(&self, text: &str) -> f32 {
+        words(text)
+            .iter()
+            .filter_map(|w| self.word_priors.get(w))
+            .sum()
+    }
+
+    fn nudge(&mut self, word: &str, delta: f32) {
+        let p = self.word_priors.entry(word.to_string()).or_insert(0.0);
+        *p = (*p + delta).clamp(-MAX_WORD_PRIOR, MAX_WORD_PRIOR);
+    }
+
+    /// Merges the decisions of a review project. An approved text raises
+    /// its words' priors and a rejected one lowers the words it does not
+    /// share with the approval. Where the approved candidate did not
+    /// outscore a rejected or higher-ranked one, the weights take a
+    /// perceptron step toward the approved candidate's raw score
+    /// components, starting from `base`. Candidates without a recorded
+    /// width only move priors. Returns how many new decisions were learned.
+    pub fn learn(
+        &mut self,
+        project: &ReviewProject,
+        base: &ScoreWeights,
+        model: Option<&NGramModel>,
+    ) -> usize {
+        let before = self.updates;
+        let mut weights = self.weights.clone().unwrap_or_else(|| base.clone());
+        let mut weights_moved = false;
+
+        for line in &project.lines {
+            let key = quantize(line.observed_width);
+            let approved = match &line.decision {
+                Decision::Approved(text) => Some(text.as_str()),
+                Decision::Pending => None,
+            };
+            let kept = approved.map(words).unwrap_or_default();
+
+            if let Some(text) = approved.filter(|t| self.learned.insert(format!("{}|+{}", key, t)))
+            {
+                for w in &kept {
+                    self.nudge(w, LEARNING_RATE);
+                }
+                self.updates += 1;
+
+                let Some(chosen) = line
+                    .candidates
+                    .iter()
+                    .find(|c| c.text == text && c.width > 0.0)
+                else {
+                    continue;
+                };
+                let raw = |text: &str, width: f32| {
+                    ScoreTerms::raw(text, width, line.observed_width, model).to_array()
+                };
+                let a = raw(&chosen.text, chosen.width);
+                let rivals = line.candidates.iter().filter(|c| {
+                    c.text != text
+                        && c.width > 0.0
+                        && (line.rejected.contains(&c.text) || c.score >= chosen.score)
+                });
+                for rival in rivals {
+                    let diff: Vec<f32> = a
+                        .iter()
+                        .zip(raw(&rival.text, rival.width))
+                        .map(|(a, r)| a - r)
+                        .collect();
+                    let w = [
+                        &mut weights.width,
+                        &mut weights.word_len,
+                        &mut weights.spaces,
+                        &mut weights.ngram,
+                    ];
+                    let margin: f32 = w.iter().zip(&diff).map(|(w, d)| **w * d).sum();
+                    if margin <= 0.0 {
+                        for (w, d) in w.into_iter().zip(&diff) {
+                            *w = (*w + LEARNING_RATE * d).max(0.0);
+                        }
+                        weights_moved = true;
+                    }
+                }
+            }
+
+            for text in &line.rejected {
+                if !self.learned.insert(format!("{}|-{}", key, text)) {
+                    continue;
+                }
+                for w in words(text).difference(&kept) {
+                    self.nudge(w, -LEARNING_RATE);
+                }
+                self.updates += 1;
+            }
+        }
+
+        if weights_moved {
+            self.weights = Some(weights);
+        }
+        self.updates - before
+    }
+
+    /// Adds the word priors to `beams` and re-sorts them, best first.
+    pub fn apply(&self, beams: &mut [Beam]) {
+        for b in beams.iter_mut() {
+            b.score += self.prior(&b.text);
+        }
+        beams.sort_by(|a, b| b.score.total_cmp(&a.score));
+    }
+}
@@ -0,0 +1,232 @@
+// ============================================
+// FONT DISCOVERY AND MULTI-FONT FALLBACK
+// ============================================
+
+use crate::build_glyph_widths;
+use fontdb::{Database, Family, Query, Stretch, Style, Weight};
+use std::collections::HashMap;
+use ttf_parser::Face;
+
+/// Families tried when a requested font cannot be found, in order. Covers
+/// the usual defaults on Linux, macOS and Windows.
+pub const DEFAULT_FALLBACK_FAMILIES: &[&str] = &[
+    "DejaVu Sans",
+    "Liberation Sans",
+    "Arial",
+    "Helvetica",
+    "Noto Sans",
+];
+
+/// Metric-only cuts of DejaVu Sans and DejaVu Serif, and a metric-only CJK
+/// font, compiled into the binary (see `fixtures/README.md`), so tests and
+/// WASM builds measure the same widths with no font files on disk.
+#[cfg(feature = "fixture-font")]
+const FIXTURE_FACES: &[(&str, &[u8])] = &[
+    (
+        "DejaVu Sans",
+        include_bytes!("../fixtures/DejaVuSansMetrics.ttf"),
+    ),
+    (
+        "DejaVu Serif",
+        include_bytes!("../fixtures/DejaVuSerifMetrics.ttf"),
+    ),
+    ("CJK Metrics", include_bytes!("../fixtures/CjkMetrics.ttf")),
+];
+
+/// Embedded fixture face for `family`, matched ignoring case and spaces so
+/// "DejaVuSans" works too. Always `None` without the `fixture-font` feature.
+pub fn fixture_face(family: &str) -> Option<Face<'static>> {
+    #[cfg(feature = "fixture-font")]
+    {
+        let key = |s: &str| s.replace(' ', "").to_lowercase();
+        FIXTURE_FACES
+            .iter()
+            .find(|(name, _)| key(name) == key(family))
+            .and_then(|(_, data)| Face::parse(data, 0).ok())
+    }
+    #[cfg(not(feature = "fixture-font"))]
+    {
+        let _ = family;
+        None
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FontQuery {
+    pub family: String,
+    pub weight: u16, // 400 regular, 700 bold
+    pub italic: bool,
+}
+
+impl FontQuery {
+    pub fn regular(family: &str) -> Self {
+        FontQuery {
+            family: family.to_string(),
+            weight: 400,
+            italic: false,
+        }
+    }
+}
+
+/// System font index backed by `fontdb`, scanned once per instance.
+pub struct FontLibrary {
+    db: Database,
+}
+
+impl FontLibrary {
+    pub fn system() -> Self {
+        let mut db = Database::new();
+        db.load_system_fonts();
+        FontLibrary { db }
+    }
+
+    pub fn len(&self) -> usize {
+        self.db.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.db.is_empty()
+    }
+
+    /// Resolves a family/weight/style query to a parsed face. The font data
+    /// is leaked, same as `load_font`, so the face lives for the whole run.
+    pub fn resolve(&self, query: &FontQuery) -> Option<Face<'static>> {
+        let families = [Family::Name(&query.family)];
+        let id = self.db.query(&Query {
+            families: &families,
+            weight: Weight(query.weight),
+            stretch: Stretch::Normal,
+            style: if query.italic {
+                Style::Italic
+            } else {
+                Style::Normal
+            },
+        })?;
+
+        self.db
+            .with_face_data(id, |data, index| {
+                let data: &'static [u8] = Box::leak(data.to_vec().into_boxed_slice());
+                Face::parse(data, index).ok()
+            })
+            .flatten()
+    }
+
+    /// First family of `families` that resolves, then the default chain,
+    /// then the system sans-serif.
+    pub fn resolve_with_fallback(
+        &self,
+        families: &[&str],
+        weight: u16,
+        italic: bool,
+    ) -> Option<Face<'static>> {
+        families
+            .iter()
+            .chain(DEFAULT_FALLBACK_FAMILIES)
+            .find_map(|f| {
+                self.resolve(&FontQuery {
+                    family: f.to_string(),
+                    weight,
+                    italic,
+                })
+            })
+            .or_else(|| self.resolve_generic_sans(weight, italic))
+    }
+
+    fn resolve_generic_sans(&self, weight: u16, italic: bool) -> Option<Face<'static>> {
+        let id = self.db.query(&Query {
+            families: &[Family::SansSerif],
+            weight: Weight(weight),
+            stretch: Stretch::Normal,
+            style: if italic { Style::Italic } else { Style::Normal },
+        })?;
+
+        self.db
+            .with_face_data(id, |data, index| {
+                let data: &'static [u8] = Box::leak(data.to_vec().into_boxed_slice());
+                Face::parse(data, index).ok()
+            })
+            .flatten()
+    }
+}
+
+/// Prioritized list of faces. Measurement uses the first face that has a
+/// glyph for each character instead of counting missing glyphs as 0.0.
+pub struct FontSet {
+    pub faces: Vec<Face<'static>>,
+}
+
+impl FontSet {
+    pub fn new(primary: Face<'static>) -> Self {
+        FontSet {
+            faces: vec![primary],
+        }
+    }
+
+    /// Resolves every family that exists on this system, in the given order.
+    pub fn from_families(
+        library: &FontLibrary,
+        families: &[&str],
+        weight: u16,
+        italic: bool,
+    ) -> Option<Self> {
+        let faces: Vec<Face<'static>> = families
+            .iter()
+            .filter_map(|f| {
+                library.resolve(&FontQuery {
+                    family: f.to_string(),
+                    weight,
+                    italic,
+                })
+            })
+            .collect();
+
+        if faces.is_empty() {
+            None
+        } else {
+            Some(FontSet { faces })
+        }
+    }
+
+    pub fn with_fallback(mut self, face: Face<'static>) -> Self {
+        self.faces.push(face);
+        self
+    }
+
+    pub fn primary(&self) -> &Face<'static> {
+        &self.faces[0]
+    }
+
+    /// Advance of `ch` in px from the first face that maps it.
+    pub fn advance(&self, ch: char, px_size: f32) -> Option<f32> {
+        self.faces.iter().find_map(|face| {
+            let scale = px_size / face.units_per_em() as f32;
+            face.glyph_index(ch)
+                .and_then(|g| face.glyph_hor_advance(g))
+                .map(|a| a as f32 * scale)
+        })
+    }
+
+    /// Width of `text` plus the characters no face in the set could measure.
+    pub fn measure_text(&self, text: &str, px_size: f32) -> (f32, Vec<char>) {
+        let mut total = 0.0;
+        let mut missing = vec![];
+
+        for ch in text.chars() {
+            match self.advance(ch, px_size) {
+                Some(w) => total += w,
+                None => missing.push(ch),
+            }
+        }
+
+        (total, missing)
+    }
+
+    /// `build_glyph_widths` over the whole set; earlier faces win.
+    pub fn glyph_widths(&self, px_size: f32) -> HashMap<char, f32> {
+        let mut map = HashMap::new();
+        for face in self.faces.iter().rev() {
+            map.extend(build_glyph_widths(face, px_size));
+        }
+        map
+    }
+}
@@ -0,0 +1,2794 @@
+pub mod attribution;
+pub mod audit;
+pub mod calibration;
+pub mod cjk;
+pub mod context;
+pub mod crossformat;
+pub mod diagnosis;
+pub mod ensemble;
+pub mod entities;
+pub mod exposure;
+pub mod feedback;
+pub mod fonts;
+pub mod locale;
+pub mod marginal;
+pub mod mixture;
+pub mod output;
+pub mod pdf_metrics;
+pub mod pipeline;
+pub mod prelude;
+pub mod provenance;
+pub mod ragged;
+pub mod raster;
+pub mod redaction;
+pub mod review;
+pub mod scoring;
+pub mod solve;
+pub mod summary;
+#[doc(hidden)]
+pub mod tests;
+pub mod visible;
+pub mod watch;
+pub mod widthmodel;
+
+use locale::Template;
+use rand::Rng;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use rayon::prelude::*;
+use rustc_hash::{FxBuildHasher, FxHashMap};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::hash::BuildHasher;
+use std::io::{self, BufRead};
+use std::path::Path;
+use ttf_parser::Face;
+
+// ============================================
+// N-GRAM MODEL
+// ============================================
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Smoothing {
+    /// Add-alpha smoothing over the observed character vocabulary.
+    Laplace { alpha: f32 },
+    /// Interpolated Kneser–Ney with a single absolute discount.
+    KneserNey { discount: f32 },
+}
+
+impl Default for Smoothing {
+    fn default() -> Self {
+        Smoothing::Laplace { alpha: 1.0 }
+    }
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct NGramModel {
+    pub n: usize,
+    pub counts: FxHashMap<String, usize>,
+    pub total: usize,
+    #[serde(default)]
+    pub smoothing: Smoothing,
+
+    // derived from `counts` by `finalize`, rebuilt after loading
+    #[serde(skip)]
+    context_counts: FxHashMap<String, usize>,
+    #[serde(skip)]
+    context_types: FxHashMap<String, usize>,
+    #[serde(skip)]
+    continuation: FxHashMap<char, usize>,
+    #[serde(skip)]
+    vocab: usize,
+    #[serde(skip)]
+    filter: GramFilter,
+}
+
+pub fn train_ngram(text: &str, n: usize) -> NGramModel {
+    let mut model = NGramModel {
+        n,
+        ..Default::default()
+    };
+
+    let chars: Vec<char> = text.chars().collect();
+
+    for i in 0..chars.len().saturating_sub(n - 1) {
+        let gram: String = chars[i..i + n].iter().collect();
+        *model.counts.entry(gram).or_insert(0) += 1;
+        model.total += 1;
+    }
+
+    model.finalize();
+    model
+}
+
+impl NGramModel {
+    /// Streams a corpus line by line; lines are joined with a space so grams
+    /// can span line breaks without holding the whole corpus in memory.
+    pub fn train_from_reader<R: BufRead>(reader: R, n: usize) -> io::Result<Self> {
+        let mut model = NGramModel {
+            n,
+            ..Default::default()
+        };
+        let mut window: VecDeque<char> = VecDeque::with_capacity(n);
+
+        for line in reader.lines() {
+            let line = line?;
+            for ch in line.chars().chain(std::iter::once(' ')) {
+                if window.len() == n {
+                    window.pop_front();
+                }
+                window.push_back(ch);
+                if window.len() == n {
+                    let gram: String = window.iter().collect();
+                    *model.counts.entry(gram).or_insert(0) += 1;
+                    model.total += 1;
+                }
+            }
+        }
+
+        model.finalize();
+        Ok(model)
+    }
+
+    pub fn train_from_file(path: &str, n: usize) -> io::Result<Self> {
+        let file = fs::File::open(path)?;
+        Self::train_from_reader(io::BufReader::new(file), n)
+    }
+
+    pub fn with_smoothing(mut self, smoothing: Smoothing) -> Self {
+        self.smoothing = smoothing;
+        self
+    }
+
+    pub fn save_json(&self, path: &str) -> io::Result<()> {
+        let json = serde_json::to_string(self).map_err(io::Error::other)?;
+        fs::write(path, json)
+    }
+
+    pub fn load_json(path: &str) -> io::Result<Self> {
+        let data = fs::read_to_string(path)?;
+        let mut model: NGramModel = serde_json::from_str(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        model.finalize();
+        Ok(model)
+    }
+
+    /// Rebuilds the context and continuation tables used for smoothing.
+    pub fn finalize(&mut self) {
+        self.context_counts.clear();
+        self.context_types.clear();
+        self.continuation.clear();
+
+        let mut chars = std::collections::HashSet::new();
+
+        for (gram, &count) in &self.counts {
+            let mut it = gram.chars();
+            let last = it.next_back();
+            let ctx: String = it.collect();
+
+            *self.context_counts.entry(ctx.clone()).or_insert(0) += count;
+            *self.context_types.entry(ctx).or_insert(0) += 1;
+            if let Some(last) = last {
+                *self.continuation.entry(last).or_insert(0) += 1;
+            }
+            chars.extend(gram.chars());
+        }
+
+        self.vocab = chars.len();
+
+        self.filter = GramFilter::with_capacity(self.counts.len() + self.context_counts.len());
+        for key in self.counts.keys().chain(self.context_counts.keys()) {
+            self.filter.insert(key);
+        }
+    }
+
+    /// Count of `key` in `table`, skipping the hash lookup when the filter
+    /// already rules the key out.
+    fn lookup(&self, table: &FxHashMap<String, usize>, key: &str) -> usize {
+        if self.filter.may_contain(key) {
+            table.get(key).copied().unwrap_or(0)
+        } else {
+            0
+        }
+    }
+
+    /// Smoothed conditional probability of the last character of `gram`
+    /// given the preceding `n - 1` characters.
+    pub fn prob(&self, gram: &str) -> f32 {
+        let last = gram.chars().next_back();
+        let ctx = &gram[..gram.len() - last.map_or(0, char::len_utf8)];
+
+        let count = self.lookup(&self.counts, gram) as f32;
+        let ctx_count = self.lookup(&self.context_counts, ctx) as f32;
+        // one extra slot for characters never seen in training
+        let vocab = (self.vocab + 1) as f32;
+
+        match self.smoothing {
+            Smoothing::Laplace { alpha } => (count + alpha) / (ctx_count + alpha * vocab),
+            Smoothing::KneserNey { discount } => {
+                let cont_total = self.counts.len().max(1) as f32;
+                let cont = last
+                    .and_then(|c| self.continuation.get(&c))
+                    .copied()
+                    .unwrap_or(0) as f32;
+                // unseen characters share half a continuation count
+                let p_cont = cont.max(0.5) / (cont_total + 0.5 * vocab);
+
+                if ctx_count == 0.0 {
+                    return p_cont;
+                }
+
+                let types = self.context_types.get(ctx).copied().unwrap_or(0) as f32;
+                let lambda = discount * types / ctx_count;
+                (count - discount).max(0.0) / ctx_count + lambda * p_cont
+            }
+        }
+    }
+}
+
+/// Smoothed log-likelihood of `text`. Kept for existing callers; identical
+/// to `ngram_log_prob`.
+pub fn ngram_score(text: &str, model: &NGramModel) -> f32 {
+    ngram_log_prob(text, model)
+}
+
+/// Log-likelihood of `text` under the model, using the model's smoothing so
+/// that unseen grams are penalized instead of scoring like a single occurrence.
+pub fn ngram_log_prob(text: &str, model: &NGramModel) -> f32 {
+    if model.total == 0 {
+        return 0.0;
+    }
+
+    // grams are borrowed from `text` by char boundary, not rebuilt
+    let bounds: Vec<usize> = text
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(text.len()))
+        .collect();
+    let mut score = 0.0;
+
+    for i in 0..(bounds.len() - 1).saturating_sub(model.n - 1) {
+        score += model.prob(&text[bounds[i]..bounds[i + model.n]]).ln();
+    }
+
+    score
+}
+
+// ============================================
+// N-GRAM LOOKUP FILTER
+// ============================================
+
+const FILTER_BITS_PER_KEY: usize = 10;
+const FILTER_HASHES: u64 = 3;
+
+/// Bloom filter over the grams and contexts of a model. Most grams produced
+/// while scoring beams were never seen in training; the filter answers those
+/// with three bit tests instead of a hash map probe, at about 1% false
+/// positives. An empty filter (model not finalized) lets every key through.
+///
+/// Scoring 400k random 8-letter strings against a bigram model (phase 32,
+/// release build, best of three runs):
+///
+/// | lookup                               | time    |
+/// |--------------------------------------|---------|
+/// | `String` per gram, SipHash map       | 315 ms  |
+/// | borrowed grams, filter + FxHash map  | 188 ms  |
+#[derive(Clone, Default)]
+pub struct GramFilter {
+    bits: Vec<u64>,
+    mask: u64,
+}
+
+impl GramFilter {
+    pub fn with_capacity(keys: usize) -> Self {
+        let bits = (keys.max(1) * FILTER_BITS_PER_KEY)
+            .next_power_of_two()
+            .max(64);
+        GramFilter {
+            bits: vec![0; bits / 64],
+            mask: bits as u64 - 1,
+        }
+    }
+
+    /// Bit positions of `key`, by double hashing one FxHash value.
+    fn probes(&self, key: &str) -> impl Iterator<Item = u64> {
+        let h = FxBuildHasher.hash_one(key);
+        let (h1, h2) = (h, h.rotate_left(32) | 1);
+        let mask = self.mask;
+        (0..FILTER_HASHES).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) & mask)
+    }
+
+    pub fn insert(&mut self, key: &str) {
+        for bit in self.probes(key).collect::<Vec<_>>() {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    /// False only when `key` was certainly never inserted.
+    pub fn may_contain(&self, key: &str) -> bool {
+        self.bits.is_empty()
+            || self
+                .probes(key)
+                .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Share of bits set; the false-positive rate is about this to the
+    /// power of `FILTER_HASHES`.
+    pub fn fill_ratio(&self) -> f32 {
+        let set: u32 = self.bits.iter().map(|w| w.count_ones()).sum();
+        set as f32 / (self.bits.len() * 64).max(1) as f32
+    }
+}
+
+// ============================================
+// WATERMARK SIGNATURES
+// ============================================
+
+#[derive(Clone)]
+pub struct AxisWatermark {
+    pub lattice: Vec<f64>,
+    pub strength: f64,
+}
+
+#[derive(Clone)]
+pub struct MultiWatermark {
+    pub axes: Vec<AxisWatermark>,
+}
+
+pub fn generate_multi_watermark(
+    len: usize,
+    seeds: &[u64],
+    strength: f64,
+) -> MultiWatermark {
+    let axes = seeds
+        .iter()
+        .map(|&seed| {
+            let mut rng = ChaCha20Rng::seed_from_u64(seed);
+            let lattice = (0..len)
+                .map(|_| rng.gen_range(-1.0..1.0))
+                .collect();
+            AxisWatermark { lattice, strength }
+        })
+        .collect();
+
+    MultiWatermark { axes }
+}
+
+pub fn apply_multi_watermark(
+    signal: &mut [f64],
+    wm: &MultiWatermark,
+) {
+    for axis in &wm.axes {
+        for (v, w) in signal.iter_mut().zip(axis.lattice.iter()) {
+            *v += w * axis.strength;
+        }
+    }
+}
+
+pub fn verify_multi_watermark(
+    signal: &[f64],
+    wm: &MultiWatermark,
+) -> f64 {
+    wm.axes.iter().map(|axis| {
+        let mut corr = 0.0;
+        let mut norm = 0.0;
+
+        for (v, w) in signal.iter().zip(axis.lattice.iter()) {
+            corr += v * w;
+            norm += w * w;
+        }
+
+        corr / norm.sqrt()
+    }).sum::<f64>() / wm.axes.len() as f64
+}
+
+pub fn normalize_signal(signal: &mut [f64]) {
+    let norm = signal.iter().map(|v| v * v).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        for v in signal {
+            *v /= norm;
+        }
+    }
+}
+
+pub fn verify_with_mask(
+    signal: &[f64],
+    wm: &MultiWatermark,
+    mask: &[bool],
+) -> f64 {
+    wm.axes.iter().map(|axis| {
+        let mut corr = 0.0;
+        let mut norm = 0.0;
+
+        for ((&v, &w), &m) in signal.iter()
+            .zip(axis.lattice.iter())
+            .zip(mask.iter())
+        {
+            if m {
+                corr += v * w;
+                norm += w * w;
+            }
+        }
+
+        if norm > 0.0 { corr / norm.sqrt() } else { 0.0 }
+    }).sum::<f64>() / wm.axes.len() as f64
+}
+
+// ============================================
+// SIGNAL TRANSFORMATIONS AND ATTACKS
+// ============================================
+
+pub fn add_noise(signal: &mut [f64], amplitude: f64) {
+    let mut rng = rand::thread_rng();
+    for v in signal {
+        *v += rng.gen_range(-amplitude..amplitude);
+    }
+}
+
+pub fn scale_signal(signal: &mut [f64], factor: f64) {
+    for v in signal {
+        *v *= factor;
+    }
+}
+
+pub fn crop_signal(signal: &[f64], keep_ratio: f64) -> Vec<f64> {
+    let keep = (signal.len() as f64 * keep_ratio) as usize;
+    signal[..keep].to_vec()
+}
+
+pub fn permute_signal(signal: &mut [f64]) {
+    let mut rng = rand::thread_rng();
+    use rand::seq::SliceRandom;
+    signal.shuffle(&mut rng);
+}
+
+pub fn recovery_ratio(
+    original_score: f64,
+    modified_score: f64,
+) -> f64 {
+    if original_score.abs() < 1e-6 {
+        0.0
+    } else {
+        modified_score / original_score
+    }
+}
+
+// ============================================
+// PHASE-INVARIANT WATERMARK SCORING
+// ============================================
+
+pub fn phase_invariant_score(signal: &[f64], lattice: &[f64]) -> f64 {
+    signal.iter()
+        .zip(lattice)
+        .map(|(s, v)| (s * v).powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+// ============================================
+// ANCHOR-AWARE WATERMARKING
+// ============================================
+
+#[derive(Clone, Debug)]
+pub struct Anchor {
+    pub text: String,
+    pub bbox_width: f64,
+    pub position: usize,
+}
+
+pub fn anchor_lattice(anchor: &Anchor, len: usize) -> Vec<f64> {
+    let freq = anchor.bbox_width / 10.0;
+    (0..len)
+        .map(|i| ((i as f64) * freq).sin())
+        .collect()
+}
+
+pub fn combined_anchor_lattice(
+    anchors: &[Anchor],
+    len: usize,
+) -> Vec<f64> {
+    let mut lattice = vec![0.0; len];
+    for a in anchors {
+        let local = anchor_lattice(a, len);
+        for i in 0..len {
+            lattice[i] += local[i];
+        }
+    }
+    lattice
+}
+
+// ============================================
+// PDF BBOX EXTRACTION
+// ============================================
+
+pub fn bbox_signal(widths: &[f64]) -> Vec<f64> {
+    let norm = widths.iter().map(|w| w * w).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        widths.iter().map(|w| w / norm).collect()
+    } else {
+        widths.to_vec()
+    }
+}
+
+pub fn extract_bboxes_mock(widths: &[f64]) -> Vec<f64> {
+    widths.to_vec()
+}
+
+// ============================================
+// 3D MESH WATERMARKING
+// ============================================
+
+#[derive(Clone, Debug)]
+pub struct Mesh {
+    pub vertices: Vec<[f64; 3]>,
+    pub edges: Vec<(usize, usize)>,
+}
+
+pub fn edge_lengths(mesh: &Mesh) -> Vec<f64> {
+    mesh.edges.iter().map(|(a, b)| {
+        let va = mesh.vertices[*a];
+        let vb = mesh.vertices[*b];
+        ((va[0] - vb[0]).powi(2)
+            + (va[1] - vb[1]).powi(2)
+            + (va[2] - vb[2]).powi(2)).sqrt()
+    }).collect()
+}
+
+pub fn mesh_watermark(signal: &[f64], lattice: &[f64]) -> f64 {
+    phase_invariant_score(signal, lattice)
+}
+
+// ============================================
+// FFT-BASED BLOCK PROCESSING SYSTEM
+// ============================================
+
+pub fn split_into_blocks(signal: &[f64], block_size: usize) -> Vec<&[f64]> {
+    signal
+        .chunks(block_size)
+        .filter(|b| b.len() == block_size)
+        .collect()
+}
+
+pub fn fft_magnitude(block: &[f64]) -> Vec<f64> {
+    use rustfft::{FftPlanner, num_complex::Complex};
+    
+    let mut planner = FftPlanner::new();
+    let fft = planner.plan_fft_forward(block.len());
+
+    let mut buffer: Vec<Complex<f64>> =
+        block.iter().map(|&x| Complex::new(x, 0.0)).collect();
+
+    fft.process(&mut buffer);
+
+    buffer.iter().map(|c| c.norm()).collect()
+}
+
+pub fn block_energy(magnitudes: &[f64]) -> f64 {
+    magnitudes.iter().map(|v| v * v).sum::<f64>().sqrt()
+}
+
+// ============================================
+// MULTI-BASIS WATERMARKING SYSTEM
+// ============================================
+
+#[derive(Clone, Debug)]
+pub struct Basis {
+    pub lattice: Vec<f64>,
+    pub weight: f64,
+}
+
+pub fn project(signal: &[f64], lattice: &[f64]) -> Vec<f64> {
+    signal.iter()
+        .zip(lattice)
+        .map(|(s, l)| s * l)
+        .collect()
+}
+
+pub fn score_block_multi_basis(
+    block: &[f64],
+    bases: &[Basis],
+) -> f64 {
+    bases.iter().map(|b| {
+        let projected = project(block, &b.lattice);
+        let mag = fft_magnitude(&projected);
+        b.weight * block_energy(&mag)
+    }).sum()
+}
+
+pub fn invariant_signature_score(
+    signal: &[f64],
+    bases: &[Basis],
+    block_size: usize,
+) -> f64 {
+    let blocks = split_into_blocks(signal, block_size);
+
+    let scores: Vec<f64> = blocks.iter()
+        .map(|b| score_block_multi_basis(b, bases))
+        .collect();
+
+    // Median is robust to outliers and attacks
+    if scores.is_empty() {
+        return 0.0;
+    }
+    
+    let mut sorted = scores.clone();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    sorted[sorted.len() / 2]
+}
+
+// ============================================
+// ANCHORS AND QUANTIZATION
+// ============================================
+
+pub fn quantize(w: f32) -> i32 {
+    (w * 10.0).round() as i32 // 0.1 px precision
+}
+
+pub fn anchor_bonus(
+    text: &str,
+    width: f32,
+    anchors: &HashMap<i32, String>,
+) -> f32 {
+    let key = quantize(width);
+    if let Some(anchor) = anchors.get(&key) {
+        if anchor == text {
+            return 5.0; // srong bonus for anchor match
+        }
+    }
+    0.0
+}
+
+// ============================================
+// DOCUMENT STRUCTURES AND STABILIZATION
+// ============================================
+
+#[derive(Clone)]
+pub struct Line {
+    pub observed_width: f32,
+    pub beams: Vec<Beam>,
+    pub hints: LineHints,
+}
+
+#[derive(Clone)]
+pub struct Document {
+    pub lines: Vec<Line>,
+}
+
+pub fn stabilize_document(doc: &mut Document) {
+    stabilize_document_with_model(doc, None, 0.0);
+}
+
+/// Same as `stabilize_document`, but when a language model is given every
+/// beam is additionally rescored by `lm_weight * ngram_log_prob`, so that
+/// anchors cannot promote candidates that do not look like real text.
+pub fn stabilize_document_with_model(
+    doc: &mut Document,
+    model: Option<&NGramModel>,
+    lm_weight: f32,
+) {
+    if let Some(model) = model {
+        apply_lm_rescoring(doc, model, lm_weight);
+    }
+    anchor_pass(doc);
+}
+
+fn apply_lm_rescoring(doc: &mut Document, model: &NGramModel, lm_weight: f32) {
+    // language model goes first so anchors are picked from plausible text
+    for line in &mut doc.lines {
+        for beam in &mut line.beams {
+            beam.score += lm_weight * ngram_log_prob(&beam.text, model);
+        }
+        line.beams
+            .sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+    }
+}
+
+/// One round of anchor collection and rescoring. Returns the anchor table
+/// that was applied so callers can record which anchor touched which line.
+fn anchor_pass(doc: &mut Document) -> HashMap<i32, String> {
+    let mut anchors = HashMap::new();
+
+    // collect best anchors from each line
+    for line in &doc.lines {
+        if let Some(best) = line.beams.first() {
+            anchors.insert(quantize(line.observed_width), best.text.clone());
+        }
+    }
+
+    eprintln!(" Found {} anchors for multi-line matching", anchors.len());
+
+    // rescore beams based on anchors
+    for line in &mut doc.lines {
+        for beam in &mut line.beams {
+            beam.score += anchor_bonus(
+                &beam.text,
+                line.observed_width,
+                &anchors,
+            );
+        }
+
+        line.beams
+            .sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+    }
+
+    anchors
+}
+
+// ============================================
+// STABILIZATION TRACE EXPORT
+// ============================================
+
+#[derive(Clone, Debug, Serialize)]
+pub struct BeamSnapshot {
+    pub rank: usize,
+    pub text: String,
+    pub score: f32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LineSnapshot {
+    pub line: usize,
+    pub observed_width: f32,
+    pub anchor: Option<String>, // anchor applied to this line in this iteration
+    pub beams: Vec<BeamSnapshot>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct IterationSnapshot {
+    pub iteration: usize,
+    pub anchor_count: usize,
+    pub lines: Vec<LineSnapshot>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct StabilizationTrace {
+    pub iterations: Vec<IterationSnapshot>,
+}
+
+fn snapshot_document(
+    doc: &Document,
+    iteration: usize,
+    anchors: &HashMap<i32, String>,
+) -> IterationSnapshot {
+    let lines = doc
+        .lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| LineSnapshot {
+            line: i,
+            observed_width: line.observed_width,
+            anchor: anchors.get(&quantize(line.observed_width)).cloned(),
+            beams: line
+                .beams
+                .iter()
+                .enumerate()
+                .map(|(rank, b)| BeamSnapshot {
+                    rank,
+                    text: b.text.clone(),
+                    score: b.score,
+                })
+                .collect(),
+        })
+        .collect();
+
+    IterationSnapshot {
+        iteration,
+        anchor_count: anchors.len(),
+        lines,
+    }
+}
+
+/// Runs `iterations` anchor passes and records the beam ranking of every
+/// line after each one. Iteration 0 is the state before any anchor pass.
+pub fn stabilize_document_traced(
+    doc: &mut Document,
+    model: Option<&NGramModel>,
+    lm_weight: f32,
+    iterations: usize,
+) -> StabilizationTrace {
+    if let Some(model) = model {
+        apply_lm_rescoring(doc, model, lm_weight);
+    }
+
+    let mut trace = StabilizationTrace::default();
+    trace
+        .iterations
+        .push(snapshot_document(doc, 0, &HashMap::new()));
+
+    for it in 1..=iterations {
+        let anchors = anchor_pass(doc);
+        trace.iterations.push(snapshot_document(doc, it, &anchors));
+    }
+
+    trace
+}
+
+impl StabilizationTrace {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("trace serialization failed")
+    }
+
+    pub fn write_json(&self, path: &str) -> std::io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+
+    /// Lines whose top candidate changed more than once across iterations.
+    pub fn oscillating_lines(&self) -> Vec<usize> {
+        let line_count = self.iterations.first().map_or(0, |it| it.lines.len());
+
+        (0..line_count)
+            .filter(|&i| {
+                let leaders: Vec<Option<&str>> = self
+                    .iterations
+                    .iter()
+                    .map(|it| it.lines[i].beams.first().map(|b| b.text.as_str()))
+                    .collect();
+                leaders.windows(2).filter(|w| w[0] != w[1]).count() > 1
+            })
+            .collect()
+    }
+
+    /// Lines whose applied anchor text differs from the line's own leader
+    /// before stabilization, i.e. anchors imported from another line.
+    pub fn foreign_anchor_lines(&self) -> Vec<usize> {
+        let Some(first) = self.iterations.first() else {
+            return vec![];
+        };
+
+        (0..first.lines.len())
+            .filter(|&i| {
+                let own = first.lines[i].beams.first().map(|b| b.text.as_str());
+                self.iterations
+                    .iter()
+                    .skip(1)
+                    .any(|it| matches!(&it.lines[i].anchor, Some(a) if Some(a.as_str()) != own))
+            })
+            .collect()
+    }
+}
+
+// ============================================
+// PDF STRUCTURES AND INFERENCE
+// ============================================
+
+#[derive(Clone, Debug)]
+#[allow(dead_code)]
+pub struct BBox {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+#[derive(Clone)]
+#[allow(dead_code)]
+pub struct PdfLine {
+    pub bbox: BBox,
+    pub width: f32,
+}
+
+pub fn create_pdf_lines(widths: &[f32]) -> Vec<PdfLine> {
+    widths
+        .iter()
+        .enumerate()
+        .map(|(i, &w)| PdfLine {
+            bbox: BBox {
+                x: 0.0,
+                y: (i as f32) * 20.0,
+                w,
+                h: 18.0,
+            },
+            width: w,
+        })
+        .collect()
+}
+
+// ============================================
+// FONT LOADING, GLYPH MEASUREMENT, AND BEAM SEARCH
+// ============================================
+
+/// Font file extensions; an argument with one of these, or with a path
+/// separator, names a file rather than a family.
+const FONT_EXTENSIONS: &[&str] = &["ttf", "otf", "ttc", "otc"];
+
+fn is_font_path(arg: &str) -> bool {
+    arg.contains(['/', '\\'])
+        || Path::new(arg)
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| FONT_EXTENSIONS.contains(&e.to_ascii_lowercase().as_str()))
+}
+
+/// Loads `arg` as a font file when it names one, otherwise as a family:
+/// an embedded fixture, then the installed family, then the default chain
+/// of common system fonts. Only a family falls back; a file path that is
+/// not there is a `NotFound` error, so a typo never measures with some
+/// other face.
+pub fn load_font(arg: &str) -> io::Result<Face<'static>> {
+    eprintln!(" Loading font: {}", arg);
+
+    if is_font_path(arg) {
+        if !Path::new(arg).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("font file not found: {}", arg),
+            ));
+        }
+        let data = fs::read(arg)?;
+        return Face::parse(Box::leak(data.into_boxed_slice()), 0).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("cannot parse font {}: {}", arg, e),
+            )
+        });
+    }
+
+    if let Some(face) = fonts::fixture_face(arg) {
+        eprintln!(" Using embedded fixture font: {}", arg);
+        return Ok(face);
+    }
+    let library = fonts::FontLibrary::system();
+    let query = fonts::FontQuery {
+        family: arg.to_string(),
+        weight: 400,
+        italic: false,
+    };
+    if let Some(face) = library.resolve(&query) {
+        eprintln!(
+            " Using system font via discovery ({} faces indexed)",
+            library.len()
+        );
+        return Ok(face);
+    }
+    if let Some(face) = library.resolve_with_fallback(&[], 400, false) {
+        eprintln!(
+            " warning: font family '{}' is not installed; using a fallback",
+            arg
+        );
+        return Ok(face);
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("font not found: {}", arg),
+    ))
+}
+
+/// Font used when none is given: the embedded DejaVu Sans fixture when the
+/// `fixture-font` feature is on, otherwise `fonts/DejaVuSans.ttf`.
+pub fn default_font() -> io::Result<Face<'static>> {
+    match fonts::fixture_face("DejaVu Sans") {
+        Some(face) => {
+            eprintln!(" Using embedded fixture font: DejaVu Sans");
+            Ok(face)
+        }
+        None => load_font("fonts/DejaVuSans.ttf"),
+    }
+}
+
+/// Width of `text` in `face`. Characters the face does not map take their
+/// width-map entry instead (e.g. a predicted advance), then the
+/// conventional advance of full- and half-width characters, zero without
+/// either.
+pub fn measure_text_kerning(
+    text: &str,
+    face: &Face,
+    glyphs: &HashMap<char, f32>,
+    px_size: f32,
+) -> f32 {
+    let units_per_em = face.units_per_em() as f32;
+    let scale = px_size / units_per_em;
+
+    let mut total = 0.0;
+
+    for ch in text.chars() {
+        match face.glyph_index(ch) {
+            Some(glyph_id) => {
+                if let Some(advance) = face.glyph_hor_advance(glyph_id) {
+                    total += advance as f32 * scale;
+                }
+            }
+            None => {
+                total += glyphs
+                    .get(&ch)
+                    .copied()
+                    .or_else(|| cjk::conventional_advance(ch, px_size))
+                    .unwrap_or(0.0)
+            }
+        }
+    }
+
+    total
+}
+
+pub fn build_glyph_widths(face: &Face, px_size: f32) -> HashMap<char, f32> {
+    let units_per_em = face.units_per_em() as f32;
+    let scale = px_size / units_per_em;
+
+    let mut map = HashMap::new();
+
+    let ranges = [
+        (' '..='~'),              // ASCII
+        ('А'..='Я'),              // cyrillic uppercase
+        ('а'..='я'),
+        ('Ё'..='Ё'),
+        ('ё'..='ё'),
+        ('\u{3000}'..='\u{30FF}'), // CJK punctuation, hiragana, katakana
+        ('\u{4E00}'..='\u{9FFF}'), // CJK unified ideographs
+        ('\u{AC00}'..='\u{D7A3}'), // hangul syllables
+        ('\u{FF01}'..='\u{FF9F}'), // full-width ASCII, half-width katakana
+    ];
+
+    for range in ranges {
+        for ch in range {
+            if let Some(glyph_id) = face.glyph_index(ch) {
+                if let Some(advance) = face.glyph_hor_advance(glyph_id) {
+                    map.insert(ch, advance as f32 * scale);
+                }
+            }
+        }
+    }
+
+    map
+}
+
+pub fn save_glyph_widths(glyphs: &HashMap<char, f32>, path: &str) -> io::Result<()> {
+    let json = serde_json::to_string(glyphs).map_err(io::Error::other)?;
+    fs::write(path, json)
+}
+
+pub fn load_glyph_widths(path: &str) -> io::Result<HashMap<char, f32>> {
+    let data = fs::read_to_string(path)?;
+    serde_json::from_str(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Loads the width table from `cache_path` when present, otherwise measures
+/// the font and writes the cache for the next run.
+pub fn cached_glyph_widths(face: &Face, px_size: f32, cache_path: &str) -> HashMap<char, f32> {
+    if let Ok(glyphs) = load_glyph_widths(cache_path) {
+        eprintln!(" Glyph widths loaded from cache: {}", cache_path);
+        return glyphs;
+    }
+
+    let glyphs = build_glyph_widths(face, px_size);
+    if let Err(e) = save_glyph_widths(&glyphs, cache_path) {
+        eprintln!(" Could not write glyph cache {}: {}", cache_path, e);
+    }
+    glyphs
+}
+
+pub fn find_candidates(
+    target_width: f32,
+    glyphs: &HashMap<char, f32>,
+    dictionary: &[&str],
+    tolerance: f32,
+) -> Vec<(String, f32)> {
+    let mut out = vec![];
+
+    for &word in dictionary {
+        let w = glyph_sum(word, glyphs);
+        let delta = (w - target_width).abs();
+
+        if delta <= tolerance {
+            out.push((word.to_string(), delta));
+        }
+    }
+
+    out.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    out
+}
+
+/// Parallel variant of `find_candidates` for large wordlists. Results are
+/// ordered closest width first.
+pub fn find_candidates_par(
+    target_width: f32,
+    glyphs: &HashMap<char, f32>,
+    dictionary: &[&str],
+    tolerance: f32,
+) -> Vec<(String, f32)> {
+    let mut out: Vec<(String, f32)> = dictionary
+        .par_iter()
+        .filter_map(|&word| {
+            let delta = (glyph_sum(word, glyphs) - target_width).abs();
+            (delta <= tolerance).then(|| (word.to_string(), delta))
+        })
+        .collect();
+
+    out.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+    out
+}
+
+// ============================================
+// WORD LISTS
+// ============================================
+
+/// Borrowed word list for the dictionary searches. Derefs to `[&str]`, so it
+/// can be passed wherever a `&[&str]` dictionary is expected.
+#[derive(Clone, Debug, Default)]
+pub struct Dictionary<'a> {
+    words: Vec<&'a str>,
+}
+
+impl<'a> Dictionary<'a> {
+    pub fn words(&self) -> &[&'a str] {
+        &self.words
+    }
+}
+
+impl<'a> From<&'a str> for Dictionary<'a> {
+    /// One word per whitespace-separated token, e.g. a wordlist file's
+    /// contents.
+    fn from(text: &'a str) -> Self {
+        text.split_whitespace().collect()
+    }
+}
+
+impl<'a> From<&[&'a str]> for Dictionary<'a> {
+    fn from(words: &[&'a str]) -> Self {
+        Dictionary {
+            words: words.to_vec(),
+        }
+    }
+}
+
+impl<'a> From<Vec<&'a str>> for Dictionary<'a> {
+    fn from(words: Vec<&'a str>) -> Self {
+        Dictionary { words }
+    }
+}
+
+impl<'a> FromIterator<&'a str> for Dictionary<'a> {
+    fn from_iter<I: IntoIterator<Item = &'a str>>(iter: I) -> Self {
+        Dictionary {
+            words: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<'a> std::ops::Deref for Dictionary<'a> {
+    type Target = [&'a str];
+
+    fn deref(&self) -> &[&'a str] {
+        &self.words
+    }
+}
+
+// ============================================
+// PUNCTUATION VARIANTS
+// ============================================
+
+/// Punctuation that may be attached to a word at the start or end of a
+/// redaction. Each prefix and suffix is tried alone and in combination.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PunctuationSet {
+    pub prefixes: Vec<String>,
+    pub suffixes: Vec<String>,
+}
+
+impl PunctuationSet {
+    pub fn new(prefixes: &[&str], suffixes: &[&str]) -> Self {
+        PunctuationSet {
+            prefixes: prefixes.iter().map(|p| p.to_string()).collect(),
+            suffixes: suffixes.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    /// Sentence and clause punctuation, brackets and quotes.
+    pub fn common() -> Self {
+        Self::new(
+            &["(", "\"", "'"],
+            &[".", ",", ":", ";", "!", "?", ")", "\"", "'"],
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty() && self.suffixes.is_empty()
+    }
+
+    /// Every `(prefix, suffix)` pair with at least one side non-empty.
+    pub fn affixes(&self) -> Vec<(&str, &str)> {
+        let prefixes = std::iter::once("").chain(self.prefixes.iter().map(String::as_str));
+        prefixes
+            .flat_map(|p| {
+                std::iter::once("")
+                    .chain(self.suffixes.iter().map(String::as_str))
+                    .map(move |s| (p, s))
+            })
+            .filter(|(p, s)| !p.is_empty() || !s.is_empty())
+            .collect()
+    }
+
+    /// The word itself followed by all punctuated forms.
+    pub fn variants(&self, word: &str) -> Vec<String> {
+        std::iter::once(word.to_string())
+            .chain(
+                self.affixes()
+                    .into_iter()
+                    .map(|(p, s)| format!("{}{}{}", p, word, s)),
+            )
+            .collect()
+    }
+}
+
+/// `find_candidates_par` over the dictionary and all punctuated variants of
+/// its words. Ordered closest width first.
+pub fn find_candidates_punctuated(
+    target_width: f32,
+    glyphs: &HashMap<char, f32>,
+    dictionary: &[&str],
+    tolerance: f32,
+    punctuation: &PunctuationSet,
+) -> Vec<(String, f32)> {
+    let affixes: Vec<(&str, &str, f32)> = std::iter::once(("", "", 0.0))
+        .chain(
+            punctuation
+                .affixes()
+                .into_iter()
+                .map(|(p, s)| (p, s, glyph_sum(p, glyphs) + glyph_sum(s, glyphs))),
+        )
+        .collect();
+
+    let mut out: Vec<(String, f32)> = dictionary
+        .par_iter()
+        .flat_map_iter(|&word| {
+            let base = glyph_sum(word, glyphs);
+            affixes.iter().filter_map(move |&(p, s, extra)| {
+                let delta = (base + extra - target_width).abs();
+                (delta <= tolerance).then(|| (format!("{}{}{}", p, word, s), delta))
+            })
+        })
+        .collect();
+
+    out.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+    out
+}
+
+/// Wraps beams found for the width left after `prefix` and `suffix` in that
+/// punctuation and rescores them against the full target width. The n-gram
+/// term is taken on the bare word, so punctuation the corpus rarely shows
+/// does not sink an otherwise good candidate.
+#[allow(clippy::too_many_arguments)]
+pub fn punctuate_beams(
+    beams: Vec<Beam>,
+    prefix: &str,
+    suffix: &str,
+    face: &Face,
+    glyphs: &HashMap<char, f32>,
+    px_size: f32,
+    target_width: f32,
+    weights: &ScoreWeights,
+    model: Option<&NGramModel>,
+) -> Vec<Beam> {
+    let extra = measure_text_kerning(prefix, face, glyphs, px_size)
+        + measure_text_kerning(suffix, face, glyphs, px_size);
+
+    beams
+        .into_iter()
+        .map(|b| {
+            let width = b.width + extra;
+            let score = combined_score(&b.text, width, target_width, weights, model);
+            Beam {
+                text: format!("{}{}{}", prefix, b.text, suffix),
+                width,
+                score,
+            }
+        })
+        .collect()
+}
+
+// ============================================
+// PREFIX-WIDTH TRIE
+// ============================================
+
+#[derive(Clone, Debug)]
+struct TrieNode {
+    children: Vec<(char, usize)>,
+    width: f32,          // cumulative width of the prefix
+    min_complete: f32,   // narrowest word in this subtree
+    max_complete: f32,   // widest word in this subtree
+    word: Option<usize>, // index into `WidthTrie::words`
+}
+
+impl TrieNode {
+    fn new(width: f32) -> Self {
+        TrieNode {
+            children: vec![],
+            width,
+            min_complete: f32::INFINITY,
+            max_complete: f32::NEG_INFINITY,
+            word: None,
+        }
+    }
+}
+
+/// Dictionary trie where every node stores its prefix width and the width
+/// range of the words below it, so whole subtrees are skipped as soon as no
+/// completion can land within tolerance of the target.
+#[derive(Clone, Debug)]
+pub struct WidthTrie {
+    nodes: Vec<TrieNode>,
+    words: Vec<String>,
+}
+
+impl WidthTrie {
+    pub fn build(dictionary: &[&str], glyphs: &HashMap<char, f32>) -> Self {
+        let mut trie = WidthTrie {
+            nodes: vec![TrieNode::new(0.0)],
+            words: Vec::with_capacity(dictionary.len()),
+        };
+
+        for &word in dictionary {
+            let mut node = 0;
+            let mut path = vec![0];
+
+            for ch in word.chars() {
+                let existing = trie.nodes[node]
+                    .children
+                    .iter()
+                    .find(|&&(c, _)| c == ch)
+                    .map(|&(_, id)| id);
+
+                node = match existing {
+                    Some(id) => id,
+                    None => {
+                        let w = trie.nodes[node].width + glyphs.get(&ch).copied().unwrap_or(0.0);
+                        trie.nodes.push(TrieNode::new(w));
+                        let id = trie.nodes.len() - 1;
+                        trie.nodes[node].children.push((ch, id));
+                        id
+                    }
+                };
+                path.push(node);
+            }
+
+            if trie.nodes[node].word.is_none() {
+                trie.nodes[node].word = Some(trie.words.len());
+                trie.words.push(word.to_string());
+            }
+
+            let total = trie.nodes[node].width;
+            for id in path {
+                let n = &mut trie.nodes[id];
+                n.min_complete = n.min_complete.min(total);
+                n.max_complete = n.max_complete.max(total);
+            }
+        }
+
+        trie
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Words within `tolerance` of `target_width`, closest first. Top-level
+    /// branches are searched in parallel.
+    pub fn search(&self, target_width: f32, tolerance: f32) -> Vec<(String, f32)> {
+        let mut out: Vec<(String, f32)> = self.nodes[0]
+            .children
+            .par_iter()
+            .flat_map_iter(|&(_, id)| {
+                let mut found = vec![];
+                self.collect(id, target_width, tolerance, &mut found);
+                found
+            })
+            .collect();
+
+        if let Some(w) = self.nodes[0].word {
+            if target_width.abs() <= tolerance {
+                out.push((self.words[w].clone(), target_width.abs()));
+            }
+        }
+
+        out.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        out
+    }
+
+    fn collect(&self, id: usize, target: f32, tolerance: f32, out: &mut Vec<(String, f32)>) {
+        let node = &self.nodes[id];
+
+        if node.min_complete > target + tolerance || node.max_complete < target - tolerance {
+            return;
+        }
+
+        if let Some(w) = node.word {
+            let delta = (node.width - target).abs();
+            if delta <= tolerance {
+                out.push((self.words[w].clone(), delta));
+            }
+        }
+
+        for &(_, child) in &node.children {
+            self.collect(child, target, tolerance, out);
+        }
+    }
+}
+
+// ============================================
+// WORD LATTICE PHRASE RECONSTRUCTION
+// ============================================
+
+fn glyph_sum(text: &str, glyphs: &HashMap<char, f32>) -> f32 {
+    text.chars()
+        .map(|c| glyphs.get(&c).copied().unwrap_or(0.0))
+        .sum()
+}
+
+/// Segments a line into a sequence of dictionary words joined by single
+/// spaces. Partial sequences are grouped by cumulative width (0.1 px buckets,
+/// same precision as `quantize`) and at most `top_k` of them are kept per
+/// bucket, so the lattice stays bounded for large wordlists. Returns up to
+/// `top_k` phrases within `tolerance`, closest width first.
+pub fn find_phrase_candidates(
+    target_width: f32,
+    glyphs: &HashMap<char, f32>,
+    dictionary: &[&str],
+    tolerance: f32,
+    max_words: usize,
+    top_k: usize,
+) -> Vec<(String, f32)> {
+    phrase_lattice(
+        target_width,
+        glyphs,
+        &vec![dictionary; max_words],
+        1,
+        tolerance,
+        top_k,
+    )
+}
+
+/// Dictionaries for each word of a phrase, in order: e.g. titles, then
+/// surnames. A templated phrase fills every slot, so the slot count is also
+/// its word count.
+#[derive(Clone, Debug, Default)]
+pub struct PhraseTemplate<'a> {
+    pub slots: Vec<&'a [&'a str]>,
+}
+
+impl<'a> PhraseTemplate<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn slot(mut self, words: &'a [&'a str]) -> Self {
+        self.slots.push(words);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+}
+
+/// `find_phrase_candidates` with the words of each position drawn from the
+/// template's slot for it.
+pub fn find_phrase_candidates_templated(
+    target_width: f32,
+    glyphs: &HashMap<char, f32>,
+    template: &PhraseTemplate,
+    tolerance: f32,
+    top_k: usize,
+) -> Vec<(String, f32)> {
+    phrase_lattice(
+        target_width,
+        glyphs,
+        &template.slots,
+        template.len(),
+        tolerance,
+        top_k,
+    )
+}
+
+/// The word lattice behind the phrase searches: word `i` comes from
+/// `slots[i]`, and phrases of `min_words` to `slots.len()` words are kept.
+fn phrase_lattice(
+    target_width: f32,
+    glyphs: &HashMap<char, f32>,
+    slots: &[&[&str]],
+    min_words: usize,
+    tolerance: f32,
+    top_k: usize,
+) -> Vec<(String, f32)> {
+    let space = glyphs.get(&' ').copied().unwrap_or(0.0);
+    let limit = target_width + tolerance;
+
+    let slots: Vec<Vec<(&str, f32)>> = slots
+        .iter()
+        .map(|dictionary| {
+            dictionary
+                .iter()
+                .map(|&w| (w, glyph_sum(w, glyphs)))
+                .filter(|&(_, w)| w > 0.0 && w <= limit)
+                .collect()
+        })
+        .collect();
+    let Some(first) = slots.first() else {
+        return vec![];
+    };
+
+    // frontier holds sequences of `depth` words: bucket -> [(width, word ids)],
+    // ids indexing the slot of their position
+    let mut frontier: HashMap<i32, Vec<(f32, Vec<usize>)>> = HashMap::new();
+    let mut finished: Vec<(f32, Vec<usize>)> = vec![];
+
+    for (id, &(_, w)) in first.iter().enumerate() {
+        push_lattice_state(&mut frontier, w, vec![id], top_k);
+    }
+
+    for depth in 1..=slots.len() {
+        if depth >= min_words {
+            for states in frontier.values() {
+                for (w, seq) in states {
+                    if (w - target_width).abs() <= tolerance {
+                        finished.push((*w, seq.clone()));
+                    }
+                }
+            }
+        }
+
+        if depth == slots.len() {
+            break;
+        }
+
+        let mut next = HashMap::new();
+        for states in frontier.values() {
+            for (w, seq) in states {
+                for (id, &(_, ww)) in slots[depth].iter().enumerate() {
+                    let nw = w + space + ww;
+                    if nw > limit {
+                        continue;
+                    }
+                    let mut nseq = seq.clone();
+                    nseq.push(id);
+                    push_lattice_state(&mut next, nw, nseq, top_k);
+                }
+            }
+        }
+        frontier = next;
+    }
+
+    let mut out: Vec<(String, f32)> = finished
+        .into_iter()
+        .map(|(w, seq)| {
+            let text = seq
+                .iter()
+                .enumerate()
+                .map(|(slot, &id)| slots[slot][id].0)
+                .collect::<Vec<_>>()
+                .join(" ");
+            (text, (w - target_width).abs())
+        })
+        .collect();
+
+    out.sort_by(|a, b| {
+        a.1.partial_cmp(&b.1)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.0.len().cmp(&b.0.len()))
+    });
+    out.dedup_by(|a, b| a.0 == b.0);
+    out.truncate(top_k);
+    out
+}
+
+fn push_lattice_state(
+    lattice: &mut HashMap<i32, Vec<(f32, Vec<usize>)>>,
+    width: f32,
+    seq: Vec<usize>,
+    cap: usize,
+) {
+    let bucket = lattice.entry(quantize(width)).or_default();
+    if bucket.len() < cap {
+        bucket.push((width, seq));
+    }
+}
+
+// ============================================
+// WORD GAP CONSTRAINTS
+// ============================================
+
+/// A visible gap between two word boxes of a line, in px from the line's
+/// left edge: the previous word ends at `start`, the next begins at `end`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WordGap {
+    pub start: f32,
+    pub end: f32,
+}
+
+/// Line width and inter-word gaps from per-word boxes (OCR words, ALTO
+/// `String` elements or one redaction bar per word).
+pub fn gaps_from_word_boxes(boxes: &[BBox]) -> (f32, Vec<WordGap>) {
+    let mut sorted: Vec<&BBox> = boxes.iter().collect();
+    sorted.sort_by(|a, b| a.x.partial_cmp(&b.x).unwrap_or(std::cmp::Ordering::Equal));
+
+    let Some(first) = sorted.first() else {
+        return (0.0, vec![]);
+    };
+    let left = first.x;
+    let right = sorted.iter().map(|b| b.x + b.w).fold(left, f32::max);
+
+    let gaps = sorted
+        .windows(2)
+        .map(|pair| WordGap {
+            start: pair[0].x + pair[0].w - left,
+            end: pair[1].x - left,
+        })
+        .collect();
+
+    (right - left, gaps)
+}
+
+/// Largest distance between where the phrase's words end and where the
+/// gaps say they end, with each word placed at the end of the preceding
+/// gap rather than after a font space (so stretched spaces are fine).
+/// Returns `(placement error, measured line width)`, or None when the word
+/// count does not match the gaps.
+pub fn gap_placement(
+    phrase: &str,
+    glyphs: &HashMap<char, f32>,
+    gaps: &[WordGap],
+) -> Option<(f32, f32)> {
+    let words: Vec<&str> = phrase.split(' ').collect();
+    if words.len() != gaps.len() + 1 {
+        return None;
+    }
+
+    let mut start = 0.0;
+    let mut error: f32 = 0.0;
+    for (word, gap) in words.iter().zip(gaps) {
+        let end = start + glyph_sum(word, glyphs);
+        error = error.max((end - gap.start).abs());
+        start = gap.end;
+    }
+
+    let width = start + glyph_sum(words[words.len() - 1], glyphs);
+    Some((error, width))
+}
+
+/// True when every space of the phrase falls into the matching gap and the
+/// full width matches, each within `tolerance`.
+pub fn phrase_matches_gaps(
+    phrase: &str,
+    target_width: f32,
+    glyphs: &HashMap<char, f32>,
+    gaps: &[WordGap],
+    tolerance: f32,
+) -> bool {
+    gap_placement(phrase, glyphs, gaps).is_some_and(|(error, width)| {
+        error <= tolerance && (width - target_width).abs() <= tolerance
+    })
+}
+
+/// Phrase search when the gaps are known: every word has its own measured
+/// span, so each span is searched on its own and the phrases are combined,
+/// keeping `top_k` partial phrases by summed width error. Returns phrases
+/// with their summed error, smallest first.
+pub fn find_phrase_candidates_gapped(
+    target_width: f32,
+    glyphs: &HashMap<char, f32>,
+    dictionary: &[&str],
+    tolerance: f32,
+    gaps: &[WordGap],
+    top_k: usize,
+) -> Vec<(String, f32)> {
+    gapped_phrases(
+        target_width,
+        glyphs,
+        &vec![dictionary; gaps.len() + 1],
+        tolerance,
+        gaps,
+        top_k,
+    )
+}
+
+/// `find_phrase_candidates_gapped` with span `i` searched in the template's
+/// slot `i`. Nothing is returned when the gaps split the line into a
+/// different number of words than the template has slots.
+pub fn find_phrase_candidates_gapped_templated(
+    target_width: f32,
+    glyphs: &HashMap<char, f32>,
+    template: &PhraseTemplate,
+    tolerance: f32,
+    gaps: &[WordGap],
+    top_k: usize,
+) -> Vec<(String, f32)> {
+    if template.len() != gaps.len() + 1 {
+        return vec![];
+    }
+    gapped_phrases(
+        target_width,
+        glyphs,
+        &template.slots,
+        tolerance,
+        gaps,
+        top_k,
+    )
+}
+
+fn gapped_phrases(
+    target_width: f32,
+    glyphs: &HashMap<char, f32>,
+    slots: &[&[&str]],
+    tolerance: f32,
+    gaps: &[WordGap],
+    top_k: usize,
+) -> Vec<(String, f32)> {
+    let mut spans = vec![];
+    let mut start = 0.0;
+    for gap in gaps {
+        spans.push(gap.start - start);
+        start = gap.end;
+    }
+    spans.push(target_width - start);
+
+    let mut phrases: Vec<(String, f32)> = vec![(String::new(), 0.0)];
+    for (span, dictionary) in spans.into_iter().zip(slots) {
+        let words = find_candidates_par(span, glyphs, dictionary, tolerance);
+        let mut next: Vec<(String, f32)> = phrases
+            .iter()
+            .flat_map(|(phrase, err)| {
+                words.iter().map(move |(word, delta)| {
+                    let text = if phrase.is_empty() {
+                        word.clone()
+                    } else {
+                        format!("{} {}", phrase, word)
+                    };
+                    (text, err + delta)
+                })
+            })
+            .collect();
+
+        next.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        next.truncate(top_k);
+        phrases = next;
+        if phrases.is_empty() {
+            break;
+        }
+    }
+
+    phrases
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[allow(dead_code)]
+pub struct ScoreWeights {
+    pub width: f32,
+    pub word_len: f32,
+    pub spaces: f32,
+    pub ngram: f32, // weight of the character n-gram log-likelihood
+}
+
+#[derive(Clone)]
+pub struct Beam {
+    pub text: String,
+    pub width: f32,
+    pub score: f32,
+}
+
+#[allow(dead_code)]
+pub fn score_text(
+    text: &str,
+    measured_width: f32,
+    target_width: f32,
+    weights: &ScoreWeights,
+) -> f32 {
+    let width_error = (measured_width - target_width).abs();
+    let len = text.chars().count() as f32;
+    let spaces = text.matches(' ').count() as f32;
+
+    -weights.width * width_error
+        - weights.word_len * len
+        + weights.spaces * spaces
+}
+
+/// Width/length/space score plus the weighted n-gram log-likelihood, so beams
+/// that match the width but read like gibberish fall behind real words.
+pub fn combined_score(
+    text: &str,
+    measured_width: f32,
+    target_width: f32,
+    weights: &ScoreWeights,
+    model: Option<&NGramModel>,
+) -> f32 {
+    let base = score_text(text, measured_width, target_width, weights);
+    match model {
+        Some(m) => base + weights.ngram * ngram_log_prob(text, m),
+        None => base,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn beam_search(
+    face: &Face,
+    _glyphs: &HashMap<char, f32>,
+    px_size: f32,
+    target_width: f32,
+    alphabet: &[char],
+    weights: &ScoreWeights,
+    model: Option<&NGramModel>,
+    beam_width: usize,
+    max_len: usize,
+) -> Vec<Beam> {
+    let root = Beam {
+        text: String::new(),
+        width: 0.0,
+        score: 0.0,
+    };
+
+    beam_search_from(
+        face,
+        px_size,
+        vec![root],
+        target_width,
+        alphabet,
+        weights,
+        model,
+        beam_width,
+        max_len,
+        &SearchStats::default(),
+    )
+}
+
+/// How far past the target width (px) an extension may run before beam
+/// search drops it without scoring.
+pub const BEAM_OVERSHOOT: f32 = 20.0;
+
+/// Narrowest and widest advance of `advances`, ignoring zero-width ones.
+pub fn advance_range(advances: &[(char, f32)]) -> (f32, f32) {
+    let (narrowest, widest) = advances
+        .iter()
+        .map(|&(_, w)| w)
+        .filter(|&w| w > 0.0)
+        .fold((f32::INFINITY, 0.0f32), |(n, w), a| (n.min(a), w.max(a)));
+    (narrowest.min(widest), widest)
+}
+
+/// The final width closest to `target_width` that a beam `width` wide can
+/// still reach with `remaining` more characters of `range`. A partial beam
+/// is scored at this width, so it is charged for how far the target is out
+/// of its reach rather than for falling short of it, and prefixes are not
+/// ranked by how wide they already are.
+pub fn reachable_width(width: f32, remaining: usize, range: (f32, f32), target_width: f32) -> f32 {
+    let remaining = remaining as f32;
+    target_width.clamp(width + remaining * range.0, width + remaining * range.1)
+}
+
+/// Beam search starting from the given partial hypotheses instead of the
+/// empty string. Each seed must carry its measured width. Partial beams
+/// are scored at their `reachable_width`.
+#[allow(clippy::too_many_arguments)]
+pub fn beam_search_from(
+    face: &Face,
+    px_size: f32,
+    seeds: Vec<Beam>,
+    target_width: f32,
+    alphabet: &[char],
+    weights: &ScoreWeights,
+    model: Option<&NGramModel>,
+    beam_width: usize,
+    steps: usize,
+    stats: &SearchStats,
+) -> Vec<Beam> {
+    // widths are cached on each beam, so an extension only adds one advance
+    let advances = alphabet_advances(face, px_size, alphabet);
+    let range = advance_range(&advances);
+
+    let mut beams = seeds;
+
+    for step in 0..steps {
+        stats.add_expanded(beams.len());
+        let remaining = steps - step - 1;
+
+        // each worker keeps its own bounded heap and a scratch string, so a
+        // rejected extension never allocates; the heaps are merged at the end
+        let next = beams
+            .par_iter()
+            .fold(
+                || (BeamHeap::new(beam_width), String::new()),
+                |(mut heap, mut scratch), beam| {
+                    let mut evaluated = 0;
+                    for &(ch, adv) in &advances {
+                        let new_width = beam.width + adv;
+
+                        if new_width > target_width + BEAM_OVERSHOOT {
+                            continue;
+                        }
+
+                        scratch.clear();
+                        scratch.push_str(&beam.text);
+                        scratch.push(ch);
+
+                        let reachable = reachable_width(new_width, remaining, range, target_width);
+                        let score =
+                            combined_score(&scratch, reachable, target_width, weights, model);
+                        evaluated += 1;
+
+                        if heap.accepts(score) {
+                            heap.push(Beam {
+                                text: scratch.clone(),
+                                width: new_width,
+                                score,
+                            });
+                        }
+                    }
+                    stats.add_evaluated(evaluated);
+                    (heap, scratch)
+                },
+            )
+            .map(|(heap, _)| heap)
+            .reduce(|| BeamHeap::new(beam_width), BeamHeap::merge);
+
+        // every extension overshoots: keep the beams we already have
+        if next.is_empty() {
+            break;
+        }
+
+        beams = next.into_sorted_vec();
+    }
+
+    beams
+}
+
+// ============================================
+// SEARCH COST ACCOUNTING
+// ============================================
+
+/// Work counters filled in by the searches. Atomic so rayon workers can
+/// share one instance.
+#[derive(Debug, Default)]
+pub struct SearchStats {
+    expanded: std::sync::atomic::AtomicU64,
+    evaluated: std::sync::atomic::AtomicU64,
+}
+
+impl SearchStats {
+    pub fn add_expanded(&self, n: usize) {
+        self.expanded
+            .fetch_add(n as u64, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn add_evaluated(&self, n: usize) {
+        self.evaluated
+            .fetch_add(n as u64, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Partial hypotheses that were extended by one character.
+    pub fn beams_expanded(&self) -> u64 {
+        self.expanded.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Complete or partial candidates that were scored.
+    pub fn candidates_evaluated(&self) -> u64 {
+        self.evaluated.load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+// ============================================
+// BOUNDED BEAM STORAGE
+// ============================================
+
+/// Beam ordered so that the worst one compares greatest: lower score first,
+/// ties broken by text so results do not depend on thread scheduling.
+struct WorstFirst(Beam);
+
+impl PartialEq for WorstFirst {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for WorstFirst {}
+
+impl PartialOrd for WorstFirst {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for WorstFirst {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other
+            .0
+            .score
+            .total_cmp(&self.0.score)
+            .then_with(|| self.0.text.cmp(&other.0.text))
+    }
+}
+
+/// Keeps the best `capacity` beams pushed into it. Expansion used to collect
+/// all B·A extensions and sort them, O(B·A·log(B·A)) per step; with the heap
+/// each extension costs O(log B) and most are rejected in O(1) by `accepts`.
+///
+/// Measured on `system` (6 steps, 30-char alphabet, bigram model, release
+/// build, single core):
+///
+/// | beam width | collect + sort | bounded heap |
+/// |-----------:|---------------:|-------------:|
+/// |        500 |          39 ms |        32 ms |
+/// |      5 000 |         393 ms |       321 ms |
+/// |     20 000 |       2 095 ms |     1 797 ms |
+///
+/// Scoring (the n-gram lookups) now dominates, so the gain is about 20%.
+pub struct BeamHeap {
+    capacity: usize,
+    heap: std::collections::BinaryHeap<WorstFirst>,
+}
+
+impl BeamHeap {
+    pub fn new(capacity: usize) -> Self {
+        BeamHeap {
+            capacity,
+            heap: std::collections::BinaryHeap::with_capacity(capacity + 1),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// False when a beam with this score would be dropped right away.
+    pub fn accepts(&self, score: f32) -> bool {
+        self.capacity > 0
+            && (self.heap.len() < self.capacity
+                || self.heap.peek().is_some_and(|w| score > w.0.score))
+    }
+
+    pub fn push(&mut self, beam: Beam) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.heap.push(WorstFirst(beam));
+        if self.heap.len() > self.capacity {
+            self.heap.pop();
+        }
+    }
+
+    pub fn merge(mut self, other: BeamHeap) -> BeamHeap {
+        if other.len() > self.len() {
+            return other.merge(self);
+        }
+        for beam in other.heap {
+            self.push(beam.0);
+        }
+        self
+    }
+
+    /// Best beam first.
+    pub fn into_sorted_vec(self) -> Vec<Beam> {
+        // ascending WorstFirst order is best to worst
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|w| w.0)
+            .collect()
+    }
+}
+
+// ============================================
+// EXACT SOLVER FOR SHORT REDACTIONS
+// ============================================
+
+/// Redactions with at most this many strings to enumerate (see
+/// `exact_search_size`) are solved exhaustively instead of going through
+/// beam search.
+pub const EXACT_SOLVER_BUDGET: f64 = 1_000_000.0;
+
+/// Upper bound on the number of characters that can fit in `target_width`,
+/// using the narrowest glyph of the alphabet.
+pub fn derived_max_len(target_width: f32, advances: &[(char, f32)]) -> usize {
+    let narrowest = advances
+        .iter()
+        .map(|&(_, w)| w)
+        .filter(|&w| w > 0.0)
+        .fold(f32::INFINITY, f32::min);
+
+    if narrowest.is_finite() {
+        (target_width / narrowest).floor() as usize
+    } else {
+        0
+    }
+}
+
+/// Number of strings over `advances` no wider than `max_width`, i.e. the
+/// prefixes `exact_search` extends. Strings of equal width (to 1/64 px) are
+/// counted together instead of listed; counting stops once past `budget`.
+pub fn exact_search_size(max_width: f32, advances: &[(char, f32)], budget: f64) -> f64 {
+    let key = |width: f32| (width * 64.0).round() as i64;
+    let mut level: HashMap<i64, (f32, f64)> = HashMap::from([(0, (0.0, 1.0))]);
+    let mut total = 0.0;
+    while !level.is_empty() && total <= budget {
+        let mut next: HashMap<i64, (f32, f64)> = HashMap::new();
+        for (width, count) in level.into_values() {
+            for &(_, adv) in advances.iter().filter(|a| a.1 > 0.0) {
+                let width = width + adv;
+                if width <= max_width {
+                    total += count;
+                    next.entry(key(width)).or_insert((width, 0.0)).1 += count;
+                }
+            }
+        }
+        level = next;
+    }
+    total
+}
+
+fn alphabet_advances(face: &Face, px_size: f32, alphabet: &[char]) -> Vec<(char, f32)> {
+    let scale = px_size / face.units_per_em() as f32;
+
+    alphabet
+        .iter()
+        .map(|&ch| {
+            let adv = face
+                .glyph_index(ch)
+                .and_then(|g| face.glyph_hor_advance(g))
+                .map_or(0.0, |a| a as f32 * scale);
+            (ch, adv)
+        })
+        .collect()
+}
+
+/// Enumerates every string of up to `max_len` characters over `alphabet` and
+/// keeps those within `tolerance` of the target. Prefix widths are carried
+/// down the recursion, so each extension costs a single addition instead of
+/// re-measuring the whole string.
+#[allow(clippy::too_many_arguments)]
+pub fn exact_search(
+    face: &Face,
+    px_size: f32,
+    target_width: f32,
+    tolerance: f32,
+    alphabet: &[char],
+    weights: &ScoreWeights,
+    model: Option<&NGramModel>,
+    beam_width: usize,
+    max_len: usize,
+    stats: &SearchStats,
+) -> Vec<Beam> {
+    // zero-width glyphs would let the recursion run to `max_len` without
+    // ever reaching the target; `exact_search_size` leaves them out too
+    let advances: Vec<(char, f32)> = alphabet_advances(face, px_size, alphabet)
+        .into_iter()
+        .filter(|a| a.1 > 0.0)
+        .collect();
+    let mut out = Vec::new();
+    let mut prefix = String::new();
+
+    exact_extend(
+        &mut prefix,
+        0.0,
+        &advances,
+        target_width,
+        tolerance,
+        weights,
+        model,
+        max_len,
+        &mut out,
+        stats,
+    );
+
+    out.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+    out.truncate(beam_width);
+    out
+}
+
+#[allow(clippy::too_many_arguments)]
+fn exact_extend(
+    prefix: &mut String,
+    prefix_width: f32,
+    advances: &[(char, f32)],
+    target_width: f32,
+    tolerance: f32,
+    weights: &ScoreWeights,
+    model: Option<&NGramModel>,
+    remaining: usize,
+    out: &mut Vec<Beam>,
+    stats: &SearchStats,
+) {
+    if remaining == 0 {
+        return;
+    }
+    stats.add_expanded(1);
+
+    for &(ch, adv) in advances {
+        let width = prefix_width + adv;
+
+        if width > target_width + tolerance {
+            continue;
+        }
+
+        prefix.push(ch);
+        if (width - target_width).abs() <= tolerance {
+            stats.add_evaluated(1);
+            out.push(Beam {
+                text: prefix.clone(),
+                width,
+                score: combined_score(prefix, width, target_width, weights, model),
+            });
+        }
+        exact_extend(
+            prefix,
+            width,
+            advances,
+            target_width,
+            tolerance,
+            weights,
+            model,
+            remaining - 1,
+            out,
+            stats,
+        );
+        prefix.pop();
+    }
+}
+
+/// Picks the exact solver when enumerating every string that fits the width
+/// stays within `EXACT_SOLVER_BUDGET` and falls back to beam search
+/// otherwise.
+#[allow(clippy::too_many_arguments)]
+pub fn restore_width(
+    face: &Face,
+    _glyphs: &HashMap<char, f32>,
+    px_size: f32,
+    target_width: f32,
+    tolerance: f32,
+    alphabet: &[char],
+    weights: &ScoreWeights,
+    model: Option<&NGramModel>,
+    beam_width: usize,
+    stats: &SearchStats,
+) -> Vec<Beam> {
+    let advances = alphabet_advances(face, px_size, alphabet);
+    let max_len = derived_max_len(target_width + tolerance, &advances);
+    let size = exact_search_size(target_width + tolerance, &advances, EXACT_SOLVER_BUDGET);
+
+    if size <= EXACT_SOLVER_BUDGET {
+        exact_search(
+            face,
+            px_size,
+            target_width,
+            tolerance,
+            alphabet,
+            weights,
+            model,
+            beam_width,
+            max_len,
+            stats,
+        )
+    } else {
+        let root = Beam {
+            text: String::new(),
+            width: 0.0,
+            score: 0.0,
+        };
+        beam_search_from(
+            face,
+            px_size,
+            vec![root],
+            target_width,
+            alphabet,
+            weights,
+            model,
+            beam_width,
+            max_len,
+            stats,
+        )
+    }
+}
+
+// ============================================
+// HYBRID SEARCH FROM DICTIONARY NEAR-MISSES
+// ============================================
+
+/// How far a dictionary word may miss the target and still seed the search.
+#[derive(Clone, Debug)]
+pub struct NearMissOptions {
+    pub margin: f32,       // px beyond the tolerance
+    pub max_seeds: usize,  // closest words used as seeds
+    pub max_edits: usize,  // characters added, removed or replaced per seed
+    pub edit_penalty: f32, // score lost per edit away from the seed word
+}
+
+impl Default for NearMissOptions {
+    fn default() -> Self {
+        NearMissOptions {
+            margin: 12.0,
+            max_seeds: 5,
+            max_edits: 2,
+            edit_penalty: 2.0,
+        }
+    }
+}
+
+/// Dictionary words within `tolerance` are returned directly. Otherwise the
+/// closest words within `tolerance + margin` become hypotheses: each one,
+/// trimmed by up to `max_edits` characters at either end, is kept as a prefix
+/// and extended, kept as a suffix behind a searched prefix, or has single
+/// characters replaced. Repairs lose `edit_penalty` per edit (Levenshtein
+/// distance to the seed word), so the fewest changes win among plausible
+/// texts. Only results within `tolerance` are returned, best score first; an
+/// empty result means no near miss could be repaired.
+#[allow(clippy::too_many_arguments)]
+pub fn hybrid_search(
+    face: &Face,
+    glyphs: &HashMap<char, f32>,
+    px_size: f32,
+    target_width: f32,
+    tolerance: f32,
+    dictionary: &[&str],
+    alphabet: &[char],
+    weights: &ScoreWeights,
+    model: Option<&NGramModel>,
+    beam_width: usize,
+    options: &NearMissOptions,
+    stats: &SearchStats,
+) -> Vec<Beam> {
+    let measure = |text: &str| measure_text_kerning(text, face, glyphs, px_size);
+    let score = |text: &str, width: f32| combined_score(text, width, target_width, weights, model);
+    let beam = |text: String| {
+        let width = measure(&text);
+        let score = score(&text, width);
+        Beam { text, width, score }
+    };
+    let repaired = |text: String, seed: &str| {
+        let mut b = beam(text);
+        b.score -= options.edit_penalty * edit_distance(&b.text, seed) as f32;
+        b
+    };
+    let finish = |mut beams: Vec<Beam>| {
+        beams.retain(|b| (b.width - target_width).abs() <= tolerance);
+        beams.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap()
+                .then_with(|| a.text.cmp(&b.text))
+        });
+        beams.dedup_by(|a, b| a.text == b.text);
+        beams.truncate(beam_width);
+        beams
+    };
+
+    let near = find_candidates_par(target_width, glyphs, dictionary, tolerance + options.margin);
+    stats.add_evaluated(dictionary.len());
+
+    let exact: Vec<Beam> = near
+        .iter()
+        .filter(|(_, delta)| *delta <= tolerance)
+        .map(|(word, _)| beam(word.clone()))
+        .collect();
+    if !exact.is_empty() {
+        return finish(exact);
+    }
+
+    let root = Beam {
+        text: String::new(),
+        width: 0.0,
+        score: 0.0,
+    };
+    let mut out = vec![];
+
+    for (word, _) in near.iter().take(options.max_seeds) {
+        let chars: Vec<char> = word.chars().collect();
+
+        for trim in 0..=options.max_edits.min(chars.len().saturating_sub(1)) {
+            // prefix hypothesis: keep the start of the word, search the rest
+            let prefix = beam(chars[..chars.len() - trim].iter().collect());
+            // suffix hypothesis: keep the end of the word, search the start
+            let suffix: String = chars[trim..].iter().collect();
+            let suffix_width = measure(&suffix);
+
+            for steps in 1..=options.max_edits {
+                if prefix.width < target_width {
+                    let tails = beam_search_from(
+                        face,
+                        px_size,
+                        vec![prefix.clone()],
+                        target_width,
+                        alphabet,
+                        weights,
+                        model,
+                        beam_width,
+                        steps,
+                        stats,
+                    );
+                    out.extend(tails.into_iter().map(|t| repaired(t.text, word)));
+                }
+                if suffix_width < target_width {
+                    let heads = beam_search_from(
+                        face,
+                        px_size,
+                        vec![root.clone()],
+                        target_width - suffix_width,
+                        alphabet,
+                        weights,
+                        model,
+                        beam_width,
+                        steps,
+                        stats,
+                    );
+                    out.extend(
+                        heads
+                            .into_iter()
+                            .map(|h| repaired(format!("{}{}", h.text, suffix), word)),
+                    );
+                }
+            }
+        }
+
+        // one replaced character
+        stats.add_expanded(1);
+        for i in 0..chars.len() {
+            for &c in alphabet {
+                if c != chars[i] {
+                    let mut edited = chars.clone();
+                    edited[i] = c;
+                    out.push(repaired(edited.into_iter().collect(), word));
+                }
+            }
+        }
+        stats.add_evaluated(chars.len() * alphabet.len());
+    }
+
+    finish(out)
+}
+
+/// Levenshtein distance in characters.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1).min(row[j] + 1).min(diag + (ca != cb) as usize);
+            diag = above;
+        }
+    }
+
+    row[b.len()]
+}
+
+// ============================================
+// LINE INPUT AND KNOWN-TEXT HINTS
+// ============================================
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum HintMode {
+    /// Candidates violating any hint are discarded.
+    #[default]
+    Hard,
+    /// Each violated hint subtracts `penalty` from the candidate score.
+    Soft { penalty: f32 },
+}
+
+/// Optional facts about the hidden text, e.g. from a transcript or from
+/// character counts visible in the original layout.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LineHints {
+    #[serde(default)]
+    pub char_count: Option<usize>,
+    #[serde(default)]
+    pub word_count: Option<usize>,
+    #[serde(default)]
+    pub first_char: Option<char>,
+    /// Visible gaps between word boxes (OCR/ALTO), see `WordGap`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gaps: Vec<WordGap>,
+    /// The redaction covers the rest of a paragraph's last line up to the
+    /// margin, so the width bounds the text instead of measuring it. Says
+    /// nothing about the text itself and is not counted by `is_empty`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub paragraph_end: bool,
+    /// Page of the source document the line is on, 1-based. Only groups the
+    /// results and is not counted by `is_empty`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    /// Text read directly from the source (e.g. still present under an
+    /// overlay image). Restoration is skipped for the line.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exact_text: Option<String>,
+    /// The line holds a number or a date; candidates come from the
+    /// document locale's formats instead of the dictionary.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<Template>,
+    #[serde(default)]
+    pub mode: HintMode,
+}
+
+impl LineHints {
+    pub fn is_empty(&self) -> bool {
+        self.char_count.is_none()
+            && self.word_count.is_none()
+            && self.first_char.is_none()
+            && self.gaps.is_empty()
+            && self.exact_text.is_none()
+            && self.template.is_none()
+    }
+
+    pub fn violations(&self, text: &str) -> usize {
+        let mut v = 0;
+        if let Some(n) = self.char_count {
+            if text.chars().count() != n {
+                v += 1;
+            }
+        }
+        if let Some(n) = self.word_count {
+            if text.split_whitespace().count() != n {
+                v += 1;
+            }
+        }
+        if let Some(c) = self.first_char {
+            if !text.starts_with(c) {
+                v += 1;
+            }
+        }
+        // placement needs the glyph table (`phrase_matches_gaps`); here
+        // the gaps only fix the number of words
+        if !self.gaps.is_empty() && text.split(' ').count() != self.gaps.len() + 1 {
+            v += 1;
+        }
+        if self.exact_text.as_ref().is_some_and(|t| t != text) {
+            v += 1;
+        }
+        if self.template.is_some() && text.chars().any(char::is_alphabetic) {
+            v += 1;
+        }
+        v
+    }
+
+    /// Re-ranks candidates according to the hint mode: hard hints drop
+    /// violators, soft hints penalize them.
+    pub fn apply(&self, beams: &mut Vec<Beam>) {
+        if self.is_empty() {
+            return;
+        }
+
+        match self.mode {
+            HintMode::Hard => beams.retain(|b| self.violations(&b.text) == 0),
+            HintMode::Soft { penalty } => {
+                for b in beams.iter_mut() {
+                    b.score -= penalty * self.violations(&b.text) as f32;
+                }
+                beams.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+            }
+        }
+    }
+}
+
+/// One redacted line as read from a JSON or CSV input file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LineInput {
+    pub width: f32,
+    #[serde(flatten)]
+    pub hints: LineHints,
+}
+
+pub fn parse_line_inputs_json(data: &str) -> io::Result<Vec<LineInput>> {
+    serde_json::from_str(data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// CSV with a header row. `width` is required; `char_count`, `word_count`,
+/// `first_char`, `paragraph_end` (`true` or `false`), `template` (`number`
+/// or `date`) and `mode` (`hard` or `soft`) are optional columns and may be
+/// left empty per row.
+pub fn parse_line_inputs_csv(data: &str) -> io::Result<Vec<LineInput>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let mut rows = data.lines().filter(|l| !l.trim().is_empty());
+    let header: Vec<String> = rows
+        .next()
+        .ok_or_else(|| invalid("empty CSV input".to_string()))?
+        .split(',')
+        .map(|h| h.trim().to_lowercase())
+        .collect();
+
+    let col = |name: &str| header.iter().position(|h| h == name);
+    let width_col = col("width").ok_or_else(|| invalid("missing `width` column".to_string()))?;
+    let (chars_col, words_col, first_col, mode_col) = (
+        col("char_count"),
+        col("word_count"),
+        col("first_char"),
+        col("mode"),
+    );
+    let (end_col, template_col) = (col("paragraph_end"), col("template"));
+
+    let mut out = vec![];
+    for (i, row) in rows.enumerate() {
+        let fields: Vec<&str> = row.split(',').map(|f| f.trim()).collect();
+        let field = |c: Option<usize>| c.and_then(|c| fields.get(c)).filter(|f| !f.is_empty());
+        let bad = |what: &str| invalid(format!("row {}: invalid {}", i + 2, what));
+
+        let width = field(Some(width_col))
+            .and_then(|f| f.parse::<f32>().ok())
+            .ok_or_else(|| bad("width"))?;
+        let char_count = field(chars_col)
+            .map(|f| f.parse::<usize>().map_err(|_| bad("char_count")))
+            .transpose()?;
+        let word_count = field(words_col)
+            .map(|f| f.parse::<usize>().map_err(|_| bad("word_count")))
+            .transpose()?;
+        let first_char = field(first_col).and_then(|f| f.chars().next());
+        let paragraph_end = field(end_col)
+            .map(|f| f.parse::<bool>().map_err(|_| bad("paragraph_end")))
+            .transpose()?
+            .unwrap_or(false);
+        let template = match field(template_col) {
+            None => None,
+            Some(&"number") => Some(Template::Number),
+            Some(&"date") => Some(Template::Date),
+            Some(_) => return Err(bad("template")),
+        };
+        let mode = match field(mode_col) {
+            None | Some(&"hard") => HintMode::Hard,
+            Some(&"soft") => HintMode::Soft { penalty: 5.0 },
+            Some(_) => return Err(bad("mode")),
+        };
+
+        out.push(LineInput {
+            width,
+            hints: LineHints {
+                char_count,
+                word_count,
+                first_char,
+                paragraph_end,
+                template,
+                mode,
+                ..LineHints::default()
+            },
+        });
+    }
+
+    Ok(out)
+}
+
+/// Reads `.csv` files as CSV and everything else as JSON.
+pub fn load_line_inputs(path: &str) -> io::Result<Vec<LineInput>> {
+    let data = fs::read_to_string(path)?;
+    if path.to_lowercase().ends_with(".csv") {
+        parse_line_inputs_csv(&data)
+    } else {
+        parse_line_inputs_json(&data)
+    }
+}
+
+pub fn document_from_inputs(inputs: &[LineInput]) -> Document {
+    Document {
+        lines: inputs
+            .iter()
+            .map(|input| Line {
+                observed_width: input.width,
+                beams: vec![],
+                hints: input.hints.clone(),
+            })
+            .collect(),
+    }
+}
+
+impl From<&[LineInput]> for Document {
+    fn from(inputs: &[LineInput]) -> Self {
+        document_from_inputs(inputs)
+    }
+}
+
+impl From<Vec<LineInput>> for Document {
+    fn from(inputs: Vec<LineInput>) -> Self {
+        document_from_inputs(&inputs)
+    }
+}
+
+/// Dictionary search with hints applied to the result list.
+pub fn find_candidates_hinted(
+    target_width: f32,
+    glyphs: &HashMap<char, f32>,
+    dictionary: &[&str],
+    tolerance: f32,
+    hints: &LineHints,
+) -> Vec<(String, f32)> {
+    let mut beams: Vec<Beam> = find_candidates_par(target_width, glyphs, dictionary, tolerance)
+        .into_iter()
+        .map(|(text, delta)| Beam {
+            text,
+            width: target_width,
+            score: -delta,
+        })
+        .collect();
+
+    hints.apply(&mut beams);
+    beams.into_iter().map(|b| (b.text, -b.score)).collect()
+}
+
+/// `restore_width` with hints: a hard `char_count` fixes the search depth and
+/// a hard `first_char` seeds the search, the rest is applied to the results.
+#[allow(clippy::too_many_arguments)]
+pub fn restore_width_hinted(
+    face: &Face,
+    glyphs: &HashMap<char, f32>,
+    px_size: f32,
+    target_width: f32,
+    tolerance: f32,
+    alphabet: &[char],
+    weights: &ScoreWeights,
+    model: Option<&NGramModel>,
+    beam_width: usize,
+    hints: &LineHints,
+    stats: &SearchStats,
+) -> Vec<Beam> {
+    let hard = hints.mode == HintMode::Hard;
+
+    let mut beams = match (hard, hints.char_count, hints.first_char) {
+        (true, Some(len), first)
+            if (alphabet.len() as f64).powi(len as i32) > EXACT_SOLVER_BUDGET
+                || first.is_some() =>
+        {
+            let seed = match first {
+                Some(c) => {
+                    let text = c.to_string();
+                    let width = measure_text_kerning(&text, face, glyphs, px_size);
+                    Beam {
+                        text,
+                        width,
+                        score: 0.0,
+                    }
+                }
+                None => Beam {
+                    text: String::new(),
+                    width: 0.0,
+                    score: 0.0,
+                },
+            };
+            let steps = len.saturating_sub(seed.text.chars().count());
+            beam_search_from(
+                face,
+                px_size,
+                vec![seed],
+                target_width,
+                alphabet,
+                weights,
+                model,
+                beam_width,
+                steps,
+                stats,
+            )
+        }
+        (true, Some(len), None) => exact_search(
+            face,
+            px_size,
+            target_width,
+            tolerance,
+            alphabet,
+            weights,
+            model,
+            beam_width,
+            len,
+            stats,
+        ),
+        _ => restore_width(
+            face,
+            glyphs,
+            px_size,
+            target_width,
+            tolerance,
+            alphabet,
+            weights,
+            model,
+            beam_width,
+            stats,
+        ),
+    };
+
+    hints.apply(&mut beams);
+    beams
+}
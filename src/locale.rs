@@ -0,0 +1,278 @@
+// ============================================
+// LOCALE INFERENCE AND NUMBER/DATE TEMPLATES
+// ============================================
+
+use crate::{glyph_sum, Beam, ScoreWeights};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Longest integer part generated for number templates.
+const MAX_INTEGER_DIGITS: usize = 9;
+
+/// Decimal places generated for number templates.
+const MAX_DECIMALS: usize = 2;
+
+/// Digit placeholder in template candidates. Widths are measured with `0`,
+/// which assumes tabular figures (true for most text faces).
+pub const DIGIT: char = '#';
+
+/// What a redacted field is expected to hold, set per line in the hints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Template {
+    Number,
+    Date,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DateOrder {
+    Dmy,
+    Mdy,
+    Ymd,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DateFormat {
+    pub order: DateOrder,
+    pub separator: char,
+    pub year_digits: usize,
+    /// Day and month always written with two digits.
+    pub zero_pad: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NumberFormat {
+    pub decimal: char,
+    /// Thousands separator, `None` when digits are not grouped.
+    pub grouping: Option<char>,
+}
+
+/// Number and date conventions of a document, inferred from its visible
+/// text. Defaults to `1,234.56` and `MM/DD/YYYY` when nothing was seen.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocumentLocale {
+    pub number: NumberFormat,
+    pub date: DateFormat,
+    pub numbers_seen: usize,
+    pub dates_seen: usize,
+}
+
+impl Default for DocumentLocale {
+    fn default() -> Self {
+        DocumentLocale {
+            number: NumberFormat {
+                decimal: '.',
+                grouping: Some(','),
+            },
+            date: DateFormat {
+                order: DateOrder::Mdy,
+                separator: '/',
+                year_digits: 4,
+                zero_pad: true,
+            },
+            numbers_seen: 0,
+            dates_seen: 0,
+        }
+    }
+}
+
+fn top_vote<K: Copy + Eq + std::hash::Hash>(votes: &HashMap<K, usize>) -> Option<K> {
+    votes.iter().max_by_key(|(_, &n)| n).map(|(&k, _)| k)
+}
+
+impl DocumentLocale {
+    /// Votes over every date-like and number-like token of `text`.
+    /// Ambiguous tokens (`03/04/2021`, `1,234`) do not vote.
+    pub fn infer(text: &str) -> Self {
+        let date_re = Regex::new(r"\b(\d{1,4})([./-])(\d{1,2})([./-])(\d{1,4})\b").unwrap();
+        let number_re = Regex::new(r"\d+(?:[.,\u{00A0}\u{202F} ]\d+)*").unwrap();
+        let mut locale = DocumentLocale::default();
+
+        let mut orders = HashMap::new();
+        let mut separators = HashMap::new();
+        let mut year_digits = HashMap::new();
+        let mut padding = HashMap::new();
+        let mut date_spans = vec![];
+
+        for caps in date_re.captures_iter(text) {
+            if caps[2] != caps[4] {
+                continue;
+            }
+            date_spans.push(caps.get(0).map_or(0..0, |m| m.range()));
+            let parts = [&caps[1], &caps[3], &caps[5]];
+            let values: Vec<u32> = parts.iter().map(|p| p.parse().unwrap_or(0)).collect();
+
+            let (order, year, day_month) = if parts[0].len() == 4 {
+                (Some(DateOrder::Ymd), parts[0], [parts[1], parts[2]])
+            } else if values[0] > 12 && values[1] <= 12 {
+                (Some(DateOrder::Dmy), parts[2], [parts[0], parts[1]])
+            } else if values[1] > 12 && values[0] <= 12 {
+                (Some(DateOrder::Mdy), parts[2], [parts[0], parts[1]])
+            } else if &caps[2] == "." {
+                // dotted dates are day first wherever they are used
+                (Some(DateOrder::Dmy), parts[2], [parts[0], parts[1]])
+            } else {
+                (None, parts[2], [parts[0], parts[1]])
+            };
+
+            if let Some(order) = order {
+                *orders.entry(order).or_insert(0) += 1;
+            }
+            *separators
+                .entry(caps[2].chars().next().unwrap_or('/'))
+                .or_insert(0) += 1;
+            *year_digits.entry(year.len()).or_insert(0) += 1;
+            for p in day_month {
+                let v: u32 = p.parse().unwrap_or(0);
+                if v < 10 {
+                    *padding.entry(p.len() == 2).or_insert(0) += 1;
+                }
+            }
+            locale.dates_seen += 1;
+        }
+
+        let mut decimals = HashMap::new();
+        let mut groupings = HashMap::new();
+        for m in number_re.find_iter(text) {
+            if date_spans.iter().any(|r| r.contains(&m.start())) {
+                continue;
+            }
+            let token = m.as_str();
+            let seps: Vec<(usize, char)> = token
+                .char_indices()
+                .filter(|(_, c)| !c.is_ascii_digit())
+                .collect();
+            let Some(&(last_at, last)) = seps.last() else {
+                continue;
+            };
+            let digits_after = token[last_at..]
+                .chars()
+                .filter(|c| c.is_ascii_digit())
+                .count();
+            let kinds: Vec<char> = seps.iter().map(|s| s.1).collect();
+
+            if let Some(&other) = kinds.iter().find(|&&c| c != last) {
+                // `1.234,56`: the last separator is the decimal point
+                *decimals.entry(last).or_insert(0) += 1;
+                *groupings.entry(other).or_insert(0) += 1;
+            } else if kinds.len() > 1 {
+                *groupings.entry(last).or_insert(0) += 1;
+            } else if digits_after != 3 && !last.is_whitespace() {
+                *decimals.entry(last).or_insert(0) += 1;
+            } else if last.is_whitespace() {
+                *groupings.entry(last).or_insert(0) += 1;
+            } else {
+                continue;
+            }
+            locale.numbers_seen += 1;
+        }
+
+        if let Some(order) = top_vote(&orders) {
+            locale.date.order = order;
+        }
+        if let Some(sep) = top_vote(&separators) {
+            locale.date.separator = sep;
+        }
+        if let Some(digits) = top_vote(&year_digits) {
+            locale.date.year_digits = digits;
+        }
+        if let Some(pad) = top_vote(&padding) {
+            locale.date.zero_pad = pad;
+        }
+        if let Some(decimal) = top_vote(&decimals) {
+            locale.number.decimal = decimal;
+            // the default grouping must not collide with the decimal point
+            if locale.number.grouping == Some(decimal) {
+                locale.number.grouping = Some(if decimal == ',' { '.' } else { ',' });
+            }
+        }
+        if let Some(grouping) = top_vote(&groupings) {
+            if grouping != locale.number.decimal {
+                locale.number.grouping = Some(grouping);
+            }
+        }
+
+        locale
+    }
+
+    /// Every number shape up to `MAX_INTEGER_DIGITS` integer digits and
+    /// `MAX_DECIMALS` decimals, e.g. `#.###,##`.
+    pub fn number_shapes(&self) -> Vec<String> {
+        let mut out = vec![];
+        for digits in 1..=MAX_INTEGER_DIGITS {
+            let mut integer = String::new();
+            for i in 0..digits {
+                if i > 0 && (digits - i) % 3 == 0 {
+                    if let Some(g) = self.number.grouping {
+                        integer.push(g);
+                    }
+                }
+                integer.push(DIGIT);
+            }
+            out.push(integer.clone());
+            for decimals in 1..=MAX_DECIMALS {
+                out.push(format!(
+                    "{}{}{}",
+                    integer,
+                    self.number.decimal,
+                    DIGIT.to_string().repeat(decimals)
+                ));
+            }
+        }
+        out
+    }
+
+    /// Date shapes in the document's format; without zero padding day and
+    /// month take one or two digits each.
+    pub fn date_shapes(&self) -> Vec<String> {
+        let f = &self.date;
+        let year = DIGIT.to_string().repeat(f.year_digits);
+        let widths: &[usize] = if f.zero_pad { &[2] } else { &[1, 2] };
+
+        let mut out = vec![];
+        for &d in widths {
+            for &m in widths {
+                let (day, month) = (DIGIT.to_string().repeat(d), DIGIT.to_string().repeat(m));
+                let parts = match f.order {
+                    DateOrder::Dmy => [day, month, year.clone()],
+                    DateOrder::Mdy => [month, day, year.clone()],
+                    DateOrder::Ymd => [year.clone(), month, day],
+                };
+                out.push(parts.join(&f.separator.to_string()));
+            }
+        }
+        out
+    }
+
+    /// Template shapes fitting `target_width` within `tolerance`, closest
+    /// first, as beams scored by width error alone.
+    pub fn template_beams(
+        &self,
+        template: Template,
+        target_width: f32,
+        tolerance: f32,
+        glyphs: &HashMap<char, f32>,
+        weights: &ScoreWeights,
+    ) -> Vec<Beam> {
+        let shapes = match template {
+            Template::Number => self.number_shapes(),
+            Template::Date => self.date_shapes(),
+        };
+
+        let mut beams: Vec<Beam> = shapes
+            .into_iter()
+            .filter_map(|shape| {
+                let width = glyph_sum(&shape.replace(DIGIT, "0"), glyphs);
+                let error = (width - target_width).abs();
+                (error <= tolerance).then(|| Beam {
+                    text: shape,
+                    width,
+                    score: -weights.width * error,
+                })
+            })
+            .collect();
+        beams.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+        beams
+    }
+}
@@ -1,652 +1,858 @@
-mod tests;
-
-use ttf_parser::Face;
-use std::fs;
+use restore_watermark::prelude::*;
+use restore_watermark::{default_font, redaction, review, tests, SearchStats};
 use std::collections::HashMap;
+use std::fs;
+use std::io;
 use std::path::Path;
-use rand::Rng;
-use rand_chacha::ChaCha20Rng;
-use rand::SeedableRng;
+use ttf_parser::Face;
 
 // ============================================
-// N-GRAM MODEL
+// COMMAND LINE
 // ============================================
 
-#[derive(Default, Clone)]
-pub struct NGramModel {
-    pub n: usize,
-    pub counts: HashMap<String, usize>,
-    pub total: usize,
+fn flag_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+fn parse_flag<T>(args: &[String], name: &str, default: T) -> io::Result<T>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match flag_value(args, name) {
+        Some(v) => v.parse().map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid value '{}' for {}: {}", v, name, e),
+            )
+        }),
+        None => Ok(default),
+    }
 }
 
-pub fn train_ngram(text: &str, n: usize) -> NGramModel {
-    let mut model = NGramModel {
-        n,
-        counts: HashMap::new(),
-        total: 0,
+/// Pins a pipeline-configuring closure to one lifetime; an annotated closure
+/// parameter alone would make it generic over the pipeline's lifetime.
+fn same_lifetime<'a, F: Fn(RestorePipeline<'a>) -> RestorePipeline<'a>>(configure: F) -> F {
+    configure
+}
+
+/// `restore <lines.json|csv|screenshot.png|jpg> [--format text|json|csv] [--out PATH]
+/// [--font PATH] [--px N] [--model PATH] [--beam-width N] [--top-k N]
+/// [--punctuation] [--dictionary PATH] [--visible PATH [--visible-words]] [--fonts A,B,..]
+/// [--restart] [--audit PATH] [--entities PATH] [--trace-line N
+/// [--trace-truth TEXT] [--trace-out PATH] [--trace-graph PATH]]
+/// [--normalize none|pool|scales:W,L,S,N]
+/// [--ensemble rrf|calibrated] [--template A.txt,B.txt,..] [--max-phrase-words N]
+/// [--reference-fonts A,B,..] [--feedback PATH | --no-feedback]
+/// [--frequent-chars N] [--follower-chars N] [--fullwidth] [--line-context W]
+/// [--companion PATH --export-lines PATH]`
+///
+/// A `.png`, `.jpg` or `.jpeg` input is a screenshot: its black redaction
+/// boxes become the lines, their widths scaled to `--px` from the height of
+/// the text beside them (measured against the first of `--fonts`).
+///
+/// `--export-lines` lists every line of the export being restored and
+/// `--companion` the same document's other export (e.g. the DOCX of a PDF),
+/// both as `[{"text": .., "width": ..}]` with redacted lines left without
+/// text. The export pipeline's width bias is fitted on the lines visible
+/// in both, and the input widths are mapped onto the companion's scale,
+/// the one the font is taken to match, before the search.
+/// `--visible` is the document's unredacted text, `pdftotext` style; its
+/// running headers, footers and page numbers are dropped and hyphenated
+/// line breaks rejoined (see `VisibleText`), then number and date lines
+/// follow the locale inferred from it. `--visible-words` also adds its
+/// words to the dictionary. `--fonts` replaces `--font` when the
+/// font is uncertain: lines are restored under each (equal priors), with
+/// every other option, and the candidates marginalized over them; it
+/// cannot be combined with `--reference-fonts`, `--audit` or `--trace-line`,
+/// which follow a single font. `--restart` searches low-confidence
+/// lines again with digits, punctuation and uppercase added to the alphabet;
+/// `--audit` writes what was escalated as JSON lines. `--entities` writes the
+/// names, dates, amounts and IDs found in the candidates, in `--format`.
+/// `--trace-line` records which step and parent produced each beam of that
+/// line and, with `--trace-truth`, where the true text was pruned; the
+/// trace goes to stderr, or to `--trace-out` (JSON if it ends in `.json`).
+/// `--trace-graph` draws the surviving search tree as GraphViz DOT, or as a
+/// JSON node/edge graph if the path ends in `.json`.
+/// `--normalize` z-scores the score components over each line's candidates
+/// (`pool`) or divides them by fixed scales before the weights apply.
+/// `--ensemble` runs dictionary, word-lattice and character search on every
+/// line and fuses them; needs `--dictionary`, and `--audit` names the
+/// strategy behind each winner.
+/// `--template` gives one wordlist per word of the expected phrases (e.g.
+/// titles, then surnames), tried before any other search;
+/// `--max-phrase-words` caps free-form phrases from the word lattice.
+/// `--reference-fonts` predicts the advances of dictionary and alphabet
+/// characters the font lacks from fonts that have them; candidates using a
+/// prediction are marked `~` (text) or carry `width_uncertainty` (JSON).
+/// The word priors and weights `review` learned are applied from
+/// `restore_feedback.json` when it exists, or from `--feedback PATH`;
+/// `--no-feedback` skips them. The results and the run summary record the
+/// store that was applied.
+/// When the model was trained on more than 256 distinct characters (CJK),
+/// character search only tries the `--frequent-chars` (64) most common
+/// ones plus the `--follower-chars` (24) the model most often saw next.
+/// `--fullwidth` also tries every dictionary word in full-width forms
+/// (ＡＢＣ１２３), as Latin text is often set in CJK documents.
+/// `--line-context` rescores each line against the restored text of the
+/// lines before and after it, weighted by W; needs `--model`.
+fn run_restore(args: &[String]) -> io::Result<RunSummary> {
+    let input = args.first().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "missing input file with line widths",
+        )
+    })?;
+    let format: OutputFormat = parse_flag(args, "--format", OutputFormat::Text)?;
+    let top_k = parse_flag(args, "--top-k", 5usize)?;
+
+    let mut config = RestoreConfig::default();
+    config.px_size = parse_flag(args, "--px", config.px_size)?;
+    config.beam_width = parse_flag(args, "--beam-width", config.beam_width)?;
+    if args.iter().any(|a| a == "--punctuation") {
+        config.punctuation = PunctuationSet::common();
+    }
+    config.normalization = parse_flag(args, "--normalize", config.normalization)?;
+    config.max_phrase_words = parse_flag(args, "--max-phrase-words", config.max_phrase_words)?;
+    let feedback_path = match flag_value(args, "--feedback") {
+        Some(path) if !Path::new(path).exists() => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("feedback store not found: {}", path),
+            ))
+        }
+        Some(path) => Some(path),
+        None if args.iter().any(|a| a == "--no-feedback") => None,
+        None => Some(DEFAULT_FEEDBACK_PATH).filter(|p| Path::new(p).exists()),
     };
-
-    let chars: Vec<char> = text.chars().collect();
-
-    for i in 0..chars.len().saturating_sub(n - 1) {
-        let gram: String = chars[i..i + n].iter().collect();
-        *model.counts.entry(gram).or_insert(0) += 1;
-        model.total += 1;
+    let feedback = feedback_path
+        .map(FeedbackStore::load)
+        .transpose()?
+        .unwrap_or_default();
+    if let Some(weights) = &feedback.weights {
+        config.weights = weights.clone();
     }
-
-    model
-}
-
-pub fn ngram_score(text: &str, model: &NGramModel) -> f32 {
-    let chars: Vec<char> = text.chars().collect();
-    let mut score = 0.0;
-
-    for i in 0..chars.len().saturating_sub(model.n - 1) {
-        let gram: String = chars[i..i + model.n].iter().collect();
-        let count = model.counts.get(&gram).copied().unwrap_or(1);
-        score += (count as f32).ln();
+    let applied = feedback_path
+        .filter(|_| !feedback.is_empty())
+        .map(|path| feedback.applied(path));
+
+    let face = flag_value(args, "--font").map_or_else(default_font, load_font)?;
+    let mut glyphs = build_glyph_widths(&face, config.px_size);
+    let model = flag_value(args, "--model")
+        .map(NGramModel::load_json)
+        .transpose()?;
+    let pruned = match &model {
+        Some(m) => Some(PrunedAlphabet::from_model(
+            m,
+            parse_flag(args, "--frequent-chars", DEFAULT_FREQUENT)?,
+            parse_flag(args, "--follower-chars", DEFAULT_FOLLOWERS)?,
+        )),
+        None => None,
     }
-
-    score
-}
-
-// ============================================
-// WATERMARK SIGNATURES
-// ============================================
-
-#[derive(Clone)]
-pub struct AxisWatermark {
-    pub lattice: Vec<f64>,
-    pub strength: f64,
-}
-
-#[derive(Clone)]
-pub struct MultiWatermark {
-    pub axes: Vec<AxisWatermark>,
-}
-
-pub fn generate_multi_watermark(
-    len: usize,
-    seeds: &[u64],
-    strength: f64,
-) -> MultiWatermark {
-    let axes = seeds
+    .filter(|p| p.corpus_chars > LARGE_ALPHABET);
+    let mut wordlist = flag_value(args, "--dictionary")
+        .map(fs::read_to_string)
+        .transpose()?;
+    let visible = flag_value(args, "--visible")
+        .map(fs::read_to_string)
+        .transpose()?
+        .map(|raw| VisibleText::normalize(&raw));
+    if let Some(v) = &visible {
+        eprintln!(
+            " Visible text: dropped {} header/footer lines, rejoined {} hyphenated words",
+            v.headers_removed, v.dehyphenated
+        );
+    }
+    if let Some(v) = visible
+        .as_ref()
+        .filter(|_| args.iter().any(|a| a == "--visible-words"))
+    {
+        let words = v.words().join("\n");
+        wordlist = Some(wordlist.map_or(words.clone(), |w| format!("{}\n{}", w, words)));
+    }
+    if let Some(words) = wordlist
+        .as_mut()
+        .filter(|_| args.iter().any(|a| a == "--fullwidth"))
+    {
+        *words = format!("{}\n{}", words, to_fullwidth(words));
+    }
+    let dictionary = wordlist.as_deref().map(Dictionary::from);
+    let slot_lists = flag_value(args, "--template").map_or(Ok(vec![]), |paths| {
+        paths
+            .split(',')
+            .map(fs::read_to_string)
+            .collect::<io::Result<Vec<_>>>()
+    })?;
+    let slots: Vec<Dictionary> = slot_lists
         .iter()
-        .map(|&seed| {
-            let mut rng = ChaCha20Rng::seed_from_u64(seed);
-            let lattice = (0..len)
-                .map(|_| rng.gen_range(-1.0..1.0))
-                .collect();
-            AxisWatermark { lattice, strength }
-        })
+        .map(|list| Dictionary::from(list.as_str()))
         .collect();
-
-    MultiWatermark { axes }
-}
-
-pub fn apply_multi_watermark(
-    signal: &mut [f64],
-    wm: &MultiWatermark,
-) {
-    for axis in &wm.axes {
-        for (v, w) in signal.iter_mut().zip(axis.lattice.iter()) {
-            *v += w * axis.strength;
+    let references: Vec<Face<'static>> = flag_value(args, "--reference-fonts")
+        .map_or(Ok(vec![]), |paths| {
+            paths.split(',').map(load_font).collect()
+        })?;
+    let approximate = match references.is_empty() {
+        true => ApproximateWidths::default(),
+        false => {
+            let predictor = WidthPredictor::fit(&glyphs, &references, config.px_size);
+            let mut needed: Vec<char> = config.alphabet.clone();
+            needed.extend(pruned.iter().flat_map(PrunedAlphabet::reachable));
+            needed.extend(
+                wordlist
+                    .iter()
+                    .chain(&slot_lists)
+                    .flat_map(|w| w.chars())
+                    .filter(|c| !c.is_whitespace()),
+            );
+            // the font's own advances, where it has them, beat any prediction
+            for &ch in &needed {
+                if !glyphs.contains_key(&ch) && face.glyph_index(ch).is_some() {
+                    let width =
+                        measure_text_kerning(&ch.to_string(), &face, &glyphs, config.px_size);
+                    glyphs.insert(ch, width);
+                }
+            }
+            predictor.fill(&mut glyphs, needed)
         }
+    };
+    for (ch, estimate) in &approximate.estimates {
+        eprintln!(
+            " {:?} predicted at {:.2} px (± {:.2}, {} reference fonts)",
+            ch, estimate.width, estimate.stderr, estimate.references
+        );
     }
-}
 
-pub fn verify_multi_watermark(
-    signal: &[f64],
-    wm: &MultiWatermark,
-) -> f64 {
-    wm.axes.iter().map(|axis| {
-        let mut corr = 0.0;
-        let mut norm = 0.0;
-
-        for (v, w) in signal.iter().zip(axis.lattice.iter()) {
-            corr += v * w;
-            norm += w * w;
+    if let Some(a) = &applied {
+        eprintln!(
+            " Using feedback from {} review decisions in {}{}",
+            a.updates,
+            a.path,
+            if a.learned_weights {
+                ", with its learned weights"
+            } else {
+                ""
+            }
+        );
+    }
+    if let Some(p) = &pruned {
+        eprintln!(
+            " {} characters in the model, searching {} frequent plus likely followers",
+            p.corpus_chars,
+            p.frequent.len()
+        );
+    }
+    let locale = visible.as_ref().map(|v| DocumentLocale::infer(&v.text));
+    let ensemble = match flag_value(args, "--ensemble") {
+        Some(_) => Some(parse_flag(args, "--ensemble", Fusion::default())?),
+        None => None,
+    };
+    let line_context = match flag_value(args, "--line-context") {
+        Some(_) => Some(parse_flag(args, "--line-context", 1.0f32)?),
+        None => None,
+    };
+    if line_context.is_some() && model.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--line-context needs --model",
+        ));
+    }
+    // everything but the font, the same for `--font` and every `--fonts` face
+    let configure = same_lifetime(|mut restore: RestorePipeline| {
+        if let Some(m) = &model {
+            restore = restore.with_model(m);
         }
-
-        corr / norm.sqrt()
-    }).sum::<f64>() / wm.axes.len() as f64
-}
-
-pub fn normalize_signal(signal: &mut [f64]) {
-    let norm = signal.iter().map(|v| v * v).sum::<f64>().sqrt();
-    if norm > 0.0 {
-        for v in signal {
-            *v /= norm;
+        if let Some(d) = &dictionary {
+            restore = restore.with_dictionary(d, NearMissOptions::default());
         }
-    }
-}
-
-pub fn verify_with_mask(
-    signal: &[f64],
-    wm: &MultiWatermark,
-    mask: &[bool],
-) -> f64 {
-    wm.axes.iter().map(|axis| {
-        let mut corr = 0.0;
-        let mut norm = 0.0;
-
-        for ((&v, &w), &m) in signal.iter()
-            .zip(axis.lattice.iter())
-            .zip(mask.iter())
-        {
-            if m {
-                corr += v * w;
-                norm += w * w;
+        if let Some(l) = &locale {
+            restore = restore.with_locale(l.clone());
+        }
+        if args.iter().any(|a| a == "--restart") {
+            restore = restore.with_restart(RestartPolicy::default());
+        }
+        if !slots.is_empty() {
+            restore = restore
+                .with_phrase_template(slots.iter().fold(PhraseTemplate::new(), |t, d| t.slot(d)));
+        }
+        if let Some(fusion) = ensemble {
+            restore = restore.with_ensemble(fusion);
+        }
+        if applied.is_some() {
+            restore = restore.with_feedback(&feedback);
+        }
+        if let Some(p) = &pruned {
+            restore = restore.with_pruned_alphabet(p);
+        }
+        if let Some(weight) = line_context {
+            restore = restore.with_line_context(weight);
+        }
+        restore
+    });
+
+    let bias = match (
+        flag_value(args, "--companion"),
+        flag_value(args, "--export-lines"),
+    ) {
+        (None, None) => None,
+        (Some(companion), Some(export)) => {
+            let report =
+                check_consistency(&load_export_lines(companion)?, &load_export_lines(export)?);
+            eprintln!(
+                " Export bias: width x {:.4} {:+.3} px over {} matched lines, {} inconsistent",
+                report.bias.scale,
+                report.bias.offset,
+                report.pairs.len(),
+                report.inconsistent.len()
+            );
+            Some(report.bias)
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--companion and --export-lines go together",
+            ))
+        }
+    };
+    let px_size = config.px_size;
+    let load_document = |face: &Face| -> io::Result<Document> {
+        let mut doc = match is_image_path(Path::new(input)) {
+            true => {
+                let (doc, redactions) =
+                    load_image_document(input, face, px_size, &RasterOptions::default())?;
+                eprintln!(
+                    " {} redaction boxes found in {} ({} sized from the box alone)",
+                    redactions.len(),
+                    input,
+                    redactions.iter().filter(|r| r.px_from_box).count()
+                );
+                doc
             }
+            false => Document::from(load_line_inputs(input)?),
+        };
+        if let Some(bias) = &bias {
+            correct_document_widths(&mut doc, bias, false);
         }
-
-        if norm > 0.0 { corr / norm.sqrt() } else { 0.0 }
-    }).sum::<f64>() / wm.axes.len() as f64
-}
-
-// ============================================
-// SIGNAL TRANSFORMATIONS AND ATTACKS
-// ============================================
-
-pub fn add_noise(signal: &mut [f64], amplitude: f64) {
-    let mut rng = rand::thread_rng();
-    for v in signal {
-        *v += rng.gen_range(-amplitude..amplitude);
+        Ok(doc)
+    };
+    if let Some(paths) = flag_value(args, "--fonts") {
+        // these follow one font's glyphs or one pipeline's decisions
+        if let Some(flag) = ["--font", "--reference-fonts", "--audit", "--trace-line"]
+            .into_iter()
+            .find(|f| flag_value(args, f).is_some())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} cannot be combined with --fonts", flag),
+            ));
+        }
+        let faces: Vec<(&str, Face<'static>)> = paths
+            .split(',')
+            .map(|p| Ok((p, load_font(p)?)))
+            .collect::<io::Result<_>>()?;
+        let doc = load_document(&faces[0].1)?;
+        let tables: Vec<HashMap<char, f32>> = faces
+            .iter()
+            .map(|(_, f)| build_glyph_widths(f, config.px_size))
+            .collect();
+        let candidates: Vec<FontCandidate> = faces
+            .iter()
+            .zip(&tables)
+            .map(|((name, face), glyphs)| FontCandidate {
+                name: name.to_string(),
+                face,
+                glyphs,
+                prior: 1.0 / faces.len() as f32,
+            })
+            .collect();
+
+        let mixed = restore_marginalized(&candidates, &config, &doc, configure)?;
+        for font in &mixed.fonts {
+            eprintln!(" {:<40} posterior {:.3}", font.name, font.posterior);
+        }
+        let mut results = RestorationResults::from_document(
+            &mixed.marginal,
+            &mixed.targets,
+            model.as_ref(),
+            top_k,
+        )
+        .with_costs(&mixed.costs)
+        .with_diagnoses(mixed.diagnoses);
+        if let Some(a) = applied.clone() {
+            results = results.with_feedback(a);
+        }
+        if let Some(path) = flag_value(args, "--entities") {
+            EntityReport::from_results(&results)
+                .write(format, path)
+                .map_err(output_error)?;
+        }
+        results
+            .write(format, flag_value(args, "--out"))
+            .map_err(output_error)?;
+        return Ok(RunSummary::from_results("restore", &results));
     }
-}
-
-pub fn scale_signal(signal: &mut [f64], factor: f64) {
-    for v in signal {
-        *v *= factor;
+    let mut doc = load_document(&face)?;
+    let mut restore = configure(RestorePipeline::new(&face, &glyphs, config));
+    if !approximate.is_empty() {
+        restore = restore.with_approximate_widths(&approximate);
     }
-}
-
-pub fn crop_signal(signal: &[f64], keep_ratio: f64) -> Vec<f64> {
-    let keep = (signal.len() as f64 * keep_ratio) as usize;
-    signal[..keep].to_vec()
-}
-
-pub fn permute_signal(signal: &mut [f64]) {
-    let mut rng = rand::thread_rng();
-    use rand::seq::SliceRandom;
-    signal.shuffle(&mut rng);
-}
-
-pub fn recovery_ratio(
-    original_score: f64,
-    modified_score: f64,
-) -> f64 {
-    if original_score.abs() < 1e-6 {
-        0.0
-    } else {
-        modified_score / original_score
+    if flag_value(args, "--trace-line").is_some() {
+        restore = restore.with_trace(
+            parse_flag(args, "--trace-line", 1)?,
+            flag_value(args, "--trace-truth"),
+        );
     }
-}
-
-// ============================================
-// PHASE-INVARIANT WATERMARK SCORING
-// ============================================
-
-pub fn phase_invariant_score(signal: &[f64], lattice: &[f64]) -> f64 {
-    signal.iter()
-        .zip(lattice)
-        .map(|(s, v)| (s * v).powi(2))
-        .sum::<f64>()
-        .sqrt()
-}
-
-// ============================================
-// ANCHOR-AWARE WATERMARKING
-// ============================================
-
-#[derive(Clone, Debug)]
-pub struct Anchor {
-    pub text: String,
-    pub bbox_width: f64,
-    pub position: usize,
-}
-
-pub fn anchor_lattice(anchor: &Anchor, len: usize) -> Vec<f64> {
-    let freq = anchor.bbox_width / 10.0;
-    (0..len)
-        .map(|i| ((i as f64) * freq).sin())
-        .collect()
-}
-
-pub fn combined_anchor_lattice(
-    anchors: &[Anchor],
-    len: usize,
-) -> Vec<f64> {
-    let mut lattice = vec![0.0; len];
-    for a in anchors {
-        let local = anchor_lattice(a, len);
-        for i in 0..len {
-            lattice[i] += local[i];
+    let costs = restore.run(&mut doc)?;
+    if let Some(path) = flag_value(args, "--audit") {
+        restore.audit_log().write(path).map_err(output_error)?;
+    }
+    if let Some(trace) = restore.search_trace() {
+        match flag_value(args, "--trace-out") {
+            Some(path) => trace.write(path, top_k).map_err(output_error)?,
+            None => eprint!("{}", trace.to_text(top_k)),
+        }
+        if let Some(path) = flag_value(args, "--trace-graph") {
+            trace.graph().write(path).map_err(output_error)?;
         }
     }
-    lattice
-}
-
-// ============================================
-// PDF BBOX EXTRACTION
-// ============================================
-
-pub fn bbox_signal(widths: &[f64]) -> Vec<f64> {
-    let norm = widths.iter().map(|w| w * w).sum::<f64>().sqrt();
-    if norm > 0.0 {
-        widths.iter().map(|w| w / norm).collect()
-    } else {
-        widths.to_vec()
+    let diagnoses = restore.diagnose(&doc);
+
+    let mut results =
+        RestorationResults::from_document(&doc, &restore.line_targets(&doc), model.as_ref(), top_k)
+            .with_costs(&costs)
+            .with_diagnoses(diagnoses)
+            .with_approximate_widths(&approximate);
+    if let Some(a) = applied {
+        results = results.with_feedback(a);
     }
-}
-
-pub fn extract_bboxes_mock(widths: &[f64]) -> Vec<f64> {
-    widths.to_vec()
-}
-
-// ============================================
-// 3D MESH WATERMARKING
-// ============================================
-
-#[derive(Clone, Debug)]
-pub struct Mesh {
-    pub vertices: Vec<[f64; 3]>,
-    pub edges: Vec<(usize, usize)>,
-}
-
-pub fn edge_lengths(mesh: &Mesh) -> Vec<f64> {
-    mesh.edges.iter().map(|(a, b)| {
-        let va = mesh.vertices[*a];
-        let vb = mesh.vertices[*b];
-        ((va[0] - vb[0]).powi(2)
-            + (va[1] - vb[1]).powi(2)
-            + (va[2] - vb[2]).powi(2)).sqrt()
-    }).collect()
-}
-
-pub fn mesh_watermark(signal: &[f64], lattice: &[f64]) -> f64 {
-    phase_invariant_score(signal, lattice)
-}
-
-// ============================================
-// FFT-BASED BLOCK PROCESSING SYSTEM
-// ============================================
-
-pub fn split_into_blocks(signal: &[f64], block_size: usize) -> Vec<&[f64]> {
-    signal
-        .chunks(block_size)
-        .filter(|b| b.len() == block_size)
-        .collect()
-}
-
-pub fn fft_magnitude(block: &[f64]) -> Vec<f64> {
-    use rustfft::{FftPlanner, num_complex::Complex};
-    
-    let mut planner = FftPlanner::new();
-    let fft = planner.plan_fft_forward(block.len());
-
-    let mut buffer: Vec<Complex<f64>> =
-        block.iter().map(|&x| Complex::new(x, 0.0)).collect();
-
-    fft.process(&mut buffer);
-
-    buffer.iter().map(|c| c.norm()).collect()
-}
-
-pub fn block_energy(magnitudes: &[f64]) -> f64 {
-    magnitudes.iter().map(|v| v * v).sum::<f64>().sqrt()
-}
-
-// ============================================
-// MULTI-BASIS WATERMARKING SYSTEM
-// ============================================
-
-#[derive(Clone, Debug)]
-pub struct Basis {
-    pub lattice: Vec<f64>,
-    pub weight: f64,
-}
-
-pub fn project(signal: &[f64], lattice: &[f64]) -> Vec<f64> {
-    signal.iter()
-        .zip(lattice)
-        .map(|(s, l)| s * l)
-        .collect()
-}
-
-pub fn score_block_multi_basis(
-    block: &[f64],
-    bases: &[Basis],
-) -> f64 {
-    bases.iter().map(|b| {
-        let projected = project(block, &b.lattice);
-        let mag = fft_magnitude(&projected);
-        b.weight * block_energy(&mag)
-    }).sum()
-}
-
-pub fn invariant_signature_score(
-    signal: &[f64],
-    bases: &[Basis],
-    block_size: usize,
-) -> f64 {
-    let blocks = split_into_blocks(signal, block_size);
-
-    let scores: Vec<f64> = blocks.iter()
-        .map(|b| score_block_multi_basis(b, bases))
-        .collect();
-
-    // Median is robust to outliers and attacks
-    if scores.is_empty() {
-        return 0.0;
+    if let Some(path) = flag_value(args, "--entities") {
+        EntityReport::from_results(&results)
+            .write(format, path)
+            .map_err(output_error)?;
     }
-    
-    let mut sorted = scores.clone();
-    sorted.sort_by(|a, b| a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal));
-    sorted[sorted.len() / 2]
-}
-
-// ============================================
-// ANCHORS AND QUANTIZATION
-// ============================================
-
-pub fn quantize(w: f32) -> i32 {
-    (w * 10.0).round() as i32 // 0.1 px precision
-}
-
-pub fn anchor_bonus(
-    text: &str,
-    width: f32,
-    anchors: &HashMap<i32, String>,
-) -> f32 {
-    let key = quantize(width);
-    if let Some(anchor) = anchors.get(&key) {
-        if anchor == text {
-            return 5.0; // srong bonus for anchor match
-        }
+    results
+        .write(format, flag_value(args, "--out"))
+        .map_err(output_error)?;
+    Ok(RunSummary::from_results("restore", &results))
+}
+
+/// `audit-redaction <original.pdf> <redacted.pdf> [--format text|json|csv]
+/// [--out PATH] [--font PATH] [--px N] [--model PATH] [--dictionary PATH]
+/// [--top-k N] [--target-bits N]`: restores every redaction of the redacted
+/// file and reports which ones give their original text away, so they can be
+/// fixed before release. With a dictionary, also suggests the box padding
+/// that leaves `--target-bits` (default 3) of ambiguity among its words.
+fn run_audit_redaction(args: &[String]) -> io::Result<()> {
+    let [original, redacted] = [args.first(), args.get(1)].map(|a| {
+        a.filter(|p| !p.starts_with("--")).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "expected the original and the redacted PDF",
+            )
+        })
+    });
+    let (original, redacted) = (
+        redaction::load_pdf(original?)?,
+        redaction::load_pdf(redacted?)?,
+    );
+    let format: OutputFormat = parse_flag(args, "--format", OutputFormat::Text)?;
+    let top_k = parse_flag(args, "--top-k", 5usize)?;
+
+    let target_bits = parse_flag(args, "--target-bits", 3.0f32)?;
+
+    let mut config = RestoreConfig::default();
+    config.px_size = parse_flag(args, "--px", config.px_size)?;
+    let (px_size, tolerance) = (config.px_size, config.tolerance);
+    let face = flag_value(args, "--font").map_or_else(default_font, load_font)?;
+    let glyphs = build_glyph_widths(&face, px_size);
+    let model = flag_value(args, "--model")
+        .map(NGramModel::load_json)
+        .transpose()?;
+    let wordlist = flag_value(args, "--dictionary")
+        .map(fs::read_to_string)
+        .transpose()?;
+    let dictionary = wordlist.as_deref().map(Dictionary::from);
+
+    let mut restore = RestorePipeline::new(&face, &glyphs, config);
+    if let Some(m) = &model {
+        restore = restore.with_model(m);
     }
-    0.0
-}
-
-// ============================================
-// DOCUMENT STRUCTURES AND STABILIZATION
-// ============================================
-
-#[derive(Clone)]
-pub struct Line {
-    pub observed_width: f32,
-    pub beams: Vec<Beam>,
-}
-
-pub struct Document {
-    pub lines: Vec<Line>,
-}
-
-pub fn stabilize_document(doc: &mut Document) {
-    let mut anchors = HashMap::new();
-
-    // collect best anchors from each line
-    for line in &doc.lines {
-        if let Some(best) = line.beams.first() {
-            anchors.insert(quantize(line.observed_width), best.text.clone());
-        }
+    if let Some(d) = &dictionary {
+        restore = restore.with_dictionary(d, NearMissOptions::default());
     }
+    let mut audit = audit_redactions(&original, &redacted, &mut restore, px_size, top_k)?;
+    if let Some(d) = &dictionary {
+        audit =
+            audit.with_padding_advice(d, &glyphs, px_size, tolerance, model.as_ref(), target_bits);
+    }
+    audit
+        .write(format, flag_value(args, "--out"))
+        .map_err(output_error)
+}
+
+/// `attribute-producer <runs.json> [--format text|json|csv] [--out PATH]
+/// [--font PATH] [--px N] [--references NAME:runs.json,..]`: ranks the
+/// tools that may have laid out a suspect document by the spacing of its
+/// visible runs, `[{"text": .., "width": ..}]` as for `restore
+/// --export-lines`. The built-in fingerprints are rough;
+/// `--references` learns one per producer from runs of documents known to
+/// come from it and uses those instead.
+fn run_attribute_producer(args: &[String]) -> io::Result<()> {
+    let runs = args
+        .first()
+        .filter(|a| !a.starts_with("--"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "missing runs file"))?;
+    let format: OutputFormat = parse_flag(args, "--format", OutputFormat::Text)?;
+    let px_size = parse_flag(args, "--px", RestoreConfig::default().px_size)?;
+    let face = flag_value(args, "--font").map_or_else(default_font, load_font)?;
+    let glyphs = build_glyph_widths(&face, px_size);
+    let samples = |path: &str| -> io::Result<Vec<SpacingSample>> {
+        Ok(SpacingSample::from_runs(
+            &load_export_lines(path)?,
+            &face,
+            &glyphs,
+            px_size,
+        ))
+    };
 
-    eprintln!(" Found {} anchors for multi-line matching", anchors.len());
-
-    // rescore beams based on anchors
-    for line in &mut doc.lines {
-        for beam in &mut line.beams {
-            beam.score += anchor_bonus(
-                &beam.text,
-                line.observed_width,
-                &anchors,
-            );
+    let fingerprints = match flag_value(args, "--references") {
+        None => default_fingerprints(),
+        Some(list) => list
+            .split(',')
+            .map(|reference| {
+                let (name, path) = reference.split_once(':').ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("expected NAME:runs.json, got {}", reference),
+                    )
+                })?;
+                Ok(ProducerFingerprint::from_samples(name, &samples(path)?))
+            })
+            .collect::<io::Result<_>>()?,
+    };
+    let samples = samples(runs)?;
+    if samples.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} has no visible runs", runs),
+        ));
+    }
+    AttributionReport::new(&samples, &fingerprints)
+        .write(format, flag_value(args, "--out"))
+        .map_err(output_error)
+}
+
+/// `train-model <out.json> --corpora a.txt:0.7,b.txt:0.3 [--n 3]
+/// [--visible PATH[:W]] [--fit-sample PATH]`: trains one n-gram model per
+/// corpus and saves their weighted mixture. Weights default to 1; with
+/// `--fit-sample` they are fitted to that in-genre text instead.
+/// `--visible` adds the document's own unredacted text as a corpus, after
+/// the same cleanup `restore --visible` does.
+fn run_train_model(args: &[String]) -> io::Result<()> {
+    let out = args
+        .first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "missing output model path"))?;
+    let corpora = flag_value(args, "--corpora")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "--corpora is required"))?;
+    let n = parse_flag(args, "--n", 3usize)?;
+
+    let weighted = |spec: &str| -> io::Result<(String, f32)> {
+        match spec.rsplit_once(':') {
+            Some((path, w)) => Ok((
+                path.to_string(),
+                w.parse::<f32>().map_err(|e| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("invalid weight in '{}': {}", spec, e),
+                    )
+                })?,
+            )),
+            None => Ok((spec.to_string(), 1.0)),
         }
+    };
 
-        line.beams.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+    let mut mixture = CorpusMixture::new();
+    for spec in corpora.split(',') {
+        let (path, weight) = weighted(spec)?;
+        mixture = mixture.with_corpus(&path, NGramModel::train_from_file(&path, n)?, weight);
     }
-}
-
-// ============================================
-// PDF STRUCTURES AND INFERENCE
-// ============================================
-
-#[derive(Clone, Debug)]
-#[allow(dead_code)]
-pub struct BBox {
-    pub x: f32,
-    pub y: f32,
-    pub w: f32,
-    pub h: f32,
-}
-
-#[derive(Clone)]
-#[allow(dead_code)]
-pub struct PdfLine {
-    pub bbox: BBox,
-    pub width: f32,
-}
-
-pub fn create_pdf_lines(widths: &[f32]) -> Vec<PdfLine> {
-    widths
-        .iter()
-        .enumerate()
-        .map(|(i, &w)| PdfLine {
-            bbox: BBox {
-                x: 0.0,
-                y: (i as f32) * 20.0,
-                w,
-                h: 18.0,
-            },
-            width: w,
-        })
-        .collect()
-}
-
-// ============================================
-// FONT LOADING, GLYPH MEASUREMENT, AND BEAM SEARCH
-// ============================================
-
-pub fn load_font(path: &str) -> Face<'static> {
-    eprintln!(" Loading font: {}", path);
-    
-    if Path::new(path).exists() {
-        let data = fs::read(path).expect("font read failed");
-        return Face::parse(Box::leak(data.into_boxed_slice()), 0)
-            .expect("font parse failed");
-    }
-    
-    let alternatives = vec![
-        "C:\\Windows\\Fonts\\arial.ttf",
-        "C:\\Windows\\Fonts\\ArialMT.ttf",
-        "C:\\Windows\\Fonts\\calibrib.ttf",
-    ];
-    
-    for alt_path in alternatives {
-        if Path::new(alt_path).exists() {
-            eprintln!(" Using system font: {}", alt_path);
-            let data = fs::read(alt_path).expect("font read failed");
-            return Face::parse(Box::leak(data.into_boxed_slice()), 0)
-                .expect("font parse failed");
-        }
+    if let Some(spec) = flag_value(args, "--visible") {
+        let (path, weight) = weighted(spec)?;
+        let visible = VisibleText::normalize(&fs::read_to_string(&path)?);
+        mixture = mixture.with_corpus(
+            &path,
+            NGramModel::train_from_reader(visible.text.as_bytes(), n)?,
+            weight,
+        );
     }
-    
-    panic!(" Font not found: {}", path);
-}
-
-pub fn measure_text_kerning(
-    text: &str,
-    face: &Face,
-    _glyphs: &HashMap<char, f32>,
-    px_size: f32,
-) -> f32 {
-    let units_per_em = face.units_per_em() as f32;
-    let scale = px_size / units_per_em;
-
-    let mut total = 0.0;
-
-    for ch in text.chars() {
-        if let Some(glyph_id) = face.glyph_index(ch) {
-            if let Some(advance) = face.glyph_hor_advance(glyph_id) {
-                total += advance as f32 * scale;
-            }
-        }
+    if let Some(path) = flag_value(args, "--fit-sample") {
+        mixture.fit_weights(&fs::read_to_string(path)?)?;
+    }
+    for (corpus, weight) in mixture.corpora.iter().zip(mixture.normalized_weights()) {
+        eprintln!(" {:<40} weight {:.3}", corpus.name, weight);
+    }
+    mixture.build()?.save_json(out)
+}
+
+/// `watch <dir> [--once] [--interval SECS] [--manifest PATH] [--format
+/// text|json|csv] [--font PATH] [--px N] [--model PATH] [--dictionary PATH]
+/// [--top-k N]`: processes every PDF or screenshot (`.png`, `.jpg`,
+/// `.jpeg`) dropped into `dir`, writing
+/// `<name>.restoration.<ext>` next to it and recording each file in the
+/// batch manifest (`dir/manifest.json` unless `--manifest`). Polls every
+/// `--interval` seconds (default 5) until killed; `--once` processes what
+/// is there and exits, its summary counting files.
+fn run_watch(args: &[String]) -> io::Result<RunSummary> {
+    let dir = args
+        .first()
+        .filter(|a| !a.starts_with("--"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "missing folder to watch"))?;
+    let format: OutputFormat = parse_flag(args, "--format", OutputFormat::Text)?;
+    let top_k = parse_flag(args, "--top-k", 5usize)?;
+    let interval = parse_flag(args, "--interval", 5.0f32)?;
+    let once = args.iter().any(|a| a == "--once");
+
+    let mut config = RestoreConfig::default();
+    config.px_size = parse_flag(args, "--px", config.px_size)?;
+    let face = flag_value(args, "--font").map_or_else(default_font, load_font)?;
+    let glyphs = build_glyph_widths(&face, config.px_size);
+    let model = flag_value(args, "--model")
+        .map(NGramModel::load_json)
+        .transpose()?;
+    let wordlist = flag_value(args, "--dictionary")
+        .map(fs::read_to_string)
+        .transpose()?;
+    let dictionary = wordlist.as_deref().map(Dictionary::from);
+
+    let mut restore = RestorePipeline::new(&face, &glyphs, config);
+    if let Some(m) = &model {
+        restore = restore.with_model(m);
+    }
+    if let Some(d) = &dictionary {
+        restore = restore.with_dictionary(d, NearMissOptions::default());
     }
 
-    total
-}
-
-pub fn build_glyph_widths(face: &Face, px_size: f32) -> HashMap<char, f32> {
-    let units_per_em = face.units_per_em() as f32;
-    let scale = px_size / units_per_em;
-
-    let mut map = HashMap::new();
-
-    let ranges = [
-        (' '..='~'),              // ASCII
-        ('А'..='Я'),              // cyrillic uppercase
-        ('а'..='я'),
-        ('Ё'..='Ё'),
-        ('ё'..='ё'),
-    ];
-
-    for range in ranges {
-        for ch in range {
-            if let Some(glyph_id) = face.glyph_index(ch) {
-                if let Some(advance) = face.glyph_hor_advance(glyph_id) {
-                    map.insert(ch, advance as f32 * scale);
-                }
+    let mut folder = DropFolder::open(std::path::Path::new(dir))?;
+    if let Some(path) = flag_value(args, "--manifest") {
+        folder = folder.with_manifest(std::path::Path::new(path))?;
+    }
+    if once {
+        folder = folder.without_settling();
+    }
+    eprintln!(
+        " Watching {} ({} files in the manifest)",
+        dir,
+        folder.manifest.entries.len()
+    );
+    loop {
+        let done =
+            folder.poll(|pdf| restore_pdf(pdf, &mut restore, model.as_ref(), top_k, format))?;
+        for entry in &done {
+            match &entry.error {
+                Some(e) => eprintln!(" {:<40} failed: {}", entry.file, e),
+                None => eprintln!(
+                    " {:<40} {} lines -> {}",
+                    entry.file,
+                    entry.lines,
+                    entry.report.as_deref().unwrap_or("-")
+                ),
             }
         }
-    }
-
-    map
-}
-
-pub fn find_candidates(
-    target_width: f32,
-    glyphs: &HashMap<char, f32>,
-    dictionary: &[&str],
-    tolerance: f32,
-) -> Vec<(String, f32)> {
-    let mut out = vec![];
-
-    for &word in dictionary {
-        let w: f32 = word.chars()
-            .map(|c| glyphs.get(&c).copied().unwrap_or(0.0))
-            .sum();
-        let delta = (w - target_width).abs();
-
-        if delta <= tolerance {
-            out.push((word.to_string(), delta));
+        if !done.is_empty() {
+            let m = &folder.manifest;
+            eprintln!(
+                " Manifest: {} processed, {} failed",
+                m.count(EntryStatus::Processed),
+                m.count(EntryStatus::Failed)
+            );
+        }
+        if once {
+            return Ok(RunSummary::from_entries("watch", &done));
         }
+        std::thread::sleep(std::time::Duration::from_secs_f32(interval.max(0.1)));
     }
-
-    out.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-    out
 }
 
-#[derive(Clone)]
-#[allow(dead_code)]
-pub struct ScoreWeights {
-    pub width: f32,
-    pub word_len: f32,
-    pub spaces: f32,
-}
-
-#[derive(Clone)]
-pub struct Beam {
-    pub text: String,
-    pub width: f32,
-    pub score: f32,
-}
-
-#[allow(dead_code)]
-pub fn score_text(
-    text: &str,
-    measured_width: f32,
-    target_width: f32,
-    weights: &ScoreWeights,
-) -> f32 {
-    let width_error = (measured_width - target_width).abs();
-    let len = text.chars().count() as f32;
-    let spaces = text.matches(' ').count() as f32;
-
-    -weights.width * width_error
-        - weights.word_len * len
-        + weights.spaces * spaces
+/// `review <project.json> [--feedback PATH] [--model PATH]`: the review
+/// TUI. On exit, the session's approvals and rejections are merged into the
+/// feedback store (`restore_feedback.json` unless `--feedback`), which
+/// later `restore` runs in the same directory apply, or runs given the
+/// same `--feedback`; `--model` lets the n-gram
+/// weight learn too.
+fn run_review(path: &str, args: &[String]) -> io::Result<()> {
+    let mut project = review::ReviewProject::load(path)?;
+    review::run_review_tui(&mut project, path)?;
+
+    let feedback_path = flag_value(args, "--feedback").unwrap_or(DEFAULT_FEEDBACK_PATH);
+    let model = flag_value(args, "--model")
+        .map(NGramModel::load_json)
+        .transpose()?;
+    let mut feedback = FeedbackStore::load(feedback_path)?;
+    let learned = feedback.learn(&project, &RestoreConfig::default().weights, model.as_ref());
+    if learned > 0 {
+        feedback.save(feedback_path).map_err(output_error)?;
+        eprintln!(" Learned {} decisions into {}", learned, feedback_path);
+    }
+    Ok(())
+}
+
+/// `upgrade-results <results.json> [--out PATH]`: rewrites a JSON results
+/// file of any earlier schema version in the current one.
+fn run_upgrade_results(args: &[String]) -> io::Result<()> {
+    let input = args.first().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "missing results file to upgrade",
+        )
+    })?;
+    RestorationResults::from_json(&fs::read_to_string(input)?)?
+        .write(OutputFormat::Json, flag_value(args, "--out"))
+        .map_err(output_error)
+}
+
+/// `width-solve --width PX --pattern P [--font PATH] [--px N] [--tolerance
+/// PX] [--model PATH] [--per-state N] [--top-k N]`: the solver on its own,
+/// without a document. Prints the `--top-k` (10) texts matching `P` (see
+/// `WidthPattern`, e.g. `"[A-Z][a-z]+ [0-9]{4}"`) that set within the
+/// tolerance of the width, best first, after how many match in all.
+/// Without `--model` only the width ranks them, so most tie; the outcome
+/// is then `partial`. `--per-state` is the number of prefixes kept per
+/// pattern position and width (see `solve_pattern`).
+fn run_width_solve(args: &[String]) -> io::Result<RunSummary> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    let width: f32 = parse_flag(args, "--width", f32::NAN)?;
+    if !width.is_finite() || width <= 0.0 {
+        return Err(invalid("--width is required and must be positive".into()));
+    }
+    let pattern: WidthPattern = flag_value(args, "--pattern")
+        .ok_or_else(|| invalid("--pattern is required".into()))?
+        .parse()
+        .map_err(|e| invalid(format!("invalid --pattern: {}", e)))?;
+    let top_k = parse_flag(args, "--top-k", 10usize)?;
+    let per_state = parse_flag(args, "--per-state", DEFAULT_PREFIXES_PER_STATE)?;
+
+    let mut config = RestoreConfig::default();
+    config.px_size = parse_flag(args, "--px", config.px_size)?;
+    config.tolerance = parse_flag(args, "--tolerance", config.tolerance)?;
+    let face = flag_value(args, "--font").map_or_else(default_font, load_font)?;
+    let glyphs = build_glyph_widths(&face, config.px_size);
+    let model = flag_value(args, "--model")
+        .map(NGramModel::load_json)
+        .transpose()?;
+
+    let total = count_pattern_matches(
+        &face,
+        &glyphs,
+        config.px_size,
+        width,
+        config.tolerance,
+        &pattern,
+    );
+    let stats = SearchStats::default();
+    // one more than shown, to tell whether the last shown ties with the rest
+    let matches = solve_pattern(
+        &face,
+        &glyphs,
+        config.px_size,
+        width,
+        config.tolerance,
+        &pattern,
+        &config.weights,
+        model.as_ref(),
+        per_state,
+        top_k.max(1) + 1,
+        &stats,
+    );
+    let tied = matches.len() > 1 && matches[0].score - matches[1].score < 1e-4;
+    eprintln!(
+        " about {:.0} texts match within {:.2} px ({} candidates scored)",
+        total,
+        config.tolerance,
+        stats.candidates_evaluated()
+    );
+    if tied && model.is_none() {
+        eprintln!(" the best matches tie on width; --model ranks them");
+    }
+    for (rank, m) in matches.iter().take(top_k).enumerate() {
+        println!(
+            "{:>3}. {:<30} {:>8.2} px  {:>+6.2}  score {:.2}",
+            rank + 1,
+            m.text,
+            m.width,
+            m.width - width,
+            m.score
+        );
+    }
+    Ok(RunSummary::from_matches("width-solve", matches.len(), tied))
 }
 
-#[allow(dead_code)]
-pub fn beam_search(
-    face: &Face,
-    _glyphs: &HashMap<char, f32>,
-    px_size: f32,
-    target_width: f32,
-    alphabet: &[char],
-    weights: &ScoreWeights,
-    beam_width: usize,
-    max_len: usize,
-) -> Vec<Beam> {
-    let mut beams = vec![Beam {
-        text: String::new(),
-        width: 0.0,
-        score: 0.0,
-    }];
-
-    for _ in 0..max_len {
-        let mut next = Vec::new();
-
-        for beam in &beams {
-            for &ch in alphabet {
-                let mut new_text = beam.text.clone();
-                new_text.push(ch);
-
-                let new_width = measure_text_kerning(
-                    &new_text,
-                    face,
-                    _glyphs,
-                    px_size,
-                );
-
-                if new_width > target_width + 20.0 {
-                    continue;
+/// Every command exits with the code of its outcome and ends stderr with
+/// its summary as one JSON line (see `summary::Outcome`); `--summary PATH`
+/// also writes the summary to PATH.
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let start = std::time::Instant::now();
+    let command = args.get(1).map_or("", String::as_str);
+    let done = |r: io::Result<()>| r.map(|()| RunSummary::done(command));
+    let result = match command {
+        "review" => match args.get(2) {
+            Some(path) => done(run_review(path, &args[3..])),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "usage: restore_watermark review <project.json> [--feedback PATH] [--model PATH]",
+            )),
+        },
+        "restore" => run_restore(&args[2..]),
+        "upgrade-results" => done(run_upgrade_results(&args[2..])),
+        "audit-redaction" => done(run_audit_redaction(&args[2..])),
+        "attribute-producer" => done(run_attribute_producer(&args[2..])),
+        "train-model" => done(run_train_model(&args[2..])),
+        "watch" => run_watch(&args[2..]),
+        "width-solve" => run_width_solve(&args[2..]),
+        "self-test" => done(run_test_suite()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{}\nusage: restore_watermark <restore|width-solve|watch|audit-redaction|\
+                 attribute-producer|train-model|upgrade-results|review|self-test> [ARGS]",
+                match command {
+                    "" => "missing command".to_string(),
+                    _ => format!("unknown command {:?}", command),
                 }
+            ),
+        )),
+    };
 
-                let score = score_text(
-                    &new_text,
-                    new_width,
-                    target_width,
-                    weights,
-                );
-
-                next.push(Beam {
-                    text: new_text,
-                    width: new_width,
-                    score,
-                });
-            }
+    let summary = result
+        .unwrap_or_else(|e| {
+            eprintln!("error: {}", e);
+            RunSummary::from_error(command, &e)
+        })
+        .with_elapsed(start.elapsed());
+    if let Some(path) = flag_value(&args, "--summary") {
+        if let Err(e) = fs::write(path, summary.to_json()) {
+            eprintln!("error: could not write summary to {}: {}", path, e);
         }
-
-        next.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
-        beams = next.into_iter().take(beam_width).collect();
     }
-
-    beams
+    eprintln!("{}", summary.to_json());
+    std::process::exit(summary.exit_code);
 }
 
-fn main() {
+/// `self-test`: runs every test phase against the bundled font.
+fn run_test_suite() -> io::Result<()> {
     eprintln!("\n╔════════════════════════════════════════════════════════════════╗");
     eprintln!("║        RESTORE_WATERMARK: Text restore system       ║");
     eprintln!("╚════════════════════════════════════════════════════════════════╝\n");
 
     eprintln!(" Initializing...");
-    let face = load_font("fonts/DejaVuSans.ttf");
-    
+    let face = default_font()?;
+
     let glyphs = build_glyph_widths(&face, 16.0);
     eprintln!(" Glyps loaded: {} symbols\n", glyphs.len());
 
     // Run extended tests with advanced watermarks
     tests::run_all_tests_with_advanced_watermarks(&face, &glyphs);
+    Ok(())
 }
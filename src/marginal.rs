@@ -0,0 +1,186 @@
+// ============================================
+// MARGINALIZING OVER CANDIDATE FONTS
+// ============================================
+
+use crate::diagnosis::LineDiagnosis;
+use crate::pipeline::{CostReport, RestoreConfig, RestorePipeline};
+use crate::{Beam, Document};
+use std::collections::HashMap;
+use std::io;
+use ttf_parser::Face;
+
+/// Log-evidence charged for a line a font finds no candidate for at all,
+/// far below any beam score.
+const NO_CANDIDATE_LOG_EVIDENCE: f32 = -50.0;
+
+/// One font the document may be set in, with its prior probability.
+pub struct FontCandidate<'a> {
+    pub name: String,
+    pub face: &'a Face<'a>,
+    pub glyphs: &'a HashMap<char, f32>,
+    pub prior: f32,
+}
+
+/// How well one font explains the document. `log_evidence` sums, over the
+/// lines, the log of the summed beam probabilities (`exp(score)`);
+/// `posterior` is normalized over the candidate fonts.
+#[derive(Clone, Debug)]
+pub struct FontPosterior {
+    pub name: String,
+    pub log_evidence: f32,
+    pub posterior: f32,
+}
+
+/// Per-font restorations and the document marginalized over them.
+pub struct FontMarginal {
+    /// Fonts most likely first.
+    pub fonts: Vec<FontPosterior>,
+    /// The document as restored under each font, in candidate order.
+    pub per_font: Vec<Document>,
+    /// Beams scored `log Σ_f P(f | doc) · exp(score_f)` over the fonts that
+    /// produced them; widths are the most likely such font's.
+    pub marginal: Document,
+    /// Work summed over the fonts, line by line.
+    pub costs: CostReport,
+    /// `marginal` diagnosed by the most likely font's pipeline.
+    pub diagnoses: Vec<Option<LineDiagnosis>>,
+    /// Width and tolerance of each line under that font's pipeline.
+    pub targets: Vec<(f32, f32)>,
+}
+
+fn log_sum_exp(values: impl IntoIterator<Item = f32>) -> f32 {
+    let values: Vec<f32> = values.into_iter().collect();
+    let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    if max == f32::NEG_INFINITY {
+        return max;
+    }
+    max + values.iter().map(|v| (v - max).exp()).sum::<f32>().ln()
+}
+
+impl FontMarginal {
+    pub fn best_font(&self) -> Option<&FontPosterior> {
+        self.fonts.first()
+    }
+}
+
+/// Restores `doc` under every candidate font instead of choosing one font
+/// upfront, then weighs each font's candidates by how well that font
+/// explains the whole document. `configure` sets up each font's pipeline the
+/// same way (model, dictionary, locale...); hooks added there run once per
+/// font and must not change the number of lines. The marginal document is
+/// diagnosed by the pipeline of the most likely font.
+pub fn restore_marginalized<'a>(
+    fonts: &'a [FontCandidate<'a>],
+    config: &RestoreConfig,
+    doc: &Document,
+    configure: impl Fn(RestorePipeline<'a>) -> RestorePipeline<'a>,
+) -> io::Result<FontMarginal> {
+    let mut per_font = vec![];
+    let mut pipelines = vec![];
+    let mut log_joint = vec![];
+    let mut costs = CostReport::default();
+    for font in fonts {
+        let mut restored = doc.clone();
+        let mut pipeline = configure(RestorePipeline::new(font.face, font.glyphs, config.clone()));
+        let cost = pipeline.run(&mut restored)?;
+        if restored.lines.len() != doc.lines.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "pipeline for font '{}' changed the number of lines",
+                    font.name
+                ),
+            ));
+        }
+
+        let evidence: f32 = restored
+            .lines
+            .iter()
+            .map(|l| match l.beams.is_empty() {
+                true => NO_CANDIDATE_LOG_EVIDENCE,
+                false => log_sum_exp(l.beams.iter().map(|b| b.score)),
+            })
+            .sum();
+        log_joint.push((evidence, font.prior.max(f32::MIN_POSITIVE).ln() + evidence));
+        per_font.push(restored);
+        pipelines.push(pipeline);
+
+        costs.lines.resize_with(cost.lines.len(), Default::default);
+        for (sum, line) in costs.lines.iter_mut().zip(&cost.lines) {
+            sum.elapsed_ms += line.elapsed_ms;
+            sum.beams_expanded += line.beams_expanded;
+            sum.candidates_evaluated += line.candidates_evaluated;
+        }
+        costs.total.elapsed_ms += cost.total.elapsed_ms;
+        costs.total.beams_expanded += cost.total.beams_expanded;
+        costs.total.candidates_evaluated += cost.total.candidates_evaluated;
+    }
+
+    let total = log_sum_exp(log_joint.iter().map(|j| j.1));
+    let log_posterior: Vec<f32> = log_joint.iter().map(|j| j.1 - total).collect();
+
+    let mut marginal = doc.clone();
+    for (i, line) in marginal.lines.iter_mut().enumerate() {
+        // text -> (per-font log terms, width under the most likely font)
+        let mut terms: HashMap<&str, (Vec<f32>, f32, f32)> = HashMap::new();
+        for (f, restored) in per_font.iter().enumerate() {
+            for b in &restored.lines[i].beams {
+                let term = log_posterior[f] + b.score;
+                let entry = terms
+                    .entry(&b.text)
+                    .or_insert((vec![], b.width, f32::NEG_INFINITY));
+                entry.0.push(term);
+                if log_posterior[f] > entry.2 {
+                    entry.1 = b.width;
+                    entry.2 = log_posterior[f];
+                }
+            }
+        }
+
+        let mut beams: Vec<Beam> = terms
+            .into_iter()
+            .map(|(text, (logs, width, _))| Beam {
+                text: text.to_string(),
+                width,
+                score: log_sum_exp(logs),
+            })
+            .collect();
+        beams.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap()
+                .then_with(|| a.text.cmp(&b.text))
+        });
+        beams.truncate(config.beam_width);
+        line.beams = beams;
+    }
+
+    let best = log_posterior
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(f, _)| &pipelines[f]);
+    let diagnoses = best.map_or(vec![], |p| p.diagnose(&marginal));
+    let targets = best.map_or(vec![], |p| p.line_targets(&marginal));
+
+    let mut ranked: Vec<FontPosterior> = fonts
+        .iter()
+        .zip(&log_joint)
+        .zip(&log_posterior)
+        .map(|((font, joint), lp)| FontPosterior {
+            name: font.name.clone(),
+            log_evidence: joint.0,
+            posterior: lp.exp(),
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.posterior.total_cmp(&a.posterior));
+
+    Ok(FontMarginal {
+        fonts: ranked,
+        per_font,
+        marginal,
+        costs,
+        diagnoses,
+        targets,
+    })
+}
@@ -0,0 +1,166 @@
+// ============================================
+// CORPUS MIXING FOR DOMAIN ADAPTATION
+// ============================================
+
+use crate::NGramModel;
+use rustc_hash::FxHashMap;
+use std::io;
+
+/// EM rounds run by `fit_weights`; on a few hundred characters of sample the
+/// weights stop moving well before this.
+const FIT_ITERATIONS: usize = 50;
+
+/// One corpus's model and its share of the mixture. Weights need not sum
+/// to one; they are normalized when used.
+pub struct MixedCorpus {
+    pub name: String,
+    pub model: NGramModel,
+    pub weight: f32,
+}
+
+/// Several corpora of the same order combined into one model, e.g. 0.7
+/// legal + 0.3 general, so a small in-genre corpus is not drowned out by a
+/// large general one.
+#[derive(Default)]
+pub struct CorpusMixture {
+    pub corpora: Vec<MixedCorpus>,
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Grams of `text` the way `ngram_log_prob` reads them.
+fn grams(text: &str, n: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    chars
+        .windows(n.max(1))
+        .map(|w| w.iter().collect())
+        .collect()
+}
+
+impl CorpusMixture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_corpus(mut self, name: &str, model: NGramModel, weight: f32) -> Self {
+        self.corpora.push(MixedCorpus {
+            name: name.to_string(),
+            model,
+            weight,
+        });
+        self
+    }
+
+    fn order(&self) -> io::Result<usize> {
+        let n = self
+            .corpora
+            .first()
+            .map(|c| c.model.n)
+            .ok_or_else(|| invalid("no corpora to mix".into()))?;
+        match self.corpora.iter().find(|c| c.model.n != n) {
+            Some(c) => Err(invalid(format!(
+                "corpus '{}' has order {}, expected {}",
+                c.name, c.model.n, n
+            ))),
+            None => Ok(n),
+        }
+    }
+
+    /// Weights scaled to sum to one, in corpus order. Negative weights count
+    /// as zero.
+    pub fn normalized_weights(&self) -> Vec<f32> {
+        let total: f32 = self.corpora.iter().map(|c| c.weight.max(0.0)).sum();
+        self.corpora
+            .iter()
+            .map(|c| {
+                if total > 0.0 {
+                    c.weight.max(0.0) / total
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    /// Replaces the weights with the ones that best explain `sample`, a bit
+    /// of text from the target genre: EM over the interpolation weights of
+    /// the corpus models, maximizing the sample's likelihood. Returns the
+    /// fitted weights.
+    pub fn fit_weights(&mut self, sample: &str) -> io::Result<Vec<f32>> {
+        let n = self.order()?;
+        let grams = grams(sample, n);
+        if grams.is_empty() {
+            return Err(invalid(format!("sample is shorter than one {}-gram", n)));
+        }
+
+        // per gram, its probability under each corpus model
+        let probs: Vec<Vec<f32>> = grams
+            .iter()
+            .map(|g| self.corpora.iter().map(|c| c.model.prob(g)).collect())
+            .collect();
+        let k = self.corpora.len();
+        let mut weights = vec![1.0 / k as f32; k];
+
+        for _ in 0..FIT_ITERATIONS {
+            let mut next = vec![0.0f32; k];
+            for p in &probs {
+                let mix: f32 = weights.iter().zip(p).map(|(w, p)| w * p).sum();
+                if mix <= 0.0 {
+                    continue;
+                }
+                for ((acc, w), p) in next.iter_mut().zip(&weights).zip(p) {
+                    *acc += w * p / mix;
+                }
+            }
+            let total: f32 = next.iter().sum();
+            if total <= 0.0 {
+                break;
+            }
+            weights = next.iter().map(|w| w / total).collect();
+        }
+
+        for (c, &w) in self.corpora.iter_mut().zip(&weights) {
+            c.weight = w;
+        }
+        Ok(weights)
+    }
+
+    /// One model whose counts are each corpus's counts rescaled so corpus i
+    /// holds its weight's share of the combined mass, whatever its size.
+    /// Coverage is kept: a gram seen in any corpus with a positive weight
+    /// keeps a count of at least one. Smoothing is the first corpus's.
+    pub fn build(&self) -> io::Result<NGramModel> {
+        let n = self.order()?;
+        let weights = self.normalized_weights();
+        if weights.iter().all(|&w| w == 0.0) {
+            return Err(invalid("all mixture weights are zero".into()));
+        }
+
+        let mass: f64 = self.corpora.iter().map(|c| c.model.total as f64).sum();
+        let mut scaled: FxHashMap<&str, f64> = FxHashMap::default();
+        for (c, &w) in self.corpora.iter().zip(&weights) {
+            if w == 0.0 || c.model.total == 0 {
+                continue;
+            }
+            let scale = w as f64 * mass / c.model.total as f64;
+            for (gram, &count) in &c.model.counts {
+                *scaled.entry(gram).or_insert(0.0) += count as f64 * scale;
+            }
+        }
+
+        let mut model = NGramModel {
+            n,
+            smoothing: self.corpora[0].model.smoothing,
+            ..Default::default()
+        };
+        for (gram, count) in scaled {
+            let count = (count.round() as usize).max(1);
+            model.counts.insert(gram.to_string(), count);
+            model.total += count;
+        }
+        model.finalize();
+        Ok(model)
+    }
+}
@@ -0,0 +1,501 @@
+// ============================================
+// STRUCTURED RESULT OUTPUT (TEXT / JSON / CSV)
+// ============================================
+
+use crate::diagnosis::LineDiagnosis;
+use crate::feedback::AppliedFeedback;
+use crate::pipeline::{CostReport, LineCost};
+use crate::widthmodel::ApproximateWidths;
+use crate::{anchor_bonus, ngram_log_prob, quantize, Document, NGramModel};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::str::FromStr;
+
+/// Version of the JSON results written by `to_json`.
+///
+/// | Version | Layout                                                    |
+/// |---------|-----------------------------------------------------------|
+/// | 1       | unversioned; top-level `lines` and `total_cost`           |
+/// | 2       | `schema_version`; lines grouped into `pages`              |
+///
+/// Within a version, fields are only ever added, as optional fields;
+/// nothing is renamed, retyped or removed, so readers should ignore fields
+/// they do not know. Any other change bumps the version, and
+/// `RestorationResults::from_json` keeps reading every earlier one.
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Csv,
+}
+
+impl OutputFormat {
+    /// File extension of a report in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Text => "txt",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" | "table" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "unknown output format '{}' (expected text, json or csv)",
+                    other
+                ),
+            )),
+        }
+    }
+}
+
+/// Parts of a beam score. `width_error` is in px from the width the line
+/// was searched against (see `LineResult::target_width`), `ngram` is the unweighted
+/// log-likelihood (0 without a model) and `anchor_bonus` what the anchor
+/// pass added. `width_uncertainty` is set when the width rests on predicted
+/// advances: the standard error of the width, in px.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScoreComponents {
+    pub width_error: f32,
+    pub ngram: f32,
+    pub anchor_bonus: f32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width_uncertainty: Option<f32>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CandidateResult {
+    pub rank: usize,
+    pub text: String,
+    pub width: f32,
+    pub score: f32,
+    pub components: ScoreComponents,
+    pub confidence: f32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LineResult {
+    /// Index in the document, 1-based and counted across pages.
+    pub line: usize,
+    /// Written as the enclosing `PageResult`.
+    #[serde(skip)]
+    pub page: u32,
+    pub observed_width: f32,
+    /// Width the line was searched against, when it differs from
+    /// `observed_width`: calibrated, or a ragged paragraph end.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_width: Option<f32>,
+    /// Tolerance the line was searched against, set for every line that
+    /// had a target: the configured one, or the calibration's for its width.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tolerance: Option<f32>,
+    /// Text was read from the source, not inferred.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub exact: bool,
+    pub candidates: Vec<CandidateResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<LineCost>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagnosis: Option<LineDiagnosis>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct RestorationResults {
+    pub lines: Vec<LineResult>,
+    pub total_cost: Option<LineCost>,
+    /// Review feedback the run applied, if any.
+    pub feedback: Option<AppliedFeedback>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PageResult {
+    pub page: u32,
+    pub lines: Vec<LineResult>,
+}
+
+/// The JSON results file, version `SCHEMA_VERSION`.
+#[derive(Serialize, Deserialize)]
+struct ResultsFile {
+    schema_version: u32,
+    pages: Vec<PageResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    total_cost: Option<LineCost>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    feedback: Option<AppliedFeedback>,
+}
+
+/// Results written before the schema was versioned.
+#[derive(Deserialize)]
+struct ResultsFileV1 {
+    lines: Vec<LineResult>,
+    #[serde(default)]
+    total_cost: Option<LineCost>,
+}
+
+/// Softmax of the scores, so the confidences of one line sum to 1.
+pub fn softmax_confidence(scores: &[f32]) -> Vec<f32> {
+    let max = scores.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let exps: Vec<f32> = scores.iter().map(|s| (s - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.iter()
+        .map(|e| if sum > 0.0 { e / sum } else { 0.0 })
+        .collect()
+}
+
+/// Anchor table of a stabilized document: the current best text per
+/// quantized width.
+fn document_anchors(doc: &Document) -> HashMap<i32, String> {
+    doc.lines
+        .iter()
+        .filter_map(|l| {
+            l.beams
+                .first()
+                .map(|b| (quantize(l.observed_width), b.text.clone()))
+        })
+        .collect()
+}
+
+pub(crate) fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+impl RestorationResults {
+    /// Collects the top `top_k` beams of every line of a restored document.
+    /// `targets` are the width and tolerance each line was searched against
+    /// (`RestorePipeline::line_targets`); lines without one are measured
+    /// against their observed width.
+    pub fn from_document(
+        doc: &Document,
+        targets: &[(f32, f32)],
+        model: Option<&NGramModel>,
+        top_k: usize,
+    ) -> Self {
+        let anchors = document_anchors(doc);
+
+        let lines = doc
+            .lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let beams = &line.beams[..line.beams.len().min(top_k)];
+                let confidence =
+                    softmax_confidence(&beams.iter().map(|b| b.score).collect::<Vec<_>>());
+                let target = targets.get(i).copied();
+                let target_width = target.map_or(line.observed_width, |t| t.0);
+
+                LineResult {
+                    line: i + 1,
+                    page: line.hints.page.unwrap_or(1),
+                    observed_width: line.observed_width,
+                    target_width: target.filter(|t| t.0 != line.observed_width).map(|t| t.0),
+                    tolerance: target.map(|t| t.1),
+                    exact: line.hints.exact_text.is_some(),
+                    candidates: beams
+                        .iter()
+                        .zip(confidence)
+                        .enumerate()
+                        .map(|(rank, (b, confidence))| CandidateResult {
+                            rank: rank + 1,
+                            text: b.text.clone(),
+                            width: b.width,
+                            score: b.score,
+                            components: ScoreComponents {
+                                width_error: (b.width - target_width).abs(),
+                                ngram: model.map_or(0.0, |m| ngram_log_prob(&b.text, m)),
+                                anchor_bonus: anchor_bonus(&b.text, line.observed_width, &anchors),
+                                width_uncertainty: None,
+                            },
+                            confidence,
+                        })
+                        .collect(),
+                    cost: None,
+                    diagnosis: None,
+                }
+            })
+            .collect();
+
+        RestorationResults {
+            lines,
+            total_cost: None,
+            feedback: None,
+        }
+    }
+
+    /// Attaches the per-line costs of the pipeline run that produced the
+    /// document.
+    pub fn with_costs(mut self, report: &CostReport) -> Self {
+        for (line, cost) in self.lines.iter_mut().zip(&report.lines) {
+            line.cost = Some(cost.clone());
+        }
+        self.total_cost = Some(report.total.clone());
+        self
+    }
+
+    /// Records the review feedback the run applied.
+    pub fn with_feedback(mut self, feedback: AppliedFeedback) -> Self {
+        self.feedback = Some(feedback);
+        self
+    }
+
+    /// Attaches the diagnoses of unresolved lines, indexed like the lines.
+    pub fn with_diagnoses(mut self, diagnoses: Vec<Option<LineDiagnosis>>) -> Self {
+        for (line, diagnosis) in self.lines.iter_mut().zip(diagnoses) {
+            line.diagnosis = diagnosis;
+        }
+        self
+    }
+
+    /// Flags the candidates whose width uses predicted advances.
+    pub fn with_approximate_widths(mut self, approximate: &ApproximateWidths) -> Self {
+        for c in self.lines.iter_mut().flat_map(|l| &mut l.candidates) {
+            let u = approximate.uncertainty(&c.text);
+            c.components.width_uncertainty = (u > 0.0).then_some(u);
+        }
+        self
+    }
+
+    /// Lines grouped by page, pages in ascending order.
+    pub fn pages(&self) -> Vec<PageResult> {
+        let mut pages: BTreeMap<u32, Vec<LineResult>> = BTreeMap::new();
+        for line in &self.lines {
+            pages.entry(line.page).or_default().push(line.clone());
+        }
+        pages
+            .into_iter()
+            .map(|(page, lines)| PageResult { page, lines })
+            .collect()
+    }
+
+    pub fn to_json(&self) -> io::Result<String> {
+        let file = ResultsFile {
+            schema_version: SCHEMA_VERSION,
+            pages: self.pages(),
+            total_cost: self.total_cost.clone(),
+            feedback: self.feedback.clone(),
+        };
+        serde_json::to_string_pretty(&file).map_err(io::Error::other)
+    }
+
+    /// Reads results of any schema version up to `SCHEMA_VERSION`; files
+    /// without `schema_version` are version 1. Lines of version 1 files are
+    /// all on page 1.
+    pub fn from_json(data: &str) -> io::Result<Self> {
+        let invalid = |e: serde_json::Error| io::Error::new(io::ErrorKind::InvalidData, e);
+        let value: serde_json::Value = serde_json::from_str(data).map_err(invalid)?;
+        let version = match value.get("schema_version") {
+            None => 1,
+            Some(v) => v.as_u64().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "schema_version is not a number")
+            })?,
+        };
+
+        match version {
+            1 => {
+                let v1: ResultsFileV1 = serde_json::from_value(value).map_err(invalid)?;
+                let lines = v1
+                    .lines
+                    .into_iter()
+                    .map(|l| LineResult { page: 1, ..l })
+                    .collect();
+                Ok(RestorationResults {
+                    lines,
+                    total_cost: v1.total_cost,
+                    feedback: None,
+                })
+            }
+            2 => {
+                let file: ResultsFile = serde_json::from_value(value).map_err(invalid)?;
+                let mut lines: Vec<LineResult> = file
+                    .pages
+                    .into_iter()
+                    .flat_map(|p| {
+                        p.lines
+                            .into_iter()
+                            .map(move |l| LineResult { page: p.page, ..l })
+                    })
+                    .collect();
+                lines.sort_by_key(|l| l.line);
+                Ok(RestorationResults {
+                    lines,
+                    total_cost: file.total_cost,
+                    feedback: file.feedback,
+                })
+            }
+            v => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "results schema version {} is newer than this build reads ({})",
+                    v, SCHEMA_VERSION
+                ),
+            )),
+        }
+    }
+
+    /// One row per candidate. The cost and failure columns repeat the line's
+    /// cost and diagnosis and are empty when none were attached.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from(
+            "line,observed_width,exact,rank,text,width,score,width_error,ngram,anchor_bonus,confidence,\
+             elapsed_ms,beams_expanded,candidates_evaluated,failure,remediation\n",
+        );
+        for line in &self.lines {
+            let cost = line.cost.as_ref().map_or(",,".to_string(), |c| {
+                format!(
+                    "{:.3},{},{}",
+                    c.elapsed_ms, c.beams_expanded, c.candidates_evaluated
+                )
+            });
+            let failure = line.diagnosis.as_ref().map_or(",".to_string(), |d| {
+                format!("{},{}", d.mode.label(), csv_field(&d.remediation))
+            });
+            for c in &line.candidates {
+                out.push_str(&format!(
+                    "{},{:.3},{},{},{},{:.3},{:.4},{:.3},{:.4},{:.2},{:.4},{},{}\n",
+                    line.line,
+                    line.observed_width,
+                    line.exact,
+                    c.rank,
+                    csv_field(&c.text),
+                    c.width,
+                    c.score,
+                    c.components.width_error,
+                    c.components.ngram,
+                    c.components.anchor_bonus,
+                    c.confidence,
+                    cost,
+                    failure
+                ));
+            }
+        }
+        out
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = format!(
+            "{:<6} {:>10} {:<24} {:>10} {:>8} {:>10} {:>10}\n{:-<84}\n",
+            "Line", "Width", "Best", "Score", "Δw", "Confidence", "Time (ms)", ""
+        );
+        for line in &self.lines {
+            let ms = line
+                .cost
+                .as_ref()
+                .map_or("-".to_string(), |c| format!("{:.1}", c.elapsed_ms));
+            match line.candidates.first() {
+                Some(c) if line.exact => out.push_str(&format!(
+                    "{:<6} {:>10.2} {:<24} {:>10} {:>8} {:>10} {:>10}\n",
+                    line.line, line.observed_width, c.text, "exact", "", "", ms
+                )),
+                // `~`: the width rests on predicted advances
+                Some(c) => out.push_str(&format!(
+                    "{:<6} {:>10.2} {:<24} {:>10.3} {:>8} {:>9.1}% {:>10}\n",
+                    line.line,
+                    line.observed_width,
+                    c.text,
+                    c.score,
+                    match c.components.width_uncertainty {
+                        Some(_) => format!("~{:.3}", c.components.width_error),
+                        None => format!("{:.3}", c.components.width_error),
+                    },
+                    c.confidence * 100.0,
+                    ms
+                )),
+                None => out.push_str(&format!(
+                    "{:<6} {:>10.2} {:<24} {:>10} {:>8} {:>10} {:>10}\n",
+                    line.line, line.observed_width, "-", "", "", "", ms
+                )),
+            }
+        }
+
+        let unresolved: Vec<(usize, &LineDiagnosis)> = self
+            .lines
+            .iter()
+            .filter_map(|l| l.diagnosis.as_ref().map(|d| (l.line, d)))
+            .collect();
+        if !unresolved.is_empty() {
+            out.push_str(&format!(
+                "{:-<84}\nUnresolved lines: {}\n",
+                "",
+                unresolved.len()
+            ));
+            for (line, d) in unresolved {
+                out.push_str(&format!(
+                    "  {:<4} {:<24} {}\n",
+                    line,
+                    d.mode.label(),
+                    d.remediation
+                ));
+            }
+        }
+
+        if let Some(total) = &self.total_cost {
+            out.push_str(&format!(
+                "{:-<84}\nTotal: {:.1} ms, {} beams expanded, {} candidates evaluated\n",
+                "", total.elapsed_ms, total.beams_expanded, total.candidates_evaluated
+            ));
+            let slowest = self
+                .lines
+                .iter()
+                .filter_map(|l| l.cost.as_ref().map(|c| (l.line, c)))
+                .max_by(|a, b| a.1.elapsed_ms.total_cmp(&b.1.elapsed_ms));
+            if let Some((line, cost)) = slowest {
+                out.push_str(&format!(
+                    "Slowest line: {} ({:.1} ms)\n",
+                    line, cost.elapsed_ms
+                ));
+            }
+        }
+        if let Some(f) = &self.feedback {
+            out.push_str(&format!(
+                "Feedback: {} review decisions from {} ({} word priors{})\n",
+                f.updates,
+                f.path,
+                f.word_priors,
+                if f.learned_weights {
+                    ", learned weights"
+                } else {
+                    ""
+                }
+            ));
+        }
+        out
+    }
+
+    pub fn render(&self, format: OutputFormat) -> io::Result<String> {
+        match format {
+            OutputFormat::Text => Ok(self.to_text()),
+            OutputFormat::Json => self.to_json(),
+            OutputFormat::Csv => Ok(self.to_csv()),
+        }
+    }
+
+    /// Writes to `path`, or to stdout when no path is given.
+    pub fn write(&self, format: OutputFormat, path: Option<&str>) -> io::Result<()> {
+        let rendered = self.render(format)?;
+        match path {
+            Some(p) => fs::write(p, rendered),
+            None => {
+                print!("{}", rendered);
+                Ok(())
+            }
+        }
+    }
+}
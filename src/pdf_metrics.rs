@@ -0,0 +1,311 @@
+// ============================================
+// PDF-EMBEDDED FONT METRICS
+// ============================================
+
+use lopdf::{Dictionary, Document as PdfDocument, Object};
+use std::collections::HashMap;
+use std::io;
+use ttf_parser::Face;
+
+/// Width table of one font resource found in a PDF. `widths` are in glyph
+/// space (1/1000 em), keyed by the Unicode character the code maps to.
+pub struct PdfFontMetrics {
+    pub resource_name: String,
+    pub base_font: String,
+    pub widths: HashMap<char, f32>,
+    pub default_width: Option<f32>,
+    /// Parsed FontFile2/FontFile3 program, when the font is embedded.
+    pub embedded: Option<Face<'static>>,
+}
+
+impl PdfFontMetrics {
+    /// Width table in px for `px_size`. Characters missing from the width
+    /// array are taken from the embedded program when one is available.
+    pub fn glyph_widths(&self, px_size: f32) -> HashMap<char, f32> {
+        let mut map: HashMap<char, f32> = HashMap::new();
+
+        if let Some(face) = &self.embedded {
+            map.extend(crate::build_glyph_widths(face, px_size));
+        }
+        for (&ch, &w) in &self.widths {
+            map.insert(ch, w / 1000.0 * px_size);
+        }
+
+        map
+    }
+}
+
+fn deref<'a>(doc: &'a PdfDocument, obj: &'a Object) -> Option<&'a Object> {
+    doc.dereference(obj).ok().map(|(_, o)| o)
+}
+
+fn dict_get<'a>(doc: &'a PdfDocument, dict: &'a Dictionary, key: &[u8]) -> Option<&'a Object> {
+    dict.get(key).ok().and_then(|o| deref(doc, o))
+}
+
+fn number(obj: &Object) -> Option<f32> {
+    obj.as_float()
+        .ok()
+        .or_else(|| obj.as_i64().ok().map(|v| v as f32))
+}
+
+// ---------- ToUnicode CMaps ----------
+
+fn hex_value(token: &str) -> Option<u32> {
+    u32::from_str_radix(token.trim_matches(|c| c == '<' || c == '>'), 16).ok()
+}
+
+fn hex_to_char(token: &str) -> Option<char> {
+    let hex = token.trim_matches(|c| c == '<' || c == '>');
+    let units: Vec<u16> = (0..hex.len() / 4)
+        .filter_map(|i| u16::from_str_radix(&hex[i * 4..i * 4 + 4], 16).ok())
+        .collect();
+    if units.is_empty() {
+        // single-byte destination
+        return hex_value(hex).and_then(char::from_u32);
+    }
+    char::decode_utf16(units).next().and_then(|r| r.ok())
+}
+
+/// Parses the `bfchar` and `bfrange` sections of a ToUnicode CMap into a
+/// code -> character table.
+pub fn parse_to_unicode(cmap: &str) -> HashMap<u32, char> {
+    let spaced = cmap
+        .replace('[', " [ ")
+        .replace(']', " ] ")
+        .replace('<', " <")
+        .replace('>', "> ");
+    let tokens: Vec<&str> = spaced.split_whitespace().collect();
+    let mut map = HashMap::new();
+    let mut i = 0;
+
+    while i < tokens.len() {
+        match tokens[i] {
+            "beginbfchar" => {
+                i += 1;
+                while i + 1 < tokens.len() && tokens[i] != "endbfchar" {
+                    if let (Some(code), Some(ch)) =
+                        (hex_value(tokens[i]), hex_to_char(tokens[i + 1]))
+                    {
+                        map.insert(code, ch);
+                    }
+                    i += 2;
+                }
+            }
+            "beginbfrange" => {
+                i += 1;
+                while i + 2 < tokens.len() && tokens[i] != "endbfrange" {
+                    let (lo, hi) = (hex_value(tokens[i]), hex_value(tokens[i + 1]));
+                    if tokens[i + 2] == "[" {
+                        let mut j = i + 3;
+                        let mut code = lo.unwrap_or(0);
+                        while j < tokens.len() && tokens[j] != "]" {
+                            if let Some(ch) = hex_to_char(tokens[j]) {
+                                map.insert(code, ch);
+                            }
+                            code += 1;
+                            j += 1;
+                        }
+                        i = j + 1;
+                    } else {
+                        if let (Some(lo), Some(hi), Some(start)) =
+                            (lo, hi, hex_to_char(tokens[i + 2]))
+                        {
+                            for code in lo..=hi {
+                                if let Some(ch) = char::from_u32(start as u32 + (code - lo)) {
+                                    map.insert(code, ch);
+                                }
+                            }
+                        }
+                        i += 3;
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    map
+}
+
+fn to_unicode_map(doc: &PdfDocument, font: &Dictionary) -> Option<HashMap<u32, char>> {
+    let stream = dict_get(doc, font, b"ToUnicode")?.as_stream().ok()?;
+    let data = stream
+        .decompressed_content()
+        .unwrap_or_else(|_| stream.content.clone());
+    Some(parse_to_unicode(&String::from_utf8_lossy(&data)))
+}
+
+// ---------- width arrays ----------
+
+/// `/FirstChar` + `/Widths` of a simple (Type1/TrueType) font.
+fn simple_font_widths(doc: &PdfDocument, font: &Dictionary) -> HashMap<u32, f32> {
+    let first = dict_get(doc, font, b"FirstChar")
+        .and_then(number)
+        .unwrap_or(0.0) as u32;
+    let mut out = HashMap::new();
+
+    if let Some(Ok(widths)) = dict_get(doc, font, b"Widths").map(|o| o.as_array()) {
+        for (i, w) in widths.iter().enumerate() {
+            if let Some(w) = deref(doc, w).and_then(number) {
+                out.insert(first + i as u32, w);
+            }
+        }
+    }
+
+    out
+}
+
+/// `/W` array of a CID font: `c [w1 w2 ...]` and `c_first c_last w` runs.
+fn cid_font_widths(doc: &PdfDocument, cid_font: &Dictionary) -> HashMap<u32, f32> {
+    let mut out = HashMap::new();
+    let Some(Ok(w)) = dict_get(doc, cid_font, b"W").map(|o| o.as_array()) else {
+        return out;
+    };
+
+    let items: Vec<&Object> = w.iter().filter_map(|o| deref(doc, o)).collect();
+    let mut i = 0;
+    while i < items.len() {
+        let Some(start) = number(items[i]).map(|v| v as u32) else {
+            i += 1;
+            continue;
+        };
+
+        if let Some(Ok(list)) = items.get(i + 1).map(|o| o.as_array()) {
+            for (k, w) in list.iter().enumerate() {
+                if let Some(w) = deref(doc, w).and_then(number) {
+                    out.insert(start + k as u32, w);
+                }
+            }
+            i += 2;
+        } else if let (Some(end), Some(w)) = (
+            items.get(i + 1).and_then(|o| number(o)),
+            items.get(i + 2).and_then(|o| number(o)),
+        ) {
+            for cid in start..=end as u32 {
+                out.insert(cid, w);
+            }
+            i += 3;
+        } else {
+            break;
+        }
+    }
+
+    out
+}
+
+fn embedded_program(doc: &PdfDocument, font: &Dictionary) -> Option<Face<'static>> {
+    let descriptor = dict_get(doc, font, b"FontDescriptor")?.as_dict().ok()?;
+    let stream = [b"FontFile2".as_slice(), b"FontFile3".as_slice()]
+        .iter()
+        .find_map(|key| dict_get(doc, descriptor, key))?
+        .as_stream()
+        .ok()?;
+
+    let data = stream
+        .decompressed_content()
+        .unwrap_or_else(|_| stream.content.clone());
+    let data: &'static [u8] = Box::leak(data.into_boxed_slice());
+    Face::parse(data, 0).ok()
+}
+
+fn font_metrics(doc: &PdfDocument, name: &[u8], font: &Dictionary) -> PdfFontMetrics {
+    let subtype = dict_get(doc, font, b"Subtype")
+        .and_then(|o| o.as_name().ok())
+        .unwrap_or(b"");
+    let base_font = dict_get(doc, font, b"BaseFont")
+        .and_then(|o| o.as_name_str().ok())
+        .unwrap_or("")
+        .to_string();
+    let to_unicode = to_unicode_map(doc, font);
+
+    let (code_widths, default_width, program_owner) = if subtype == b"Type0" {
+        let descendant = dict_get(doc, font, b"DescendantFonts")
+            .and_then(|o| o.as_array().ok())
+            .and_then(|a| a.first())
+            .and_then(|o| deref(doc, o))
+            .and_then(|o| o.as_dict().ok());
+
+        match descendant {
+            Some(cid) => (
+                cid_font_widths(doc, cid),
+                Some(dict_get(doc, cid, b"DW").and_then(number).unwrap_or(1000.0)),
+                cid,
+            ),
+            None => (HashMap::new(), None, font),
+        }
+    } else {
+        let missing = dict_get(doc, font, b"FontDescriptor")
+            .and_then(|o| o.as_dict().ok())
+            .and_then(|d| dict_get(doc, d, b"MissingWidth"))
+            .and_then(number);
+        (simple_font_widths(doc, font), missing, font)
+    };
+
+    // without a ToUnicode map simple-font codes are read as Latin-1, which
+    // matches WinAnsi and Standard encoding for the printable ASCII range
+    let widths = code_widths
+        .into_iter()
+        .filter_map(|(code, w)| {
+            let ch = match &to_unicode {
+                Some(map) => map.get(&code).copied(),
+                None if subtype != b"Type0" => char::from_u32(code),
+                None => None,
+            };
+            ch.map(|c| (c, w))
+        })
+        .collect();
+
+    PdfFontMetrics {
+        resource_name: String::from_utf8_lossy(name).to_string(),
+        base_font,
+        widths,
+        default_width,
+        embedded: embedded_program(doc, program_owner),
+    }
+}
+
+/// Metrics of every distinct font resource used on any page.
+pub fn extract_font_metrics(doc: &PdfDocument) -> Vec<PdfFontMetrics> {
+    let mut seen = std::collections::HashSet::new();
+    let mut out = vec![];
+
+    for page_id in doc.get_pages().values() {
+        let Ok(fonts) = doc.get_page_fonts(*page_id) else {
+            continue;
+        };
+        for (name, font) in fonts {
+            let key = (
+                name.clone(),
+                dict_get(doc, font, b"BaseFont")
+                    .and_then(|o| o.as_name().ok())
+                    .map(|n| n.to_vec()),
+            );
+            if seen.insert(key) {
+                out.push(font_metrics(doc, &name, font));
+            }
+        }
+    }
+
+    out
+}
+
+pub fn load_pdf_font_metrics(path: &str) -> io::Result<Vec<PdfFontMetrics>> {
+    let doc = PdfDocument::load(path)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    Ok(extract_font_metrics(&doc))
+}
+
+/// Glyph width table for restoring text set in `metrics`, falling back to
+/// the local face only when the PDF carries no usable metrics for it.
+pub fn pdf_glyph_widths(
+    metrics: Option<&PdfFontMetrics>,
+    local: &Face,
+    px_size: f32,
+) -> HashMap<char, f32> {
+    match metrics {
+        Some(m) if !m.widths.is_empty() || m.embedded.is_some() => m.glyph_widths(px_size),
+        _ => crate::build_glyph_widths(local, px_size),
+    }
+}
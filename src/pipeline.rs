@@ -0,0 +1,796 @@
+// ============================================
+// RESTORATION PIPELINE AND PREPROCESSING HOOKS
+// ============================================
+
+use crate::audit::{AuditEvent, AuditLog};
+use crate::calibration::{stabilize_document_calibrated, Calibration};
+use crate::cjk::{pruned_beam_search, PrunedAlphabet};
+use crate::context::rescore_with_neighbors;
+use crate::diagnosis::{diagnose_line, LineDiagnosis};
+use crate::ensemble::{ensemble_search, top_contributor, Fusion, Strategy};
+use crate::feedback::FeedbackStore;
+use crate::locale::DocumentLocale;
+use crate::output::softmax_confidence;
+use crate::provenance::{beam_search_traced, SearchTrace};
+use crate::ragged::RaggedEdgePrior;
+use crate::scoring::{CompositeScorer, ScoreNormalization};
+use crate::widthmodel::ApproximateWidths;
+use crate::{
+    alphabet_advances, combined_score, derived_max_len, find_phrase_candidates_gapped,
+    find_phrase_candidates_gapped_templated, find_phrase_candidates_templated, gap_placement,
+    hybrid_search, measure_text_kerning, punctuate_beams, restore_width_hinted, stabilize_document,
+    Beam, Document, NGramModel, NearMissOptions, PhraseTemplate, PunctuationSet, ScoreWeights,
+    SearchStats,
+};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::time::Instant;
+use ttf_parser::Face;
+
+/// User code that sees the fully extracted document before any line is
+/// restored: drop noise lines, relabel hints, or add lines the extractor
+/// missed. Closures `FnMut(&mut Document) -> io::Result<()>` implement it.
+pub trait DocumentHook {
+    fn name(&self) -> &str {
+        "hook"
+    }
+
+    fn process(&mut self, doc: &mut Document) -> io::Result<()>;
+}
+
+impl<F> DocumentHook for F
+where
+    F: FnMut(&mut Document) -> io::Result<()>,
+{
+    fn process(&mut self, doc: &mut Document) -> io::Result<()> {
+        self(doc)
+    }
+}
+
+/// Gives a closure hook a name for error messages.
+pub struct NamedHook<F> {
+    pub name: String,
+    pub hook: F,
+}
+
+impl<F> DocumentHook for NamedHook<F>
+where
+    F: FnMut(&mut Document) -> io::Result<()>,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn process(&mut self, doc: &mut Document) -> io::Result<()> {
+        (self.hook)(doc)
+    }
+}
+
+#[derive(Clone)]
+pub struct RestoreConfig {
+    pub px_size: f32,
+    pub tolerance: f32,
+    pub alphabet: Vec<char>,
+    pub weights: ScoreWeights,
+    pub beam_width: usize,
+    /// Punctuation tried around each line's text; hints apply to the bare
+    /// word. Empty by default since every affix costs one more search.
+    pub punctuation: PunctuationSet,
+    /// Puts width, length and n-gram terms on comparable scales before
+    /// `weights` apply. Off by default.
+    pub normalization: ScoreNormalization,
+    /// Most words in a free-form phrase from the word lattice. Phrase
+    /// templates set their own word count.
+    pub max_phrase_words: usize,
+}
+
+impl Default for RestoreConfig {
+    fn default() -> Self {
+        RestoreConfig {
+            px_size: 16.0,
+            tolerance: 0.5,
+            alphabet: ('a'..='z').collect(),
+            weights: ScoreWeights {
+                width: 1.0,
+                word_len: 0.0,
+                spaces: 0.0,
+                ngram: 1.0,
+            },
+            beam_width: 200,
+            punctuation: PunctuationSet::default(),
+            normalization: ScoreNormalization::default(),
+            max_phrase_words: 3,
+        }
+    }
+}
+
+/// Lines whose best beam gets less than `min_confidence` (softmax over the
+/// line's top beams, as reported) are searched again with a wider alphabet, one stage at a
+/// time until the confidence is reached; each stage adds to the previous.
+/// Every stage run is recorded in the audit log.
+#[derive(Clone, Debug)]
+pub struct RestartPolicy {
+    pub min_confidence: f32,
+    pub stages: Vec<Vec<char>>,
+}
+
+impl Default for RestartPolicy {
+    /// Digits, then punctuation, then uppercase letters.
+    fn default() -> Self {
+        RestartPolicy {
+            min_confidence: 0.5,
+            stages: vec![
+                ('0'..='9').collect(),
+                ".,;:'-!?()".chars().collect(),
+                ('A'..='Z').collect(),
+            ],
+        }
+    }
+}
+
+/// Beams the restart confidence is taken over, the default `--top-k`.
+const RESTART_CONFIDENCE_BEAMS: usize = 5;
+
+fn best_confidence(beams: &[Beam]) -> f32 {
+    let scores: Vec<f32> = beams
+        .iter()
+        .take(RESTART_CONFIDENCE_BEAMS)
+        .map(|b| b.score)
+        .collect();
+    softmax_confidence(&scores).first().copied().unwrap_or(0.0)
+}
+
+/// Share of the ragged-edge prior searched for a paragraph-final line, see
+/// `with_ragged_prior`.
+const RAGGED_MASS: f32 = 0.9;
+
+/// Time and search work spent on one line.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LineCost {
+    pub elapsed_ms: f64,
+    pub beams_expanded: u64,
+    pub candidates_evaluated: u64,
+}
+
+/// Per-line costs of a run, indexed like the document after the hooks ran.
+/// `total.elapsed_ms` is the wall time of the whole run, hooks and
+/// stabilization included.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct CostReport {
+    pub lines: Vec<LineCost>,
+    pub total: LineCost,
+}
+
+impl CostReport {
+    /// Index and cost of the line that took longest.
+    pub fn slowest(&self) -> Option<(usize, &LineCost)> {
+        self.lines
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.elapsed_ms.total_cmp(&b.1.elapsed_ms))
+    }
+}
+
+/// Extraction output in, restored document out: hooks run in registration
+/// order, then every line is searched and the document is stabilized.
+pub struct RestorePipeline<'a> {
+    face: &'a Face<'a>,
+    glyphs: &'a HashMap<char, f32>,
+    model: Option<&'a NGramModel>,
+    calibration: Option<Calibration>,
+    dictionary: Option<(&'a [&'a str], NearMissOptions)>,
+    template: Option<PhraseTemplate<'a>>,
+    ragged: Option<(RaggedEdgePrior, f32)>,
+    locale: DocumentLocale,
+    restart: Option<RestartPolicy>,
+    ensemble: Option<Fusion>,
+    approximate: Option<&'a ApproximateWidths>,
+    feedback: Option<&'a FeedbackStore>,
+    pruned: Option<&'a PrunedAlphabet>,
+    context: Option<f32>,
+    audit: AuditLog,
+    trace: Option<(usize, Option<String>)>,
+    search_trace: Option<SearchTrace>,
+    pub config: RestoreConfig,
+    hooks: Vec<Box<dyn DocumentHook + 'a>>,
+}
+
+impl<'a> RestorePipeline<'a> {
+    pub fn new(face: &'a Face<'a>, glyphs: &'a HashMap<char, f32>, config: RestoreConfig) -> Self {
+        RestorePipeline {
+            face,
+            glyphs,
+            model: None,
+            calibration: None,
+            dictionary: None,
+            template: None,
+            ragged: None,
+            locale: DocumentLocale::default(),
+            restart: None,
+            ensemble: None,
+            approximate: None,
+            feedback: None,
+            pruned: None,
+            context: None,
+            audit: AuditLog::default(),
+            trace: None,
+            search_trace: None,
+            config,
+            hooks: vec![],
+        }
+    }
+
+    pub fn with_model(mut self, model: &'a NGramModel) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    /// Searches each line at its calibrated width with a per-line tolerance
+    /// instead of `config.tolerance`, and stabilizes with the noise model.
+    /// With a character variance table, candidates are also ranked by how
+    /// likely their width is under their own characters' noise.
+    pub fn with_calibration(mut self, calibration: Calibration) -> Self {
+        self.calibration = Some(calibration);
+        self
+    }
+
+    /// Tries the dictionary first: lines with word gaps get gap-constrained
+    /// phrases, lines without hints exact matches and repaired near misses
+    /// (see `hybrid_search`). The full search only runs when neither is found.
+    pub fn with_dictionary(mut self, dictionary: &'a [&'a str], options: NearMissOptions) -> Self {
+        self.dictionary = Some((dictionary, options));
+        self
+    }
+
+    /// Tries phrases shaped by `template` (a dictionary per word) before
+    /// anything else: on lines with word gaps, span by span; on lines
+    /// without hints, through the word lattice. Lines no templated phrase
+    /// fits fall through to the other searches.
+    pub fn with_phrase_template(mut self, template: PhraseTemplate<'a>) -> Self {
+        self.template = Some(template);
+        self
+    }
+
+    /// Lines hinted `paragraph_end` are searched over the likely widths under
+    /// `prior` instead of at their observed width, which only bounds the
+    /// text; the width error inside each bin is not charged and
+    /// `weight * log_prior` is added instead. The prior must
+    /// be in the units the lines are searched in (glyph-table units when
+    /// calibrated).
+    pub fn with_ragged_prior(mut self, prior: RaggedEdgePrior, weight: f32) -> Self {
+        self.ragged = Some((prior, weight));
+        self
+    }
+
+    /// Number and date formats for lines hinted with a `template`, usually
+    /// `DocumentLocale::infer` over the visible text. Defaults to
+    /// `1,234.56` and `MM/DD/YYYY`.
+    pub fn with_locale(mut self, locale: DocumentLocale) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    /// Restarts low-confidence lines with a wider alphabet, see
+    /// `RestartPolicy`. Lines with a template or exact text never restart.
+    pub fn with_restart(mut self, policy: RestartPolicy) -> Self {
+        self.restart = Some(policy);
+        self
+    }
+
+    /// With a dictionary, lines without hints run dictionary lookup, the
+    /// word lattice and character beam search side by side and fuse their
+    /// lists (see `ensemble_search`) instead of trying them in turn. The
+    /// strategy behind each winner goes to the audit log.
+    pub fn with_ensemble(mut self, fusion: Fusion) -> Self {
+        self.ensemble = Some(fusion);
+        self
+    }
+
+    /// Marks the width-map entries that are predictions (see
+    /// `WidthPredictor::fill`). A candidate using them is not charged for
+    /// width error within the standard error of its predicted width.
+    pub fn with_approximate_widths(mut self, approximate: &'a ApproximateWidths) -> Self {
+        self.approximate = Some(approximate);
+        self
+    }
+
+    /// Adds the word priors learned from review decisions to every line's
+    /// candidates. Learned weights are not applied here; they go in
+    /// `config.weights`.
+    pub fn with_feedback(mut self, feedback: &'a FeedbackStore) -> Self {
+        self.feedback = Some(feedback);
+        self
+    }
+
+    /// Character search extends each beam with what `pruned` allows after
+    /// it plus `config.alphabet` (see `pruned_beam_search`), for scripts
+    /// like CJK whose full alphabet is too large to try at every step.
+    pub fn with_pruned_alphabet(mut self, pruned: &'a PrunedAlphabet) -> Self {
+        self.pruned = Some(pruned);
+        self
+    }
+
+    /// After stabilization each line is rescored against the text of the
+    /// lines around it (see `rescore_with_neighbors`), weighted by
+    /// `weight`. Needs `with_model`; lines it changes are audited.
+    pub fn with_line_context(mut self, weight: f32) -> Self {
+        self.context = Some(weight);
+        self
+    }
+
+    /// The font the pipeline measures with.
+    pub fn face(&self) -> &'a Face<'a> {
+        self.face
+    }
+
+    /// Decisions taken during the last `run`.
+    pub fn audit_log(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    /// Also runs a provenance-recording beam search on `line` (1-based,
+    /// after the hooks), following `watch` when given, e.g. the known
+    /// truth. Only the beam search is traced, not the dictionary, template
+    /// or exact paths the line's beams may have come from.
+    pub fn with_trace(mut self, line: usize, watch: Option<&str>) -> Self {
+        self.trace = Some((line, watch.map(str::to_string)));
+        self
+    }
+
+    /// Trace of the line selected with `with_trace` during the last `run`.
+    pub fn search_trace(&self) -> Option<&SearchTrace> {
+        self.search_trace.as_ref()
+    }
+
+    pub fn add_hook(&mut self, hook: impl DocumentHook + 'a) -> &mut Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    pub fn hook_count(&self) -> usize {
+        self.hooks.len()
+    }
+
+    /// Runs only the registered hooks. A failing hook stops the pipeline
+    /// and the document is left as that hook returned it.
+    pub fn preprocess(&mut self, doc: &mut Document) -> io::Result<()> {
+        for hook in &mut self.hooks {
+            hook.process(doc)
+                .map_err(|e| io::Error::new(e.kind(), format!("hook '{}': {}", hook.name(), e)))?;
+        }
+        Ok(())
+    }
+
+    /// Line width and tolerance in glyph-table units, calibrated when a
+    /// calibration is set.
+    fn line_target(&self, observed_width: f32) -> (f32, f32) {
+        match &self.calibration {
+            Some(cal) => (
+                cal.normalize_width(observed_width),
+                cal.tolerance(observed_width),
+            ),
+            None => (observed_width, self.config.tolerance),
+        }
+    }
+
+    /// Width and tolerance every line of a document this pipeline restored
+    /// was searched against, in the units of its beams' widths: calibrated
+    /// when a calibration is set, and for a ragged paragraph end where its
+    /// best beam ends, as the bar only bounds the text there.
+    pub fn line_targets(&self, doc: &Document) -> Vec<(f32, f32)> {
+        doc.lines
+            .iter()
+            .map(|line| {
+                let (target, tolerance) = self.line_target(line.observed_width);
+                match line.hints.paragraph_end {
+                    true => (
+                        line.beams.first().map_or(target, |b| b.width.min(target)),
+                        tolerance,
+                    ),
+                    false => (target, tolerance),
+                }
+            })
+            .collect()
+    }
+
+    /// Diagnosis of every line of a document this pipeline restored, `None`
+    /// for the lines that resolved. Exactly recovered lines are flagged when
+    /// their text does not fit the observed width.
+    pub fn diagnose(&self, doc: &Document) -> Vec<Option<LineDiagnosis>> {
+        doc.lines
+            .iter()
+            .zip(self.line_targets(doc))
+            .map(|(line, (target, tolerance))| {
+                diagnose_line(
+                    line,
+                    target,
+                    tolerance,
+                    self.glyphs,
+                    self.dictionary.as_ref().map(|d| d.0),
+                )
+            })
+            .collect()
+    }
+
+    pub fn run(&mut self, doc: &mut Document) -> io::Result<CostReport> {
+        let start = Instant::now();
+        self.preprocess(doc)?;
+
+        let c = &self.config;
+        let scorer = CompositeScorer::new(c.weights.clone(), c.normalization);
+        let weights = scorer.search_weights();
+        let mut report = CostReport::default();
+        let mut audit = AuditLog::default();
+        let mut search_trace = None;
+        for (i, line) in doc.lines.iter_mut().enumerate() {
+            let line_start = Instant::now();
+            let stats = SearchStats::default();
+
+            // text recovered verbatim needs no inference
+            if let Some(text) = &line.hints.exact_text {
+                let width = measure_text_kerning(text, self.face, self.glyphs, c.px_size);
+                line.beams = vec![Beam {
+                    text: text.clone(),
+                    width,
+                    score: 0.0,
+                }];
+                report.lines.push(LineCost {
+                    elapsed_ms: line_start.elapsed().as_secs_f64() * 1000.0,
+                    ..LineCost::default()
+                });
+                continue;
+            }
+
+            let (target, tolerance) = self.line_target(line.observed_width);
+            // who proposed each fused candidate, for the audit log
+            let sources: RefCell<HashMap<String, Vec<(Strategy, usize)>>> =
+                RefCell::new(HashMap::new());
+            let search = |width: f32, tolerance: f32, alphabet: &[char]| {
+                if let Some(template) = line.hints.template {
+                    return self.locale.template_beams(
+                        template,
+                        width,
+                        tolerance,
+                        self.glyphs,
+                        &weights,
+                    );
+                }
+                let gaps = &line.hints.gaps;
+                let phrase_beams = |phrases: Vec<(String, f32)>| {
+                    let mut beams: Vec<Beam> = phrases
+                        .into_iter()
+                        .map(|(text, _)| {
+                            let measured = match gaps.is_empty() {
+                                true => {
+                                    measure_text_kerning(&text, self.face, self.glyphs, c.px_size)
+                                }
+                                false => {
+                                    gap_placement(&text, self.glyphs, gaps).map_or(width, |p| p.1)
+                                }
+                            };
+                            let score =
+                                combined_score(&text, measured, width, &weights, self.model);
+                            Beam {
+                                text,
+                                width: measured,
+                                score,
+                            }
+                        })
+                        .collect();
+                    line.hints.apply(&mut beams);
+                    beams
+                };
+                if let Some(template) = &self.template {
+                    let phrases = match (gaps.is_empty(), line.hints.is_empty()) {
+                        (false, _) => find_phrase_candidates_gapped_templated(
+                            width,
+                            self.glyphs,
+                            template,
+                            tolerance,
+                            gaps,
+                            c.beam_width,
+                        ),
+                        (true, true) => find_phrase_candidates_templated(
+                            width,
+                            self.glyphs,
+                            template,
+                            tolerance,
+                            c.beam_width,
+                        ),
+                        (true, false) => vec![],
+                    };
+                    if !phrases.is_empty() {
+                        return phrase_beams(phrases);
+                    }
+                }
+                if let (Some((dict, _)), false) = (&self.dictionary, gaps.is_empty()) {
+                    let phrases = find_phrase_candidates_gapped(
+                        width,
+                        self.glyphs,
+                        dict,
+                        tolerance,
+                        gaps,
+                        c.beam_width,
+                    );
+                    if !phrases.is_empty() {
+                        return phrase_beams(phrases);
+                    }
+                }
+                if let (Some(fusion), Some((dict, _)), true) =
+                    (self.ensemble, &self.dictionary, line.hints.is_empty())
+                {
+                    let fused = ensemble_search(
+                        self.face,
+                        self.glyphs,
+                        c.px_size,
+                        width,
+                        tolerance,
+                        dict,
+                        alphabet,
+                        &weights,
+                        self.model,
+                        c.beam_width,
+                        c.max_phrase_words,
+                        fusion,
+                        &stats,
+                    );
+                    let mut sources = sources.borrow_mut();
+                    for candidate in &fused.candidates {
+                        sources.insert(candidate.text.clone(), candidate.sources.clone());
+                    }
+                    return fused.beams();
+                }
+                if let (Some((dict, options)), true) = (&self.dictionary, line.hints.is_empty()) {
+                    let seeded = hybrid_search(
+                        self.face,
+                        self.glyphs,
+                        c.px_size,
+                        width,
+                        tolerance,
+                        dict,
+                        alphabet,
+                        &weights,
+                        self.model,
+                        c.beam_width,
+                        options,
+                        &stats,
+                    );
+                    if !seeded.is_empty() {
+                        return seeded;
+                    }
+                }
+                if let Some(pruned) = self.pruned {
+                    let mut beams = pruned_beam_search(
+                        self.face,
+                        self.glyphs,
+                        c.px_size,
+                        width,
+                        tolerance,
+                        pruned,
+                        alphabet,
+                        &weights,
+                        self.model,
+                        c.beam_width,
+                        line.hints.char_count,
+                        &stats,
+                    );
+                    line.hints.apply(&mut beams);
+                    return beams;
+                }
+                restore_width_hinted(
+                    self.face,
+                    self.glyphs,
+                    c.px_size,
+                    width,
+                    tolerance,
+                    alphabet,
+                    &weights,
+                    self.model,
+                    c.beam_width,
+                    &line.hints,
+                    &stats,
+                )
+            };
+
+            let restore_at = |target: f32, tolerance: f32, alphabet: &[char]| {
+                let mut beams = search(target, tolerance, alphabet);
+                for (prefix, suffix) in c.punctuation.affixes() {
+                    let extra = measure_text_kerning(prefix, self.face, self.glyphs, c.px_size)
+                        + measure_text_kerning(suffix, self.face, self.glyphs, c.px_size);
+                    if extra >= target {
+                        continue;
+                    }
+                    beams.extend(punctuate_beams(
+                        search(target - extra, tolerance, alphabet),
+                        prefix,
+                        suffix,
+                        self.face,
+                        self.glyphs,
+                        c.px_size,
+                        target,
+                        &weights,
+                        self.model,
+                    ));
+                }
+                if !c.punctuation.is_empty() {
+                    beams.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+                    beams.truncate(c.beam_width);
+                }
+                beams
+            };
+
+            let restore_line = |alphabet: &[char]| match (&self.ragged, line.hints.paragraph_end) {
+                (Some((prior, weight)), true) => {
+                    let mut beams: Vec<Beam> = prior
+                        .hypotheses(target, RAGGED_MASS)
+                        .into_iter()
+                        .flat_map(|h| {
+                            // anywhere inside the bin is as good as its centre;
+                            // the prior ranks the bins
+                            let mut beams =
+                                restore_at(h.width, h.tolerance.max(tolerance), alphabet);
+                            for b in &mut beams {
+                                b.score += weights.width * (b.width - h.width).abs();
+                            }
+                            beams
+                        })
+                        .collect();
+                    prior.apply(&mut beams, *weight);
+                    let mut seen = HashSet::new();
+                    beams.retain(|b| seen.insert(b.text.clone()));
+                    beams.truncate(c.beam_width);
+                    beams
+                }
+                _ => restore_at(target, tolerance, alphabet),
+            };
+
+            let mut beams = restore_line(&c.alphabet);
+            if let (Some(policy), None) = (&self.restart, line.hints.template) {
+                let mut alphabet = c.alphabet.clone();
+                let mut confidence = best_confidence(&beams);
+                for (stage, extra) in policy.stages.iter().enumerate() {
+                    if confidence >= policy.min_confidence {
+                        break;
+                    }
+                    let added: String = extra.iter().filter(|ch| !alphabet.contains(ch)).collect();
+                    alphabet.extend(added.chars());
+
+                    // the narrower run's beams stay in the running
+                    let mut widened = restore_line(&alphabet);
+                    widened.append(&mut beams);
+                    widened.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+                    let mut seen = HashSet::new();
+                    widened.retain(|b| seen.insert(b.text.clone()));
+                    widened.truncate(c.beam_width);
+
+                    let after = best_confidence(&widened);
+                    audit.record(
+                        i + 1,
+                        AuditEvent::AlphabetEscalation {
+                            stage: stage + 1,
+                            added,
+                            confidence_before: confidence,
+                            confidence_after: after,
+                        },
+                    );
+                    beams = widened;
+                    confidence = after;
+                }
+            }
+            if let Some(approximate) = self.approximate.filter(|a| !a.is_empty()) {
+                for b in &mut beams {
+                    b.score += weights.width
+                        * (b.width - target)
+                            .abs()
+                            .min(approximate.uncertainty(&b.text));
+                }
+                beams.sort_by(|a, b| b.score.total_cmp(&a.score));
+            }
+            // pixel-measured widths: the width term becomes the likelihood
+            // under the candidate's own character noise
+            if let Some(cal) = self
+                .calibration
+                .as_ref()
+                .filter(|c| c.char_variance.is_some())
+            {
+                for b in &mut beams {
+                    b.score += weights.width
+                        * ((b.width - target).abs()
+                            + cal.width_log_likelihood(&b.text, b.width, line.observed_width));
+                }
+                beams.sort_by(|a, b| b.score.total_cmp(&a.score));
+            }
+            if let Some(feedback) = self.feedback {
+                feedback.apply(&mut beams);
+            }
+            // a ragged line's width is only an upper bound, so its pool has
+            // no width error to standardize
+            if !(self.ragged.is_some() && line.hints.paragraph_end) {
+                scorer.rescore(&mut beams, target, self.model);
+            }
+            if let Some(best) = beams.first() {
+                let sources = sources.borrow();
+                let found = sources.get(&best.text).map_or(&[][..], Vec::as_slice);
+                if let Some(strategy) = top_contributor(found) {
+                    audit.record(
+                        i + 1,
+                        AuditEvent::EnsembleWinner {
+                            text: best.text.clone(),
+                            strategy,
+                            agreeing: found
+                                .iter()
+                                .map(|s| s.0)
+                                .filter(|&s| s != strategy)
+                                .collect(),
+                        },
+                    );
+                }
+            }
+            line.beams = beams;
+
+            if let Some((_, watch)) = self.trace.as_ref().filter(|t| t.0 == i + 1) {
+                let steps = line.hints.char_count.unwrap_or_else(|| {
+                    derived_max_len(
+                        target + tolerance,
+                        &alphabet_advances(self.face, c.px_size, &c.alphabet),
+                    )
+                });
+                let root = Beam {
+                    text: String::new(),
+                    width: 0.0,
+                    score: 0.0,
+                };
+                let (_, mut trace) = beam_search_traced(
+                    self.face,
+                    c.px_size,
+                    vec![root],
+                    target,
+                    &c.alphabet,
+                    &weights,
+                    self.model,
+                    c.beam_width,
+                    steps,
+                    watch.as_deref(),
+                );
+                trace.line = Some(i + 1);
+                search_trace = Some(trace);
+            }
+
+            let cost = LineCost {
+                elapsed_ms: line_start.elapsed().as_secs_f64() * 1000.0,
+                beams_expanded: stats.beams_expanded(),
+                candidates_evaluated: stats.candidates_evaluated(),
+            };
+            report.total.beams_expanded += cost.beams_expanded;
+            report.total.candidates_evaluated += cost.candidates_evaluated;
+            report.lines.push(cost);
+        }
+
+        // beams already carry the n-gram term through `weights.ngram`
+        match &self.calibration {
+            Some(cal) => {
+                stabilize_document_calibrated(doc, cal);
+            }
+            None => stabilize_document(doc),
+        }
+
+        if let (Some(weight), Some(model)) = (self.context, self.model) {
+            for change in rescore_with_neighbors(doc, model, weight) {
+                audit.record(
+                    change.line + 1,
+                    AuditEvent::ContextPromotion {
+                        from: change.from,
+                        to: change.to,
+                    },
+                );
+            }
+        }
+
+        self.audit = audit;
+        self.search_trace = search_trace;
+        report.total.elapsed_ms = start.elapsed().as_secs_f64() * 1000.0;
+        Ok(report)
+    }
+}
@@ -0,0 +1,49 @@
+// ============================================
+// PRELUDE
+// ============================================
+
+//! Everything needed to run a restoration:
+//! `use restore_watermark::prelude::*;` replaces the imports from
+//! `pipeline`, `output`, `calibration` and the crate root.
+
+pub use crate::attribution::{
+    attribute_producer, default_fingerprints, AttributionReport, ProducerFingerprint, SpacingSample,
+};
+pub use crate::audit::{AuditEvent, AuditLog};
+pub use crate::calibration::Calibration;
+pub use crate::cjk::{
+    pruned_beam_search, to_fullwidth, to_halfwidth, PrunedAlphabet, WidthClass, DEFAULT_FOLLOWERS,
+    DEFAULT_FREQUENT, LARGE_ALPHABET,
+};
+pub use crate::context::{boundary_log_prob, rescore_with_neighbors, ContextChange};
+pub use crate::crossformat::{
+    check_consistency, correct_document_widths, load_export_lines, ConsistencyReport, ExportLine,
+    PipelineBias,
+};
+pub use crate::diagnosis::{FailureMode, LineDiagnosis};
+pub use crate::ensemble::{ensemble_search, EnsembleResult, Fusion, Strategy};
+pub use crate::entities::{Entity, EntityKind, EntityReport};
+pub use crate::exposure::{audit_redactions, Exposure, RedactionAudit};
+pub use crate::feedback::{FeedbackStore, DEFAULT_FEEDBACK_PATH};
+pub use crate::locale::{DocumentLocale, Template};
+pub use crate::marginal::{restore_marginalized, FontCandidate, FontMarginal, FontPosterior};
+pub use crate::mixture::CorpusMixture;
+pub use crate::output::{OutputFormat, RestorationResults};
+pub use crate::pipeline::{
+    CostReport, DocumentHook, NamedHook, RestartPolicy, RestoreConfig, RestorePipeline,
+};
+pub use crate::provenance::SearchTrace;
+pub use crate::raster::{is_image_path, load_image_document, RasterOptions};
+pub use crate::scoring::{CompositeScorer, ScoreNormalization, ScoreScales, ScoreTerms};
+pub use crate::solve::{
+    count_pattern_matches, solve_pattern, PatternPart, WidthPattern, DEFAULT_PREFIXES_PER_STATE,
+};
+pub use crate::summary::{output_error, Outcome, RunSummary};
+pub use crate::visible::VisibleText;
+pub use crate::watch::{restore_pdf, BatchManifest, DropFolder, EntryStatus, ManifestEntry};
+pub use crate::widthmodel::{ApproximateWidths, UnicodeBlock, WidthEstimate, WidthPredictor};
+pub use crate::{
+    build_glyph_widths, load_font, load_line_inputs, measure_text_kerning, train_ngram, Beam,
+    Dictionary, Document, HintMode, Line, LineHints, LineInput, NGramModel, NearMissOptions,
+    PhraseTemplate, PunctuationSet, ScoreWeights,
+};
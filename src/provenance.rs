@@ -0,0 +1,468 @@
+// ============================================
+// BEAM PROVENANCE FOR SEARCH DEBUGGING
+// ============================================
+
+use crate::scoring::ScoreTerms;
+use crate::{
+    advance_range, alphabet_advances, combined_score, reachable_width, Beam, BeamHeap, NGramModel,
+    ScoreWeights, BEAM_OVERSHOOT,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use ttf_parser::Face;
+
+/// A hypothesis that survived a step. `parent` is the id of the beam it
+/// extends, `None` for the seeds.
+#[derive(Clone, Debug, Serialize)]
+pub struct BeamRecord {
+    pub id: usize,
+    pub step: usize,
+    pub parent: Option<usize>,
+    pub text: String,
+    pub width: f32,
+    pub score: f32,
+    pub components: ScoreTerms,
+}
+
+/// Survivors of one step, best first. `cutoff` is the score of the worst
+/// survivor: anything scoring at or below it was pruned.
+#[derive(Clone, Debug, Serialize)]
+pub struct StepRecord {
+    pub step: usize,
+    pub evaluated: usize,
+    pub cutoff: Option<f32>,
+    pub survivors: Vec<BeamRecord>,
+}
+
+/// A prefix of the watched text as evaluated at one step.
+#[derive(Clone, Debug, Serialize)]
+pub struct WatchRecord {
+    pub step: usize,
+    pub text: String,
+    pub width: f32,
+    pub score: f32,
+    pub components: ScoreTerms,
+    /// Rank among the step's survivors, 0-based; `None` when pruned.
+    pub rank: Option<usize>,
+    /// Rejected before scoring for running past the target width.
+    pub overshoot: bool,
+}
+
+/// Why the watched text was lost: its prefix at `step` scored below the
+/// worst survivor, mostly on `component`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Pruning {
+    pub step: usize,
+    pub text: String,
+    pub score: f32,
+    pub cutoff_text: String,
+    pub cutoff: f32,
+    pub component: Option<(&'static str, f32)>,
+}
+
+/// Every survivor of every step of one beam search, linked to its parent,
+/// plus the fate of an optional watched text (usually the known truth).
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SearchTrace {
+    pub line: Option<usize>,
+    pub target_width: f32,
+    pub watched: Option<String>,
+    pub steps: Vec<StepRecord>,
+    pub watch: Vec<WatchRecord>,
+}
+
+/// A beam in `SearchGraph`. `on_watch` beams are prefixes of the watched
+/// text; `pruned` is the one prefix that did not survive.
+#[derive(Clone, Debug, Serialize)]
+pub struct GraphNode {
+    pub id: usize,
+    pub step: usize,
+    pub text: String,
+    pub width: f32,
+    pub score: f32,
+    pub on_watch: bool,
+    pub final_beam: bool,
+    pub pruned: bool,
+}
+
+/// The beam search tree of a `SearchTrace`, for drawing.
+#[derive(Clone, Debug, Serialize)]
+pub struct SearchGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<(usize, usize)>,
+}
+
+/// `text` inside a DOT double-quoted string.
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl SearchGraph {
+    /// GraphViz source: one rank per step, left to right. The watched
+    /// text's path is drawn bold, its pruned prefix red and dashed, the
+    /// final beams doubled.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from(
+            "digraph search {\n  rankdir=LR;\n  node [shape=box, fontname=\"monospace\"];\n",
+        );
+        let last = self.nodes.iter().map(|n| n.step).max().unwrap_or(0);
+        for step in 0..=last {
+            out.push_str("  { rank=same;");
+            for n in self.nodes.iter().filter(|n| n.step == step) {
+                out.push_str(&format!(" n{};", n.id));
+            }
+            out.push_str(" }\n");
+        }
+        for n in &self.nodes {
+            let text = if n.text.is_empty() {
+                "∅".to_string()
+            } else {
+                format!("\\\"{}\\\"", dot_escape(&n.text))
+            };
+            let style = match (n.pruned, n.on_watch, n.final_beam) {
+                (true, _, _) => ", color=red, style=dashed",
+                (false, true, _) => ", style=bold",
+                (false, false, true) => ", peripheries=2",
+                _ => "",
+            };
+            out.push_str(&format!(
+                "  n{} [label=\"{}\\nw {:.2}  s {:.2}\"{}];\n",
+                n.id, text, n.width, n.score, style
+            ));
+        }
+        for &(from, to) in &self.edges {
+            out.push_str(&format!("  n{} -> n{};\n", from, to));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_json(&self) -> io::Result<String> {
+        serde_json::to_string_pretty(self).map_err(io::Error::other)
+    }
+
+    /// DOT, or the JSON node/edge graph if `path` ends in `.json`.
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let data = match path.ends_with(".json") {
+            true => self.to_json()?,
+            false => self.to_dot(),
+        };
+        fs::write(path, data)
+    }
+}
+
+/// `beam_search_from` with provenance: same beams, same order, but run on
+/// one thread and recorded step by step. `watch` is followed prefix by
+/// prefix so the step that pruned it can be named.
+#[allow(clippy::too_many_arguments)]
+pub fn beam_search_traced(
+    face: &Face,
+    px_size: f32,
+    seeds: Vec<Beam>,
+    target_width: f32,
+    alphabet: &[char],
+    weights: &ScoreWeights,
+    model: Option<&NGramModel>,
+    beam_width: usize,
+    steps: usize,
+    watch: Option<&str>,
+) -> (Vec<Beam>, SearchTrace) {
+    let advances = alphabet_advances(face, px_size, alphabet);
+    let range = advance_range(&advances);
+    // components at the width the beam is scored at, as in `beam_search_from`
+    let components = |text: &str, width: f32, step: usize| {
+        let reachable = reachable_width(width, steps.saturating_sub(step), range, target_width);
+        ScoreTerms::of(text, reachable, target_width, weights, model)
+    };
+    let mut trace = SearchTrace {
+        target_width,
+        watched: watch.map(str::to_string),
+        ..SearchTrace::default()
+    };
+
+    let mut next_id = 0;
+    let mut record = |step: usize, parent: Option<usize>, beam: &Beam, components: ScoreTerms| {
+        next_id += 1;
+        BeamRecord {
+            id: next_id - 1,
+            step,
+            parent,
+            text: beam.text.clone(),
+            width: beam.width,
+            score: beam.score,
+            components,
+        }
+    };
+
+    let survivors: Vec<BeamRecord> = seeds
+        .iter()
+        .map(|b| record(0, None, b, components(&b.text, b.width, 0)))
+        .collect();
+    trace.steps.push(StepRecord {
+        step: 0,
+        evaluated: 0,
+        cutoff: survivors.last().map(|r| r.score),
+        survivors,
+    });
+    let mut beams = seeds;
+
+    for step in 1..=steps {
+        let parents: HashMap<&str, usize> = trace.steps[step - 1]
+            .survivors
+            .iter()
+            .map(|r| (r.text.as_str(), r.id))
+            .collect();
+        let mut heap = BeamHeap::new(beam_width);
+        let mut evaluated = 0;
+        let mut watched = None;
+
+        for beam in &beams {
+            for &(ch, adv) in &advances {
+                let width = beam.width + adv;
+                let mut text = beam.text.clone();
+                text.push(ch);
+                let is_watched = watch.is_some_and(|w| w.starts_with(&text));
+
+                if width > target_width + BEAM_OVERSHOOT {
+                    if is_watched {
+                        let components = components(&text, width, step);
+                        watched = Some((text, width, components.total(), components, true));
+                    }
+                    continue;
+                }
+
+                let reachable = reachable_width(width, steps - step, range, target_width);
+                let score = combined_score(&text, reachable, target_width, weights, model);
+                evaluated += 1;
+                if is_watched {
+                    let components = components(&text, width, step);
+                    watched = Some((text.clone(), width, score, components, false));
+                }
+                if heap.accepts(score) {
+                    heap.push(Beam { text, width, score });
+                }
+            }
+        }
+
+        if heap.is_empty() {
+            break;
+        }
+        beams = heap.into_sorted_vec();
+
+        let survivors: Vec<BeamRecord> = beams
+            .iter()
+            .map(|b| {
+                let parent = b
+                    .text
+                    .char_indices()
+                    .last()
+                    .and_then(|(i, _)| parents.get(&b.text[..i]).copied());
+                record(step, parent, b, components(&b.text, b.width, step))
+            })
+            .collect();
+        if let Some((text, width, score, components, overshoot)) = watched {
+            let rank = survivors.iter().position(|r| r.text == text);
+            trace.watch.push(WatchRecord {
+                step,
+                text,
+                width,
+                score,
+                components,
+                rank,
+                overshoot,
+            });
+        }
+        trace.steps.push(StepRecord {
+            step,
+            evaluated,
+            cutoff: survivors.last().map(|r| r.score),
+            survivors,
+        });
+    }
+
+    (beams, trace)
+}
+
+impl SearchTrace {
+    pub fn record(&self, id: usize) -> Option<&BeamRecord> {
+        self.steps
+            .iter()
+            .flat_map(|s| &s.survivors)
+            .find(|r| r.id == id)
+    }
+
+    /// The chain of beams that produced `id`, seed first.
+    pub fn lineage(&self, id: usize) -> Vec<&BeamRecord> {
+        let mut chain = vec![];
+        let mut current = self.record(id);
+        while let Some(r) = current {
+            chain.push(r);
+            current = r.parent.and_then(|p| self.record(p));
+        }
+        chain.reverse();
+        chain
+    }
+
+    /// Where the watched text fell out of the beam, if it did.
+    pub fn pruning(&self) -> Option<Pruning> {
+        let lost = self.watch.iter().find(|w| w.rank.is_none())?;
+        let worst = self
+            .steps
+            .iter()
+            .find(|s| s.step == lost.step)?
+            .survivors
+            .last()?;
+        Some(Pruning {
+            step: lost.step,
+            text: lost.text.clone(),
+            score: lost.score,
+            cutoff_text: worst.text.clone(),
+            cutoff: worst.score,
+            component: match lost.overshoot {
+                true => Some(("overshoot", lost.components.width - worst.components.width)),
+                false => lost.components.largest_deficit(&worst.components),
+            },
+        })
+    }
+
+    pub fn to_json(&self) -> io::Result<String> {
+        serde_json::to_string_pretty(self).map_err(io::Error::other)
+    }
+
+    /// Step summaries, the final beams' lineages (`top` of them) and the
+    /// watched text's fate, for reading in a terminal.
+    pub fn to_text(&self, top: usize) -> String {
+        let mut out = format!("Target width {:.2}px", self.target_width);
+        if let Some(line) = self.line {
+            out.push_str(&format!(", line {}", line));
+        }
+        out.push_str(&format!(
+            "\n\n{:>5} {:>10} {:>10} {:>10}  Best\n",
+            "Step", "Evaluated", "Kept", "Cutoff"
+        ));
+        for s in &self.steps {
+            out.push_str(&format!(
+                "{:>5} {:>10} {:>10} {:>10}  {}\n",
+                s.step,
+                s.evaluated,
+                s.survivors.len(),
+                s.cutoff.map_or("-".to_string(), |c| format!("{:.2}", c)),
+                s.survivors.first().map_or("", |r| r.text.as_str())
+            ));
+        }
+
+        if let Some(last) = self.steps.last() {
+            out.push_str("\nFinal beams and their lineage:\n");
+            for r in last.survivors.iter().take(top) {
+                let chain: Vec<String> = self
+                    .lineage(r.id)
+                    .iter()
+                    .map(|b| format!("{:?}", b.text))
+                    .collect();
+                let c = &r.components;
+                out.push_str(&format!(
+                    "  {:<20} {:>8.2}  width {:.2} ngram {:.2} length {:.2} spaces {:.2}\n    {}\n",
+                    r.text,
+                    r.score,
+                    c.width,
+                    c.ngram,
+                    c.length,
+                    c.spaces,
+                    chain.join(" -> ")
+                ));
+            }
+        }
+
+        if let Some(watched) = &self.watched {
+            out.push_str(&format!("\nWatched {:?}: ", watched));
+            match (self.pruning(), self.watch.last()) {
+                (Some(p), _) => out.push_str(&format!(
+                    "pruned at step {} as {:?} ({:.2} vs cutoff {:.2} for {:?}){}\n",
+                    p.step,
+                    p.text,
+                    p.score,
+                    p.cutoff,
+                    p.cutoff_text,
+                    p.component.map_or(String::new(), |(name, d)| format!(
+                        ", behind on {} by {:.2}",
+                        name, -d
+                    ))
+                )),
+                (None, Some(w)) if w.text == *watched => out.push_str(&format!(
+                    "survived step {} at rank {}\n",
+                    w.step,
+                    w.rank.map_or(0, |r| r + 1)
+                )),
+                (None, _) => out.push_str("never fully evaluated\n"),
+            }
+        }
+        out
+    }
+
+    /// The surviving search tree as nodes and parent -> child edges. The
+    /// watched text's pruned prefix, if any, is added as a `pruned` node
+    /// under the survivor it extends.
+    pub fn graph(&self) -> SearchGraph {
+        let watched = self.watched.as_deref().unwrap_or_default();
+        let last = self.steps.last().map_or(0, |s| s.step);
+        let mut nodes: Vec<GraphNode> = self
+            .steps
+            .iter()
+            .flat_map(|s| &s.survivors)
+            .map(|r| GraphNode {
+                id: r.id,
+                step: r.step,
+                text: r.text.clone(),
+                width: r.width,
+                score: r.score,
+                on_watch: !watched.is_empty() && watched.starts_with(&r.text),
+                final_beam: r.step == last,
+                pruned: false,
+            })
+            .collect();
+        let mut edges: Vec<(usize, usize)> = self
+            .steps
+            .iter()
+            .flat_map(|s| &s.survivors)
+            .filter_map(|r| r.parent.map(|p| (p, r.id)))
+            .collect();
+
+        if let Some(lost) = self.watch.iter().find(|w| w.rank.is_none()) {
+            let id = nodes.len();
+            let prefix = lost
+                .text
+                .char_indices()
+                .last()
+                .map_or("", |(i, _)| &lost.text[..i]);
+            let parent = self
+                .steps
+                .iter()
+                .find(|s| s.step + 1 == lost.step)
+                .and_then(|s| s.survivors.iter().find(|r| r.text == prefix));
+            nodes.push(GraphNode {
+                id,
+                step: lost.step,
+                text: lost.text.clone(),
+                width: lost.width,
+                score: lost.score,
+                on_watch: true,
+                final_beam: false,
+                pruned: true,
+            });
+            if let Some(parent) = parent {
+                edges.push((parent.id, id));
+            }
+        }
+        SearchGraph { nodes, edges }
+    }
+
+    pub fn write(&self, path: &str, top: usize) -> io::Result<()> {
+        let data = match path.ends_with(".json") {
+            true => self.to_json()?,
+            false => self.to_text(top),
+        };
+        fs::write(path, data)
+    }
+}
@@ -0,0 +1,126 @@
+// ============================================
+// RAGGED-EDGE PRIOR FOR PARAGRAPH-FINAL LINES
+// ============================================
+
+use crate::Beam;
+
+/// Fill ratios of final lines are counted in this many equal bins of the
+/// column width.
+const RAGGED_BINS: usize = 10;
+
+/// Add-one smoothing, so a bin never seen in the visible paragraphs keeps
+/// some mass.
+const RAGGED_ALPHA: f32 = 1.0;
+
+/// How full the last line of a left-aligned paragraph is, learned from the
+/// paragraphs that are visible. A redaction over a paragraph's last line is
+/// often drawn to the right margin, so its width only bounds the text; this
+/// prior says where inside that bound the text is likely to end.
+#[derive(Clone, Debug)]
+pub struct RaggedEdgePrior {
+    pub column_width: f32,
+    /// Log-probability of each fill-ratio bin.
+    pub log_probs: Vec<f32>,
+    pub samples: usize,
+}
+
+/// A width to search a paragraph-final line at: the centre of a prior bin,
+/// its half-width as tolerance and the bin's log-probability.
+#[derive(Clone, Copy, Debug)]
+pub struct WidthHypothesis {
+    pub width: f32,
+    pub tolerance: f32,
+    pub log_prior: f32,
+}
+
+impl RaggedEdgePrior {
+    /// Learns from the line widths of visible paragraphs, top to bottom. The
+    /// column width is the widest non-final line; paragraphs of a single
+    /// line only contribute their final width.
+    pub fn from_paragraphs(paragraphs: &[Vec<f32>]) -> Self {
+        let column_width = paragraphs
+            .iter()
+            .flat_map(|p| p.iter().rev().skip(1))
+            .cloned()
+            .fold(0.0f32, f32::max);
+
+        let mut counts = [0usize; RAGGED_BINS];
+        let mut samples = 0;
+        if column_width > 0.0 {
+            for last in paragraphs.iter().filter_map(|p| p.last()) {
+                counts[Self::bin(last / column_width)] += 1;
+                samples += 1;
+            }
+        }
+
+        let total = samples as f32 + RAGGED_ALPHA * RAGGED_BINS as f32;
+        RaggedEdgePrior {
+            column_width,
+            log_probs: counts
+                .iter()
+                .map(|&c| ((c as f32 + RAGGED_ALPHA) / total).ln())
+                .collect(),
+            samples,
+        }
+    }
+
+    fn bin(ratio: f32) -> usize {
+        ((ratio.clamp(0.0, 1.0) * RAGGED_BINS as f32) as usize).min(RAGGED_BINS - 1)
+    }
+
+    fn bin_width(&self) -> f32 {
+        self.column_width / RAGGED_BINS as f32
+    }
+
+    /// Log-probability of a final line ending at `width`.
+    pub fn log_prior(&self, width: f32) -> f32 {
+        if self.column_width <= 0.0 {
+            return 0.0;
+        }
+        self.log_probs[Self::bin(width / self.column_width)]
+    }
+
+    /// Most likely widths first, until they hold `mass` of the prior, for a
+    /// final line whose redaction is `max_width` wide. Bins are cut at
+    /// `max_width`.
+    pub fn hypotheses(&self, max_width: f32, mass: f32) -> Vec<WidthHypothesis> {
+        let step = self.bin_width();
+        if step <= 0.0 {
+            return vec![];
+        }
+
+        let mut bins: Vec<WidthHypothesis> = self
+            .log_probs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &log_prior)| {
+                let lo = i as f32 * step;
+                let hi = ((i + 1) as f32 * step).min(max_width);
+                (hi > lo).then_some(WidthHypothesis {
+                    width: (lo + hi) / 2.0,
+                    tolerance: (hi - lo) / 2.0,
+                    log_prior,
+                })
+            })
+            .collect();
+        bins.sort_by(|a, b| b.log_prior.total_cmp(&a.log_prior));
+
+        let available: f32 = bins.iter().map(|h| h.log_prior.exp()).sum();
+        let mut covered = 0.0;
+        bins.into_iter()
+            .take_while(|h| {
+                let keep = covered < mass * available;
+                covered += h.log_prior.exp();
+                keep
+            })
+            .collect()
+    }
+
+    /// Adds `weight * log_prior(width)` to every beam and re-sorts.
+    pub fn apply(&self, beams: &mut [Beam], weight: f32) {
+        for b in beams.iter_mut() {
+            b.score += weight * self.log_prior(b.width);
+        }
+        beams.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+    }
+}
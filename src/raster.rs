@@ -0,0 +1,228 @@
+// ============================================
+// RASTER INPUT: REDACTION BOXES IN SCREENSHOTS
+// ============================================
+
+use crate::{BBox, Document, Line, LineHints};
+use image::GrayImage;
+use std::collections::VecDeque;
+use std::io;
+use std::path::Path;
+use ttf_parser::Face;
+
+/// Inputs with these extensions are screenshots, read for their redaction
+/// boxes rather than as line widths or PDFs.
+pub const IMAGE_EXTENSIONS: [&str; 3] = ["png", "jpg", "jpeg"];
+
+pub fn is_image_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| IMAGE_EXTENSIONS.iter().any(|x| e.eq_ignore_ascii_case(x)))
+}
+
+/// Connected component of dark pixels.
+#[derive(Clone, Debug)]
+pub struct Component {
+    pub bbox: BBox,
+    pub pixels: usize,
+}
+
+impl Component {
+    /// Share of the bounding box covered by the component.
+    pub fn fill_ratio(&self) -> f32 {
+        let area = self.bbox.w * self.bbox.h;
+        if area > 0.0 {
+            self.pixels as f32 / area
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RasterRedaction {
+    pub bbox: BBox,
+    /// Font size in px estimated from visible text on the same line.
+    pub px_size: f32,
+    /// True when no neighbouring text was found and the size comes from
+    /// the box height alone.
+    pub px_from_box: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct RasterOptions {
+    pub dark_threshold: u8, // pixels at or below are "ink"
+    pub min_box_width: f32,
+    pub min_box_height: f32,
+    pub min_fill: f32, // redaction boxes are (almost) solid
+}
+
+impl Default for RasterOptions {
+    fn default() -> Self {
+        RasterOptions {
+            dark_threshold: 80,
+            min_box_width: 12.0,
+            min_box_height: 6.0,
+            min_fill: 0.92,
+        }
+    }
+}
+
+pub fn load_grayscale(path: &str) -> io::Result<GrayImage> {
+    image::open(path)
+        .map(|img| img.to_luma8())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+/// 4-connected components of pixels at or below `dark_threshold`.
+pub fn dark_components(img: &GrayImage, dark_threshold: u8) -> Vec<Component> {
+    let (w, h) = img.dimensions();
+    let mut seen = vec![false; (w * h) as usize];
+    let mut out = vec![];
+
+    for y in 0..h {
+        for x in 0..w {
+            let idx = (y * w + x) as usize;
+            if seen[idx] || img.get_pixel(x, y)[0] > dark_threshold {
+                continue;
+            }
+
+            let (mut x0, mut y0, mut x1, mut y1) = (x, y, x, y);
+            let mut pixels = 0;
+            let mut queue = VecDeque::from([(x, y)]);
+            seen[idx] = true;
+
+            while let Some((cx, cy)) = queue.pop_front() {
+                pixels += 1;
+                x0 = x0.min(cx);
+                y0 = y0.min(cy);
+                x1 = x1.max(cx);
+                y1 = y1.max(cy);
+
+                let neighbours = [
+                    (cx.wrapping_sub(1), cy),
+                    (cx + 1, cy),
+                    (cx, cy.wrapping_sub(1)),
+                    (cx, cy + 1),
+                ];
+                for (nx, ny) in neighbours {
+                    if nx >= w || ny >= h {
+                        continue;
+                    }
+                    let nidx = (ny * w + nx) as usize;
+                    if !seen[nidx] && img.get_pixel(nx, ny)[0] <= dark_threshold {
+                        seen[nidx] = true;
+                        queue.push_back((nx, ny));
+                    }
+                }
+            }
+
+            out.push(Component {
+                bbox: BBox {
+                    x: x0 as f32,
+                    y: y0 as f32,
+                    w: (x1 - x0 + 1) as f32,
+                    h: (y1 - y0 + 1) as f32,
+                },
+                pixels,
+            });
+        }
+    }
+
+    out
+}
+
+fn vertical_overlap(a: &BBox, b: &BBox) -> f32 {
+    ((a.y + a.h).min(b.y + b.h) - a.y.max(b.y)).max(0.0)
+}
+
+/// Finds solid dark rectangles and estimates the font size of each line
+/// from the vertical extent of the glyph components beside it. `extent_em`
+/// is the font's ascender-to-descender height in em units.
+pub fn detect_redactions(
+    img: &GrayImage,
+    options: &RasterOptions,
+    extent_em: f32,
+) -> Vec<RasterRedaction> {
+    let components = dark_components(img, options.dark_threshold);
+    let is_box = |c: &Component| {
+        c.bbox.w >= options.min_box_width
+            && c.bbox.h >= options.min_box_height
+            && c.fill_ratio() >= options.min_fill
+    };
+
+    let (boxes, glyphs): (Vec<&Component>, Vec<&Component>) =
+        components.iter().partition(|c| is_box(c));
+
+    let mut out: Vec<RasterRedaction> = boxes
+        .into_iter()
+        .map(|b| {
+            let line: Vec<&BBox> = glyphs
+                .iter()
+                .map(|g| &g.bbox)
+                .filter(|g| vertical_overlap(g, &b.bbox) > 0.5 * g.h.min(b.bbox.h))
+                .collect();
+
+            if line.is_empty() {
+                return RasterRedaction {
+                    bbox: b.bbox.clone(),
+                    px_size: b.bbox.h / extent_em,
+                    px_from_box: true,
+                };
+            }
+
+            let top = line.iter().map(|g| g.y).fold(f32::INFINITY, f32::min);
+            let bottom = line
+                .iter()
+                .map(|g| g.y + g.h)
+                .fold(f32::NEG_INFINITY, f32::max);
+
+            RasterRedaction {
+                bbox: b.bbox.clone(),
+                px_size: (bottom - top) / extent_em,
+                px_from_box: false,
+            }
+        })
+        .collect();
+
+    // reading order: top to bottom, then left to right
+    out.sort_by(|a, b| {
+        (a.bbox.y, a.bbox.x)
+            .partial_cmp(&(b.bbox.y, b.bbox.x))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    out
+}
+
+/// Ascender-to-descender height of `face` in em units.
+pub fn face_extent_em(face: &Face) -> f32 {
+    (face.ascender() as f32 - face.descender() as f32) / face.units_per_em() as f32
+}
+
+/// Converts detected boxes into document lines whose widths are expressed
+/// at `reference_px`, the size the glyph width table was built for.
+pub fn document_from_redactions(redactions: &[RasterRedaction], reference_px: f32) -> Document {
+    Document {
+        lines: redactions
+            .iter()
+            .map(|r| Line {
+                observed_width: r.bbox.w * reference_px / r.px_size.max(1.0),
+                beams: vec![],
+                hints: LineHints::default(),
+            })
+            .collect(),
+    }
+}
+
+pub fn load_image_document(
+    path: &str,
+    face: &Face,
+    reference_px: f32,
+    options: &RasterOptions,
+) -> io::Result<(Document, Vec<RasterRedaction>)> {
+    let img = load_grayscale(path)?;
+    let redactions = detect_redactions(&img, options, face_extent_em(face));
+    Ok((
+        document_from_redactions(&redactions, reference_px),
+        redactions,
+    ))
+}
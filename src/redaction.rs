@@ -0,0 +1,628 @@
+// ============================================
+// REDACTION TECHNIQUE DETECTION IN PDF CONTENT
+// ============================================
+
+use crate::pdf_metrics::{extract_font_metrics, PdfFontMetrics};
+use crate::{BBox, Document, Line, LineHints};
+use lopdf::content::Content;
+use lopdf::{Document as PdfDocument, Object};
+use std::collections::HashMap;
+use std::io;
+
+/// A kerning adjustment or text move that skips more than this many ems on
+/// the same baseline is treated as removed text. Word spaces are about a
+/// quarter em, so justified text stays well below it.
+const REMOVED_TEXT_EMS: f32 = 1.0;
+
+/// Fill colours at or below this gray level count as redaction ink.
+const DARK_FILL: f32 = 0.25;
+
+/// Consecutive runs on baselines closer than this share of the font size
+/// are on the same line.
+const SAME_BASELINE: f32 = 0.3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RedactionTechnique {
+    /// An image XObject pasted over the region.
+    ImageOverlay,
+    /// A dark filled path (usually `re f`).
+    VectorBox,
+    /// The text operators were removed but the following text kept its
+    /// position, leaving a gap in the run.
+    TextRemoval,
+}
+
+/// What to do with a region, decided by the evidence it left.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecoveryStrategy {
+    /// The covered text is still in the content stream. `width` is the span
+    /// it occupies in user space, for checking it against the layout.
+    ExtractUnderlying { text: String, width: f32 },
+    /// Restore by width from the gap the removed text left, in user space.
+    WidthFromGap(f32),
+    /// Restore by width from the covering box, in user space. Boxes are
+    /// often padded, so this is the least precise.
+    WidthFromBox(f32),
+}
+
+#[derive(Clone, Debug)]
+pub struct RedactionRegion {
+    pub page: u32,
+    pub technique: RedactionTechnique,
+    /// User space, origin bottom left.
+    pub bbox: BBox,
+    /// Size of the text set on the region's baseline, when there is any.
+    pub font_size: Option<f32>,
+    pub strategy: RecoveryStrategy,
+}
+
+impl RedactionRegion {
+    /// Text still present under the cover, recovered exactly.
+    pub fn exact_text(&self) -> Option<&str> {
+        match &self.strategy {
+            RecoveryStrategy::ExtractUnderlying { text, .. } => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Line for the restoration pipeline, with widths scaled from the text's
+    /// font size to `px_size`. Exactly recovered text is carried as
+    /// `exact_text` so the pipeline passes it through without searching.
+    /// `None` for width-based regions whose font size is unknown.
+    pub fn to_line(&self, px_size: f32) -> Option<Line> {
+        let (width, exact_text) = match &self.strategy {
+            RecoveryStrategy::WidthFromGap(w) | RecoveryStrategy::WidthFromBox(w) => (*w, None),
+            RecoveryStrategy::ExtractUnderlying { text, width } => (*width, Some(text.clone())),
+        };
+        let scale = match self.font_size {
+            Some(size) if size > 0.0 => px_size / size,
+            _ if exact_text.is_some() => 1.0,
+            _ => return None,
+        };
+        Some(Line {
+            observed_width: width * scale,
+            beams: vec![],
+            hints: LineHints {
+                exact_text,
+                page: Some(self.page),
+                ..LineHints::default()
+            },
+        })
+    }
+}
+
+/// One line per region, in region order, for the restoration pipeline.
+/// Regions whose text is still in the PDF bypass inference.
+pub fn route_regions(regions: &[RedactionRegion], px_size: f32) -> Document {
+    Document {
+        lines: regions.iter().filter_map(|r| r.to_line(px_size)).collect(),
+    }
+}
+
+// ---------- content stream walk ----------
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Matrix([f32; 6]);
+
+impl Matrix {
+    const IDENTITY: Matrix = Matrix([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    /// `self` applied first, then `other` (PDF row-vector convention).
+    fn then(&self, other: &Matrix) -> Matrix {
+        let [a, b, c, d, e, f] = self.0;
+        let [a2, b2, c2, d2, e2, f2] = other.0;
+        Matrix([
+            a * a2 + b * c2,
+            a * b2 + b * d2,
+            c * a2 + d * c2,
+            c * b2 + d * d2,
+            e * a2 + f * c2 + e2,
+            e * b2 + f * d2 + f2,
+        ])
+    }
+
+    fn translate(tx: f32, ty: f32) -> Matrix {
+        Matrix([1.0, 0.0, 0.0, 1.0, tx, ty])
+    }
+
+    fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        let [a, b, c, d, e, f] = self.0;
+        (a * x + c * y + e, b * x + d * y + f)
+    }
+
+    /// Axis-aligned box of the rectangle `(x, y, w, h)` under the matrix.
+    fn rect(&self, x: f32, y: f32, w: f32, h: f32) -> BBox {
+        let corners = [
+            self.apply(x, y),
+            self.apply(x + w, y),
+            self.apply(x, y + h),
+            self.apply(x + w, y + h),
+        ];
+        let (x0, x1) = corners.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| {
+            (lo.min(p.0), hi.max(p.0))
+        });
+        let (y0, y1) = corners.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| {
+            (lo.min(p.1), hi.max(p.1))
+        });
+        BBox {
+            x: x0,
+            y: y0,
+            w: x1 - x0,
+            h: y1 - y0,
+        }
+    }
+}
+
+/// Shown text with its box and the user-space span of each character, in
+/// drawing order.
+#[derive(Clone, Debug)]
+struct TextRun {
+    bbox: BBox,
+    chars: Vec<(char, f32, f32)>,
+    font_size: f32,
+    order: usize,
+}
+
+impl TextRun {
+    /// Characters whose centre lies inside `cover`, without surrounding
+    /// spaces, and the width they span.
+    fn covered_text(&self, cover: &BBox) -> (String, f32) {
+        let cy = self.bbox.y + self.bbox.h / 2.0;
+        if cy < cover.y || cy > cover.y + cover.h {
+            return (String::new(), 0.0);
+        }
+        let covered: Vec<&(char, f32, f32)> = self
+            .chars
+            .iter()
+            .filter(|(_, x0, x1)| {
+                let cx = (x0 + x1) / 2.0;
+                cx >= cover.x && cx <= cover.x + cover.w
+            })
+            .collect();
+        let first = covered.iter().position(|c| c.0 != ' ');
+        let last = covered.iter().rposition(|c| c.0 != ' ');
+        match (first, last) {
+            (Some(a), Some(b)) => (
+                covered[a..=b].iter().map(|c| c.0).collect(),
+                covered[b].2 - covered[a].1,
+            ),
+            _ => (String::new(), 0.0),
+        }
+    }
+}
+
+/// A covering shape, in drawing order.
+struct Cover {
+    technique: RedactionTechnique,
+    bbox: BBox,
+    order: usize,
+}
+
+/// A skipped stretch on a baseline.
+struct Gap {
+    bbox: BBox,
+    font_size: f32,
+}
+
+fn num(obj: &Object) -> f32 {
+    obj.as_float()
+        .ok()
+        .or_else(|| obj.as_i64().ok().map(|v| v as f32))
+        .unwrap_or(0.0)
+}
+
+fn nums(operands: &[Object]) -> Vec<f32> {
+    operands.iter().map(num).collect()
+}
+
+fn overlaps(a: &BBox, b: &BBox) -> bool {
+    a.x < b.x + b.w && b.x < a.x + a.w && a.y < b.y + b.h && b.y < a.y + a.h
+}
+
+#[derive(Clone)]
+struct TextState {
+    font: Option<String>,
+    size: f32,
+    char_spacing: f32,
+    word_spacing: f32,
+    scale: f32,
+    leading: f32,
+}
+
+struct PageWalk<'a> {
+    fonts: &'a HashMap<String, &'a PdfFontMetrics>,
+    images: Vec<Vec<u8>>,
+    runs: Vec<TextRun>,
+    covers: Vec<Cover>,
+    gaps: Vec<Gap>,
+    ctm: Matrix,
+    stack: Vec<(Matrix, f32)>,
+    fill_gray: f32,
+    path: Vec<BBox>,
+    text: TextState,
+    tm: Matrix,
+    tlm: Matrix,
+    /// User-space end of the last run and its baseline, to spot text moves
+    /// that jump over removed text.
+    pen: Option<(f32, f32)>,
+}
+
+impl PageWalk<'_> {
+    fn advance(&self, ch: char) -> f32 {
+        let w = self
+            .text
+            .font
+            .as_ref()
+            .and_then(|f| self.fonts.get(f))
+            .and_then(|m| m.widths.get(&ch).copied().or(m.default_width))
+            .unwrap_or(500.0);
+        let space = if ch == ' ' {
+            self.text.word_spacing
+        } else {
+            0.0
+        };
+        (w / 1000.0 * self.text.size + self.text.char_spacing + space) * self.text.scale
+    }
+
+    fn text_to_user(&self) -> Matrix {
+        self.tm.then(&self.ctm)
+    }
+
+    /// Records a gap when the pen jumped further than `REMOVED_TEXT_EMS`
+    /// along the baseline since the last shown text.
+    fn check_jump(&mut self) {
+        let (x, y) = self.text_to_user().apply(0.0, 0.0);
+        if let Some((end, baseline)) = self.pen {
+            let size = self.text.size;
+            if (y - baseline).abs() < SAME_BASELINE * size && x - end > REMOVED_TEXT_EMS * size {
+                self.gaps.push(Gap {
+                    bbox: BBox {
+                        x: end,
+                        y,
+                        w: x - end,
+                        h: size,
+                    },
+                    font_size: size,
+                });
+            }
+        }
+    }
+
+    fn show(&mut self, bytes: &[u8], order: usize) {
+        self.check_jump();
+        let start = self.text_to_user().apply(0.0, 0.0);
+        let mut chars = vec![];
+        for &b in bytes {
+            // simple fonts: Latin-1, as in `pdf_metrics`
+            let ch = b as char;
+            let x0 = self.text_to_user().apply(0.0, 0.0).0;
+            let tx = self.advance(ch);
+            self.tm = Matrix::translate(tx, 0.0).then(&self.tm);
+            chars.push((ch, x0, self.text_to_user().apply(0.0, 0.0).0));
+        }
+        let end = self.text_to_user().apply(0.0, 0.0);
+        let size = self.text.size;
+        self.runs.push(TextRun {
+            bbox: BBox {
+                x: start.0,
+                y: start.1,
+                w: end.0 - start.0,
+                h: size,
+            },
+            chars,
+            font_size: size,
+            order,
+        });
+        self.pen = Some((end.0, end.1));
+    }
+
+    fn kern(&mut self, amount: f32) {
+        let tx = -amount / 1000.0 * self.text.size * self.text.scale;
+        let before = self.text_to_user().apply(0.0, 0.0);
+        self.tm = Matrix::translate(tx, 0.0).then(&self.tm);
+        if tx > REMOVED_TEXT_EMS * self.text.size {
+            let after = self.text_to_user().apply(0.0, 0.0);
+            let size = self.text.size;
+            self.gaps.push(Gap {
+                bbox: BBox {
+                    x: before.0,
+                    y: before.1,
+                    w: after.0 - before.0,
+                    h: size,
+                },
+                font_size: size,
+            });
+            // the gap is recorded; do not count it again as a jump
+            self.pen = Some(after);
+        }
+    }
+
+    fn next_line(&mut self, tx: f32, ty: f32) {
+        self.tlm = Matrix::translate(tx, ty).then(&self.tlm);
+        self.tm = self.tlm;
+    }
+
+    fn op(&mut self, operator: &str, operands: &[Object], order: usize) {
+        let n = nums(operands);
+        let arg = |i: usize| n.get(i).copied().unwrap_or(0.0);
+        match operator {
+            "q" => self.stack.push((self.ctm, self.fill_gray)),
+            "Q" => {
+                if let Some((ctm, gray)) = self.stack.pop() {
+                    self.ctm = ctm;
+                    self.fill_gray = gray;
+                }
+            }
+            "cm" if n.len() == 6 => {
+                self.ctm = Matrix([n[0], n[1], n[2], n[3], n[4], n[5]]).then(&self.ctm);
+            }
+            "g" => self.fill_gray = arg(0),
+            "rg" => self.fill_gray = (arg(0) + arg(1) + arg(2)) / 3.0,
+            "k" => self.fill_gray = (1.0 - arg(3)) * (1.0 - (arg(0) + arg(1) + arg(2)) / 3.0),
+            "re" => self
+                .path
+                .push(self.ctm.rect(arg(0), arg(1), arg(2), arg(3))),
+            "f" | "F" | "f*" | "B" | "B*" | "b" | "b*" => {
+                if self.fill_gray <= DARK_FILL {
+                    for bbox in self.path.drain(..) {
+                        self.covers.push(Cover {
+                            technique: RedactionTechnique::VectorBox,
+                            bbox,
+                            order,
+                        });
+                    }
+                }
+                self.path.clear();
+            }
+            "n" | "S" | "s" => self.path.clear(),
+            "Do" => {
+                let is_image = operands
+                    .first()
+                    .and_then(|o| o.as_name().ok())
+                    .is_some_and(|name| self.images.iter().any(|i| i == name));
+                if is_image {
+                    let bbox = self.ctm.rect(0.0, 0.0, 1.0, 1.0);
+                    self.covers.push(Cover {
+                        technique: RedactionTechnique::ImageOverlay,
+                        bbox,
+                        order,
+                    });
+                }
+            }
+            "BT" => {
+                self.tm = Matrix::IDENTITY;
+                self.tlm = Matrix::IDENTITY;
+                self.pen = None;
+            }
+            "Tf" => {
+                self.text.font = operands
+                    .first()
+                    .and_then(|o| o.as_name_str().ok())
+                    .map(String::from);
+                self.text.size = arg(1);
+            }
+            "Tc" => self.text.char_spacing = arg(0),
+            "Tw" => self.text.word_spacing = arg(0),
+            "Tz" => self.text.scale = arg(0) / 100.0,
+            "TL" => self.text.leading = arg(0),
+            "Td" => self.next_line(arg(0), arg(1)),
+            "TD" => {
+                self.text.leading = -arg(1);
+                self.next_line(arg(0), arg(1));
+            }
+            "T*" => self.next_line(0.0, -self.text.leading),
+            "Tm" if n.len() == 6 => {
+                self.tlm = Matrix([n[0], n[1], n[2], n[3], n[4], n[5]]);
+                self.tm = self.tlm;
+            }
+            "Tj" | "'" | "\"" => {
+                if operator != "Tj" {
+                    self.next_line(0.0, -self.text.leading);
+                }
+                if let Some(Ok(bytes)) = operands.last().map(|o| o.as_str()) {
+                    self.show(bytes, order);
+                }
+            }
+            "TJ" => {
+                if let Some(Ok(items)) = operands.first().map(|o| o.as_array()) {
+                    for item in items {
+                        match item.as_str() {
+                            Ok(bytes) => self.show(bytes, order),
+                            Err(_) => self.kern(num(item)),
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Names of the image XObjects in the page's resources.
+fn page_images(doc: &PdfDocument, page_id: lopdf::ObjectId) -> Vec<Vec<u8>> {
+    let Ok((resources, inherited)) = doc.get_page_resources(page_id) else {
+        return vec![];
+    };
+    let dicts = resources.into_iter().chain(
+        inherited
+            .iter()
+            .filter_map(|id| doc.get_dictionary(*id).ok()),
+    );
+
+    let mut out = vec![];
+    for res in dicts {
+        let Some(xobjects) = res
+            .get(b"XObject")
+            .ok()
+            .and_then(|o| doc.dereference(o).ok())
+            .and_then(|(_, o)| o.as_dict().ok())
+        else {
+            continue;
+        };
+        for (name, obj) in xobjects.iter() {
+            let is_image = doc
+                .dereference(obj)
+                .ok()
+                .and_then(|(_, o)| o.as_stream().ok())
+                .and_then(|s| s.dict.get(b"Subtype").ok())
+                .and_then(|o| o.as_name().ok())
+                .is_some_and(|s| s == b"Image");
+            if is_image {
+                out.push(name.clone());
+            }
+        }
+    }
+    out
+}
+
+/// Runs the page's content stream through the walker.
+fn walk_page<'a>(
+    doc: &PdfDocument,
+    fonts: &'a HashMap<String, &'a PdfFontMetrics>,
+    page_id: lopdf::ObjectId,
+) -> Option<PageWalk<'a>> {
+    let ops = doc
+        .get_page_content(page_id)
+        .and_then(|c| Content::decode(&c))
+        .ok()?;
+
+    let mut walk = PageWalk {
+        fonts,
+        images: page_images(doc, page_id),
+        runs: vec![],
+        covers: vec![],
+        gaps: vec![],
+        ctm: Matrix::IDENTITY,
+        stack: vec![],
+        fill_gray: 0.0,
+        path: vec![],
+        text: TextState {
+            font: None,
+            size: 0.0,
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            scale: 1.0,
+            leading: 0.0,
+        },
+        tm: Matrix::IDENTITY,
+        tlm: Matrix::IDENTITY,
+        pen: None,
+    };
+    for (order, op) in ops.operations.iter().enumerate() {
+        walk.op(&op.operator, &op.operands, order);
+    }
+    Some(walk)
+}
+
+/// Every redaction region on every page, classified by the evidence it left:
+/// covers (images or dark boxes) drawn over text or over a gap in a text
+/// run, and gaps no cover explains. Only simple-font text is decoded.
+pub fn detect_redactions(doc: &PdfDocument) -> Vec<RedactionRegion> {
+    let metrics = extract_font_metrics(doc);
+    let fonts: HashMap<String, &PdfFontMetrics> = metrics
+        .iter()
+        .map(|m| (m.resource_name.clone(), m))
+        .collect();
+    let mut out = vec![];
+
+    for (page, page_id) in doc.get_pages() {
+        let Some(walk) = walk_page(doc, &fonts, page_id) else {
+            continue;
+        };
+
+        let mut explained = vec![false; walk.gaps.len()];
+        for cover in &walk.covers {
+            // text drawn after the cover is on top of it, not hidden
+            let under: Vec<(&TextRun, (String, f32))> = walk
+                .runs
+                .iter()
+                .filter(|r| r.order < cover.order && overlaps(&r.bbox, &cover.bbox))
+                .map(|r| (r, r.covered_text(&cover.bbox)))
+                .filter(|(_, (text, _))| !text.is_empty())
+                .collect();
+            let gap = walk
+                .gaps
+                .iter()
+                .enumerate()
+                .find(|(_, g)| overlaps(&g.bbox, &cover.bbox));
+            let font_size = under
+                .first()
+                .map(|(r, _)| r.font_size)
+                .or(gap.map(|(_, g)| g.font_size))
+                .or_else(|| {
+                    walk.runs
+                        .iter()
+                        .find(|r| (r.bbox.y - cover.bbox.y).abs() < cover.bbox.h)
+                        .map(|r| r.font_size)
+                });
+
+            let strategy = if !under.is_empty() {
+                RecoveryStrategy::ExtractUnderlying {
+                    text: under
+                        .iter()
+                        .map(|(_, t)| t.0.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    width: under.iter().map(|(_, t)| t.1).sum(),
+                }
+            } else if let Some((i, g)) = gap {
+                explained[i] = true;
+                RecoveryStrategy::WidthFromGap(g.bbox.w)
+            } else {
+                RecoveryStrategy::WidthFromBox(cover.bbox.w)
+            };
+
+            out.push(RedactionRegion {
+                page,
+                technique: cover.technique,
+                bbox: cover.bbox.clone(),
+                font_size,
+                strategy,
+            });
+        }
+
+        for (gap, _) in walk.gaps.iter().zip(&explained).filter(|(_, e)| !**e) {
+            out.push(RedactionRegion {
+                page,
+                technique: RedactionTechnique::TextRemoval,
+                bbox: gap.bbox.clone(),
+                font_size: Some(gap.font_size),
+                strategy: RecoveryStrategy::WidthFromGap(gap.bbox.w),
+            });
+        }
+    }
+
+    out
+}
+
+/// Text of `page` inside `bbox`, in drawing order, whatever is drawn over
+/// it. Used on the unredacted original to learn what a region hides.
+pub fn text_under(doc: &PdfDocument, page: u32, bbox: &BBox) -> String {
+    let metrics = extract_font_metrics(doc);
+    let fonts: HashMap<String, &PdfFontMetrics> = metrics
+        .iter()
+        .map(|m| (m.resource_name.clone(), m))
+        .collect();
+    let Some(walk) = doc
+        .get_pages()
+        .get(&page)
+        .and_then(|id| walk_page(doc, &fonts, *id))
+    else {
+        return String::new();
+    };
+    walk.runs
+        .iter()
+        .filter(|r| overlaps(&r.bbox, bbox))
+        .map(|r| r.covered_text(bbox).0)
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn load_pdf(path: &str) -> io::Result<PdfDocument> {
+    PdfDocument::load(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+pub fn load_redactions(path: &str) -> io::Result<Vec<RedactionRegion>> {
+    Ok(detect_redactions(&load_pdf(path)?))
+}
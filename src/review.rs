@@ -0,0 +1,393 @@
+// ============================================
+// INTERACTIVE REVIEW OF RESTORATIONS
+// ============================================
+
+use crate::output::softmax_confidence;
+use crate::{anchor_bonus, quantize, Document};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line as TextLine;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReviewCandidate {
+    pub text: String,
+    /// Measured width; 0 in projects saved before it was recorded.
+    #[serde(default)]
+    pub width: f32,
+    pub score: f32,
+    pub confidence: f32, // softmax of scores within the line
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum Decision {
+    #[default]
+    Pending,
+    Approved(String),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReviewLine {
+    pub observed_width: f32,
+    pub candidates: Vec<ReviewCandidate>,
+    #[serde(default)]
+    pub decision: Decision,
+    #[serde(default)]
+    pub rejected: Vec<String>,
+    #[serde(default)]
+    pub pinned: Option<String>,
+}
+
+impl ReviewLine {
+    /// Text shown for this line: approved, else pinned, else best
+    /// candidate that was not rejected.
+    pub fn current_text(&self) -> Option<&str> {
+        if let Decision::Approved(t) = &self.decision {
+            return Some(t);
+        }
+        if let Some(p) = &self.pinned {
+            return Some(p);
+        }
+        self.candidates
+            .iter()
+            .find(|c| !self.rejected.contains(&c.text))
+            .map(|c| c.text.as_str())
+    }
+}
+
+/// Review state of a whole document, persisted as the project file.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ReviewProject {
+    pub lines: Vec<ReviewLine>,
+}
+
+impl ReviewProject {
+    pub fn from_document(doc: &Document, top_k: usize) -> Self {
+        let lines = doc
+            .lines
+            .iter()
+            .map(|line| {
+                let beams: Vec<_> = line.beams.iter().take(top_k).collect();
+                let conf = softmax_confidence(&beams.iter().map(|b| b.score).collect::<Vec<_>>());
+                ReviewLine {
+                    observed_width: line.observed_width,
+                    candidates: beams
+                        .iter()
+                        .zip(conf)
+                        .map(|(b, confidence)| ReviewCandidate {
+                            text: b.text.clone(),
+                            width: b.width,
+                            score: b.score,
+                            confidence,
+                        })
+                        .collect(),
+                    decision: Decision::Pending,
+                    rejected: vec![],
+                    pinned: None,
+                }
+            })
+            .collect();
+
+        ReviewProject { lines }
+    }
+
+    pub fn load(path: &str) -> io::Result<Self> {
+        let data = fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::other)?;
+        fs::write(path, json)
+    }
+
+    pub fn approve(&mut self, line: usize, candidate: usize) {
+        if let Some(l) = self.lines.get_mut(line) {
+            if let Some(c) = l.candidates.get(candidate) {
+                let text = c.text.clone();
+                l.rejected.retain(|r| *r != text);
+                l.decision = Decision::Approved(text);
+            }
+        }
+    }
+
+    pub fn reject(&mut self, line: usize, candidate: usize) {
+        if let Some(l) = self.lines.get_mut(line) {
+            if let Some(c) = l.candidates.get(candidate) {
+                let text = c.text.clone();
+                if l.decision == Decision::Approved(text.clone()) {
+                    l.decision = Decision::Pending;
+                }
+                if l.pinned.as_ref() == Some(&text) {
+                    l.pinned = None;
+                }
+                if !l.rejected.contains(&text) {
+                    l.rejected.push(text);
+                }
+            }
+        }
+    }
+
+    /// Pins a candidate to the top of its line; pinning it again unpins.
+    pub fn toggle_pin(&mut self, line: usize, candidate: usize) {
+        if let Some(l) = self.lines.get_mut(line) {
+            if let Some(c) = l.candidates.get(candidate) {
+                l.pinned = if l.pinned.as_ref() == Some(&c.text) {
+                    None
+                } else {
+                    Some(c.text.clone())
+                };
+            }
+        }
+    }
+
+    pub fn reset(&mut self, line: usize) {
+        if let Some(l) = self.lines.get_mut(line) {
+            l.decision = Decision::Pending;
+            l.rejected.clear();
+            l.pinned = None;
+        }
+    }
+
+    /// Approved texts keyed by quantized width, in the format used by
+    /// `stabilize_document`.
+    pub fn anchors(&self) -> HashMap<i32, String> {
+        self.lines
+            .iter()
+            .filter_map(|l| match &l.decision {
+                Decision::Approved(t) => Some((quantize(l.observed_width), t.clone())),
+                Decision::Pending => None,
+            })
+            .collect()
+    }
+
+    /// Feeds review decisions back into a document with the same lines:
+    /// rejected beams are dropped, approvals act as anchors for every line
+    /// of the same width and pinned beams are moved to the top.
+    pub fn apply_to_document(&self, doc: &mut Document) {
+        let anchors = self.anchors();
+
+        for (line, review) in doc.lines.iter_mut().zip(&self.lines) {
+            line.beams.retain(|b| !review.rejected.contains(&b.text));
+
+            for beam in &mut line.beams {
+                beam.score += anchor_bonus(&beam.text, line.observed_width, &anchors);
+            }
+            line.beams
+                .sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+
+            if let Some(pin) = &review.pinned {
+                if let Some(pos) = line.beams.iter().position(|b| &b.text == pin) {
+                    let beam = line.beams.remove(pos);
+                    line.beams.insert(0, beam);
+                }
+            }
+        }
+    }
+}
+
+// ---------- terminal UI ----------
+
+struct ReviewState {
+    line: ListState,
+    candidate: usize,
+    dirty: bool,
+    message: String,
+}
+
+fn status_mark(line: &ReviewLine) -> &'static str {
+    match (&line.decision, &line.pinned) {
+        (Decision::Approved(_), _) => "✓",
+        (Decision::Pending, Some(_)) => "📌",
+        _ if !line.rejected.is_empty() => "✗",
+        _ => " ",
+    }
+}
+
+fn draw(frame: &mut Frame, project: &ReviewProject, state: &mut ReviewState) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(5),
+            Constraint::Length(5),
+            Constraint::Length(3),
+        ])
+        .split(frame.area());
+    let cols = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(45), Constraint::Percentage(55)])
+        .split(rows[0]);
+
+    let items: Vec<ListItem> = project
+        .lines
+        .iter()
+        .enumerate()
+        .map(|(i, l)| {
+            ListItem::new(format!(
+                "{:>3} {} {:>8.2}px  {}",
+                i + 1,
+                status_mark(l),
+                l.observed_width,
+                l.current_text().unwrap_or("-")
+            ))
+        })
+        .collect();
+    let lines = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(" Lines "))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(lines, cols[0], &mut state.line);
+
+    let selected = state.line.selected().unwrap_or(0);
+    let current = project.lines.get(selected);
+
+    let candidates: Vec<TextLine> = current
+        .map(|l| {
+            l.candidates
+                .iter()
+                .enumerate()
+                .map(|(i, c)| {
+                    let mut tags = String::new();
+                    if l.decision == Decision::Approved(c.text.clone()) {
+                        tags.push_str(" [approved]");
+                    }
+                    if l.pinned.as_ref() == Some(&c.text) {
+                        tags.push_str(" [pinned]");
+                    }
+                    if l.rejected.contains(&c.text) {
+                        tags.push_str(" [rejected]");
+                    }
+                    let text = format!(
+                        "{} {:<24} {:>6.1}%  score {:>8.2}{}",
+                        if i == state.candidate { ">" } else { " " },
+                        c.text,
+                        c.confidence * 100.0,
+                        c.score,
+                        tags
+                    );
+                    let style = if l.rejected.contains(&c.text) {
+                        Style::default().fg(Color::DarkGray)
+                    } else if i == state.candidate {
+                        Style::default().add_modifier(Modifier::BOLD)
+                    } else {
+                        Style::default()
+                    };
+                    TextLine::styled(text, style)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    frame.render_widget(
+        Paragraph::new(candidates)
+            .block(Block::default().borders(Borders::ALL).title(" Candidates ")),
+        cols[1],
+    );
+
+    let context_line = |i: Option<usize>| {
+        i.and_then(|i| project.lines.get(i))
+            .and_then(|l| l.current_text())
+            .unwrap_or("")
+            .to_string()
+    };
+    let context = vec![
+        TextLine::from(format!("prev: {}", context_line(selected.checked_sub(1)))),
+        TextLine::from(format!("this: {}", context_line(Some(selected)))),
+        TextLine::from(format!("next: {}", context_line(Some(selected + 1)))),
+    ];
+    frame.render_widget(
+        Paragraph::new(context).block(Block::default().borders(Borders::ALL).title(" Context ")),
+        rows[1],
+    );
+
+    let help = format!(
+        "↑↓ line  ←→ candidate  a approve  r reject  p pin  u reset  s save  q quit   {}",
+        state.message
+    );
+    frame.render_widget(
+        Paragraph::new(help).block(Block::default().borders(Borders::ALL)),
+        rows[2],
+    );
+}
+
+/// Runs the review UI on `project`, writing it to `path` on save and quit.
+pub fn run_review_tui(project: &mut ReviewProject, path: &str) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let mut state = ReviewState {
+        line: ListState::default().with_selected(Some(0)),
+        candidate: 0,
+        dirty: false,
+        message: String::new(),
+    };
+
+    let result = loop {
+        if let Err(e) = terminal.draw(|f| draw(f, project, &mut state)) {
+            break Err(e);
+        }
+
+        let key = match event::read() {
+            Ok(Event::Key(k)) if k.kind == KeyEventKind::Press => k,
+            Ok(_) => continue,
+            Err(e) => break Err(e),
+        };
+
+        let line = state.line.selected().unwrap_or(0);
+        let candidate_count = project.lines.get(line).map_or(0, |l| l.candidates.len());
+        state.message.clear();
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => break Ok(()),
+            KeyCode::Down | KeyCode::Char('j') if line + 1 < project.lines.len() => {
+                state.line.select(Some(line + 1));
+                state.candidate = 0;
+            }
+            KeyCode::Up | KeyCode::Char('k') if line > 0 => {
+                state.line.select(Some(line - 1));
+                state.candidate = 0;
+            }
+            KeyCode::Right | KeyCode::Char('l') if state.candidate + 1 < candidate_count => {
+                state.candidate += 1;
+            }
+            KeyCode::Left | KeyCode::Char('h') => {
+                state.candidate = state.candidate.saturating_sub(1)
+            }
+            KeyCode::Char('a') => {
+                project.approve(line, state.candidate);
+                state.dirty = true;
+            }
+            KeyCode::Char('r') => {
+                project.reject(line, state.candidate);
+                state.dirty = true;
+            }
+            KeyCode::Char('p') => {
+                project.toggle_pin(line, state.candidate);
+                state.dirty = true;
+            }
+            KeyCode::Char('u') => {
+                project.reset(line);
+                state.dirty = true;
+            }
+            KeyCode::Char('s') => {
+                state.message = match project.save(path) {
+                    Ok(()) => {
+                        state.dirty = false;
+                        format!("saved to {}", path)
+                    }
+                    Err(e) => format!("save failed: {}", e),
+                };
+            }
+            _ => {}
+        }
+    };
+
+    ratatui::restore();
+
+    if state.dirty {
+        project.save(path)?;
+    }
+    result
+}
@@ -0,0 +1,274 @@
+// ============================================
+// SCORE TERMS AND NORMALIZATION
+// ============================================
+
+use crate::{ngram_log_prob, Beam, NGramModel, ScoreWeights};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// The terms `combined_score` adds up for one hypothesis, each already
+/// multiplied by its weight.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct ScoreTerms {
+    pub width: f32,
+    pub length: f32,
+    pub spaces: f32,
+    pub ngram: f32,
+}
+
+impl ScoreTerms {
+    pub fn of(
+        text: &str,
+        measured_width: f32,
+        target_width: f32,
+        weights: &ScoreWeights,
+        model: Option<&NGramModel>,
+    ) -> Self {
+        ScoreTerms {
+            width: -weights.width * (measured_width - target_width).abs(),
+            length: -weights.word_len * text.chars().count() as f32,
+            spaces: weights.spaces * text.matches(' ').count() as f32,
+            ngram: model.map_or(0.0, |m| weights.ngram * ngram_log_prob(text, m)),
+        }
+    }
+
+    /// The components before weighting: width error in px (negated),
+    /// length in characters (negated), spaces, and log-likelihood in nats.
+    pub fn raw(
+        text: &str,
+        measured_width: f32,
+        target_width: f32,
+        model: Option<&NGramModel>,
+    ) -> Self {
+        let unit = ScoreWeights {
+            width: 1.0,
+            word_len: 1.0,
+            spaces: 1.0,
+            ngram: 1.0,
+        };
+        Self::of(text, measured_width, target_width, &unit, model)
+    }
+
+    pub fn weighted(&self, weights: &ScoreWeights) -> Self {
+        ScoreTerms {
+            width: weights.width * self.width,
+            length: weights.word_len * self.length,
+            spaces: weights.spaces * self.spaces,
+            ngram: weights.ngram * self.ngram,
+        }
+    }
+
+    pub(crate) fn to_array(self) -> [f32; 4] {
+        [self.width, self.length, self.spaces, self.ngram]
+    }
+
+    fn from_array(a: [f32; 4]) -> Self {
+        ScoreTerms {
+            width: a[0],
+            length: a[1],
+            spaces: a[2],
+            ngram: a[3],
+        }
+    }
+
+    pub fn total(&self) -> f32 {
+        self.width + self.length + self.spaces + self.ngram
+    }
+
+    /// The component where `self` falls furthest behind `other`, and by how
+    /// much (negative). `None` when `self` is ahead on every component.
+    pub fn largest_deficit(&self, other: &ScoreTerms) -> Option<(&'static str, f32)> {
+        [
+            ("width", self.width - other.width),
+            ("length", self.length - other.length),
+            ("spaces", self.spaces - other.spaces),
+            ("ngram", self.ngram - other.ngram),
+        ]
+        .into_iter()
+        .filter(|(_, d)| *d < 0.0)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+/// Typical spread of each raw component. Width error is in pixels, the
+/// n-gram term in nats and length in characters, so the weights only mean
+/// something once each component is divided by its scale. The default (all
+/// 1) leaves scores as they are.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScoreScales {
+    pub width: f32,
+    pub length: f32,
+    pub spaces: f32,
+    pub ngram: f32,
+}
+
+impl Default for ScoreScales {
+    fn default() -> Self {
+        ScoreScales {
+            width: 1.0,
+            length: 1.0,
+            spaces: 1.0,
+            ngram: 1.0,
+        }
+    }
+}
+
+/// Mean and standard deviation of each component over `pool`.
+fn moments(pool: &[ScoreTerms]) -> ([f32; 4], [f32; 4]) {
+    let n = pool.len().max(1) as f32;
+    let mut mean = [0.0; 4];
+    let mut std = [0.0; 4];
+    for c in pool {
+        for (m, v) in mean.iter_mut().zip(c.to_array()) {
+            *m += v / n;
+        }
+    }
+    for c in pool {
+        for ((s, m), v) in std.iter_mut().zip(mean).zip(c.to_array()) {
+            *s += (v - m).powi(2) / n;
+        }
+    }
+    (mean, std.map(f32::sqrt))
+}
+
+impl ScoreScales {
+    /// Standard deviation of each raw component over a candidate pool, e.g.
+    /// the beams of a few representative lines. Components that do not vary
+    /// keep a scale of 1.
+    pub fn measure(pool: &[ScoreTerms]) -> Self {
+        let (_, std) = moments(pool);
+        let [width, length, spaces, ngram] = std.map(|s| if s > f32::EPSILON { s } else { 1.0 });
+        ScoreScales {
+            width,
+            length,
+            spaces,
+            ngram,
+        }
+    }
+
+    /// Weights that score raw components as `weight * component / scale`.
+    pub fn apply(&self, weights: &ScoreWeights) -> ScoreWeights {
+        ScoreWeights {
+            width: weights.width / self.width,
+            word_len: weights.word_len / self.length,
+            spaces: weights.spaces / self.spaces,
+            ngram: weights.ngram / self.ngram,
+        }
+    }
+}
+
+/// How score components are brought to comparable scales before weighting.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoreNormalization {
+    /// Raw components, as `combined_score` has always added them.
+    #[default]
+    None,
+    /// Divide by fixed scales; the search itself runs on the scaled weights.
+    Reference(ScoreScales),
+    /// Z-score each component against the line's candidate pool once the
+    /// search is done. The search still prunes on raw weights.
+    Pool,
+}
+
+impl ScoreNormalization {
+    /// Weights the search should score with.
+    pub fn search_weights(&self, weights: &ScoreWeights) -> ScoreWeights {
+        match self {
+            ScoreNormalization::Reference(scales) => scales.apply(weights),
+            _ => weights.clone(),
+        }
+    }
+
+    /// With `Pool`, rescores `beams` as the weighted sum of their
+    /// standardized components and re-sorts them, best first. Whatever a
+    /// beam's score holds beyond `combined_score` (edit penalties, priors,
+    /// hint bonuses) is carried over unchanged. `weights` must be the ones
+    /// the beams were scored with.
+    pub fn rescore(
+        &self,
+        beams: &mut [Beam],
+        target_width: f32,
+        weights: &ScoreWeights,
+        model: Option<&NGramModel>,
+    ) {
+        if *self != ScoreNormalization::Pool || beams.len() < 2 {
+            return;
+        }
+        let raw: Vec<ScoreTerms> = beams
+            .iter()
+            .map(|b| ScoreTerms::raw(&b.text, b.width, target_width, model))
+            .collect();
+        let (mean, std) = moments(&raw);
+
+        for (beam, c) in beams.iter_mut().zip(&raw) {
+            let residual = beam.score - c.weighted(weights).total();
+            let mut z = c.to_array();
+            for ((v, m), s) in z.iter_mut().zip(mean).zip(std) {
+                *v = if s > f32::EPSILON { (*v - m) / s } else { 0.0 };
+            }
+            beam.score = ScoreTerms::from_array(z).weighted(weights).total() + residual;
+        }
+        beams.sort_by(|a, b| b.score.total_cmp(&a.score));
+    }
+}
+
+/// The weights and the normalization applied ahead of them, which together
+/// decide how a line's candidates are searched and finally ranked.
+#[derive(Clone, Debug)]
+pub struct CompositeScorer {
+    pub weights: ScoreWeights,
+    pub normalization: ScoreNormalization,
+}
+
+impl CompositeScorer {
+    pub fn new(weights: ScoreWeights, normalization: ScoreNormalization) -> Self {
+        CompositeScorer {
+            weights,
+            normalization,
+        }
+    }
+
+    /// Weights the search should score with.
+    pub fn search_weights(&self) -> ScoreWeights {
+        self.normalization.search_weights(&self.weights)
+    }
+
+    /// Ranks a finished line's beams, standardizing their terms against the
+    /// pool first when the normalization asks for it.
+    pub fn rescore(&self, beams: &mut [Beam], target_width: f32, model: Option<&NGramModel>) {
+        self.normalization
+            .rescore(beams, target_width, &self.search_weights(), model);
+    }
+}
+
+impl FromStr for ScoreNormalization {
+    type Err = String;
+
+    /// `none`, `pool`, or `scales:WIDTH,LENGTH,SPACES,NGRAM`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(ScoreNormalization::None),
+            "pool" => Ok(ScoreNormalization::Pool),
+            _ => {
+                let values = s
+                    .strip_prefix("scales:")
+                    .ok_or_else(|| "expected none, pool or scales:W,L,S,N".to_string())?
+                    .split(',')
+                    .map(|v| v.trim().parse::<f32>().map_err(|e| e.to_string()))
+                    .collect::<Result<Vec<f32>, String>>()?;
+                match values[..] {
+                    [width, length, spaces, ngram] if values.iter().all(|&v| v > 0.0) => {
+                        Ok(ScoreNormalization::Reference(ScoreScales {
+                            width,
+                            length,
+                            spaces,
+                            ngram,
+                        }))
+                    }
+                    _ => Err("scales takes four positive numbers".to_string()),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,452 @@
+// ============================================
+// PATTERN-CONSTRAINED WIDTH SOLVER
+// ============================================
+
+use crate::{
+    measure_text_kerning, ngram_log_prob, score_text, Beam, BeamHeap, NGramModel, ScoreWeights,
+    SearchStats,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::str::FromStr;
+use ttf_parser::Face;
+
+/// One element of a `WidthPattern`: a character set repeated `min` to
+/// `max` times; unbounded repeats stop where the width runs out.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PatternPart {
+    pub chars: Vec<char>,
+    pub min: usize,
+    pub max: Option<usize>,
+}
+
+/// The shape of the hidden text in a small regular-expression subset:
+/// literal characters, `.` (printable ASCII), classes like `[A-Za-z]` or
+/// `[-./]`, `\d`, `\s` (a space), `\w` and `\` escapes, each optionally
+/// followed by `?`, `*`, `+`, `{n}`, `{n,}` or `{n,m}`. No alternation,
+/// groups or negated classes, so every pattern is a sequence of parts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WidthPattern {
+    pub parts: Vec<PatternPart>,
+}
+
+fn printable() -> Vec<char> {
+    (' '..='~').collect()
+}
+
+/// The characters of a `[...]` class, each item with whether it was
+/// escaped; `a-z` is a range unless the `-` was escaped.
+fn class_set(items: &[(char, bool)]) -> Result<Vec<char>, String> {
+    let mut set = vec![];
+    let mut i = 0;
+    while i < items.len() {
+        match items.get(i..i + 3) {
+            Some(&[(from, false), ('-', false), (to, false)]) => {
+                if to < from {
+                    return Err(format!("invalid range '{}-{}'", from, to));
+                }
+                set.extend(from..=to);
+                i += 3;
+            }
+            _ => {
+                let (c, escaped) = items[i];
+                set.extend(if escaped { class_escape(c) } else { vec![c] });
+                i += 1;
+            }
+        }
+    }
+    Ok(set)
+}
+
+fn class_escape(ch: char) -> Vec<char> {
+    match ch {
+        'd' => ('0'..='9').collect(),
+        's' => vec![' '],
+        'w' => ('a'..='z')
+            .chain('A'..='Z')
+            .chain('0'..='9')
+            .chain(['_'])
+            .collect(),
+        c => vec![c],
+    }
+}
+
+/// `{n}`, `{n,}` or `{n,m}`, with `chars` just past the opening brace.
+fn parse_repeat(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+) -> Result<(usize, Option<usize>), String> {
+    let mut body = String::new();
+    loop {
+        match chars.next() {
+            Some('}') => break,
+            Some(c) => body.push(c),
+            None => return Err("unclosed '{'".into()),
+        }
+    }
+    let number = |s: &str| {
+        s.trim()
+            .parse::<usize>()
+            .map_err(|_| format!("invalid repeat count '{{{}}}'", body))
+    };
+    let (min, max) = match body.split_once(',') {
+        None => (number(&body)?, Some(number(&body)?)),
+        Some((min, max)) if max.trim().is_empty() => (number(min)?, None),
+        Some((min, max)) => (number(min)?, Some(number(max)?)),
+    };
+    match max {
+        Some(max) if max < min => Err(format!("repeat '{{{}}}' has max below min", body)),
+        _ => Ok((min, max)),
+    }
+}
+
+impl FromStr for WidthPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts: Vec<PatternPart> = vec![];
+        let mut chars = s.chars().peekable();
+        while let Some(ch) = chars.next() {
+            let set = match ch {
+                '.' => printable(),
+                '\\' => class_escape(chars.next().ok_or("pattern ends in '\\'")?),
+                '[' => {
+                    let mut items: Vec<(char, bool)> = vec![];
+                    loop {
+                        match chars.next() {
+                            Some(']') if !items.is_empty() => break,
+                            Some('\\') => {
+                                items.push((chars.next().ok_or("pattern ends in '\\'")?, true))
+                            }
+                            Some('^') if items.is_empty() => {
+                                return Err("negated classes are not supported".into())
+                            }
+                            Some(c) => items.push((c, false)),
+                            None => return Err("unclosed '['".into()),
+                        }
+                    }
+                    class_set(&items)?
+                }
+                '?' | '*' | '+' | '{' => return Err(format!("'{}' does not follow anything", ch)),
+                '(' | ')' | '|' => {
+                    return Err(format!(
+                        "'{}' is not supported; patterns are a sequence of parts",
+                        ch
+                    ))
+                }
+                c => vec![c],
+            };
+            let quantifier = chars.next_if(|c| "?*+{".contains(*c));
+            let (min, max) = match quantifier {
+                Some('?') => (0, Some(1)),
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some(_) => parse_repeat(&mut chars)?,
+                None => (1, Some(1)),
+            };
+            parts.push(PatternPart {
+                chars: dedup(set),
+                min,
+                max,
+            });
+        }
+        Ok(WidthPattern { parts })
+    }
+}
+
+fn dedup(set: Vec<char>) -> Vec<char> {
+    let mut seen = HashSet::new();
+    set.into_iter().filter(|c| seen.insert(*c)).collect()
+}
+
+/// Where a partial match is: inside part `part`, having emitted `count` of
+/// its characters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Position {
+    part: usize,
+    count: usize,
+}
+
+impl WidthPattern {
+    /// Parts a next character can come from at `pos`, with the position
+    /// after it: more of the current part, or the first of any later part
+    /// reachable by skipping parts that allow zero repeats.
+    fn steps(&self, pos: Position) -> Vec<(usize, Position)> {
+        let mut out = vec![];
+        if let Some(part) = self.parts.get(pos.part) {
+            if part.max.is_none_or(|max| pos.count < max) {
+                out.push((
+                    pos.part,
+                    Position {
+                        part: pos.part,
+                        count: pos.count + 1,
+                    },
+                ));
+            }
+            if pos.count < part.min {
+                return out;
+            }
+        }
+        for next in pos.part + 1..self.parts.len() {
+            if self.parts[next].max != Some(0) {
+                out.push((
+                    next,
+                    Position {
+                        part: next,
+                        count: 1,
+                    },
+                ));
+            }
+            if self.parts[next].min > 0 {
+                break;
+            }
+        }
+        out
+    }
+
+    /// True when the text may end at `pos`.
+    fn accepts(&self, pos: Position) -> bool {
+        self.parts.get(pos.part).is_none_or(|p| pos.count >= p.min)
+            && self.parts.iter().skip(pos.part + 1).all(|p| p.min == 0)
+    }
+
+    /// Least and most width the rest of a match can add after `pos`, from
+    /// each part's narrowest and widest character in `advances`. The most
+    /// is infinite when a part repeats without bound; the least is infinite
+    /// when a required part has no character with a width.
+    fn remaining_width(&self, pos: Position, advances: &HashMap<char, f32>) -> (f32, f32) {
+        let (mut least, mut most) = (0.0, 0.0);
+        for (i, part) in self.parts.iter().enumerate().skip(pos.part) {
+            let done = if i == pos.part { pos.count } else { 0 };
+            let widths = part.chars.iter().filter_map(|c| advances.get(c).copied());
+            let (narrow, wide) =
+                widths.fold((f32::INFINITY, 0.0f32), |(n, w), a| (n.min(a), w.max(a)));
+            if part.min > done {
+                least += (part.min - done) as f32 * narrow;
+            }
+            most += match part.max {
+                Some(max) => max.saturating_sub(done) as f32 * wide,
+                None if wide > 0.0 => f32::INFINITY,
+                None => 0.0,
+            };
+        }
+        (least, most)
+    }
+
+    /// Whether `text` matches the whole pattern.
+    pub fn matches(&self, text: &str) -> bool {
+        let mut positions: HashSet<Position> = [Position { part: 0, count: 0 }].into();
+        for ch in text.chars() {
+            positions = positions
+                .into_iter()
+                .flat_map(|pos| self.steps(pos))
+                .filter(|(part, _)| self.parts[*part].chars.contains(&ch))
+                .map(|(_, pos)| pos)
+                .collect();
+        }
+        positions.into_iter().any(|pos| self.accepts(pos))
+    }
+}
+
+/// Width buckets per px in `solve_pattern`'s table of prefixes.
+const WIDTH_BUCKETS_PER_PX: f32 = 4.0;
+
+/// Prefixes `solve_pattern` keeps per pattern position and width bucket,
+/// by default.
+pub const DEFAULT_PREFIXES_PER_STATE: usize = 16;
+
+fn pattern_advances(
+    face: &Face,
+    glyphs: &HashMap<char, f32>,
+    px_size: f32,
+    pattern: &WidthPattern,
+) -> HashMap<char, f32> {
+    pattern
+        .parts
+        .iter()
+        .flat_map(|p| p.chars.iter().copied())
+        .map(|ch| {
+            (
+                ch,
+                measure_text_kerning(&ch.to_string(), face, glyphs, px_size),
+            )
+        })
+        .filter(|&(_, adv)| adv > 0.0)
+        .collect()
+}
+
+/// `combined_score`, but the model reads `text` between the spaces it saw
+/// around lines in training (`train_from_reader` joins lines with one):
+/// a match shorter than the model's order still has grams to score, and a
+/// `complete` one must end where text can. Prefixes get the leading space
+/// only.
+pub fn pattern_score(
+    text: &str,
+    width: f32,
+    target_width: f32,
+    weights: &ScoreWeights,
+    model: Option<&NGramModel>,
+    complete: bool,
+) -> f32 {
+    let base = score_text(text, width, target_width, weights);
+    match model {
+        Some(m) if complete => base + weights.ngram * ngram_log_prob(&format!(" {} ", text), m),
+        Some(m) => base + weights.ngram * ngram_log_prob(&format!(" {}", text), m),
+        None => base,
+    }
+}
+
+/// Texts matching `pattern` whose width is within `tolerance` of
+/// `target_width`, best first, at most `limit`.
+///
+/// The search is a table over (position in the pattern, prefix width in
+/// quarter-px buckets) rather than one beam: each step extends every
+/// prefix by what the pattern allows next and keeps the `per_state` best
+/// per cell, by `pattern_score` without its width term (all of them while
+/// they are too short for the model to score). Prefixes of every
+/// width and shape survive, so the width decides which complete, and the
+/// model (if any) decides among prefixes of the same width. Extensions that
+/// can no longer end within tolerance, given the narrowest and widest
+/// characters the rest of the pattern allows, are dropped. Complete matches
+/// are measured as whole strings with `measure_text_kerning`.
+#[allow(clippy::too_many_arguments)]
+pub fn solve_pattern(
+    face: &Face,
+    glyphs: &HashMap<char, f32>,
+    px_size: f32,
+    target_width: f32,
+    tolerance: f32,
+    pattern: &WidthPattern,
+    weights: &ScoreWeights,
+    model: Option<&NGramModel>,
+    per_state: usize,
+    limit: usize,
+    stats: &SearchStats,
+) -> Vec<Beam> {
+    let advances = pattern_advances(face, glyphs, px_size, pattern);
+    let bucket = |width: f32| (width * WIDTH_BUCKETS_PER_PX).round() as i32;
+
+    // a prefix too short for the model to read yet ties with every other
+    // one; cutting those to `per_state` would keep whichever came first
+    let unscored = |text: &str| model.is_some_and(|m| text.chars().count() + 1 < m.n);
+
+    let mut table: BTreeMap<(Position, i32), Vec<Beam>> = BTreeMap::new();
+    table.insert(
+        (Position { part: 0, count: 0 }, 0),
+        vec![Beam {
+            text: String::new(),
+            width: 0.0,
+            score: 0.0,
+        }],
+    );
+    let mut done = BeamHeap::new(limit);
+    let mut found: HashSet<String> = HashSet::new();
+
+    while !table.is_empty() {
+        let mut next_table: BTreeMap<(Position, i32), Vec<Beam>> = BTreeMap::new();
+        let mut evaluated = 0;
+        for ((pos, _), mut prefixes) in table {
+            if !prefixes.first().is_some_and(|b| unscored(&b.text)) {
+                prefixes.sort_by(|a, b| b.score.total_cmp(&a.score));
+                prefixes.truncate(per_state);
+            }
+            stats.add_expanded(prefixes.len());
+            for (part, next) in pattern.steps(pos) {
+                // the rest of the pattern must still fit
+                let (least, most) = pattern.remaining_width(next, &advances);
+                for &ch in &pattern.parts[part].chars {
+                    let Some(&adv) = advances.get(&ch) else {
+                        continue;
+                    };
+                    for prefix in &prefixes {
+                        let width = prefix.width + adv;
+                        if width + least > target_width + tolerance
+                            || width + most < target_width - tolerance
+                        {
+                            continue;
+                        }
+                        let mut text = prefix.text.clone();
+                        text.push(ch);
+                        evaluated += 1;
+
+                        if pattern.accepts(next) && !found.contains(&text) {
+                            let measured = measure_text_kerning(&text, face, glyphs, px_size);
+                            if (measured - target_width).abs() <= tolerance {
+                                found.insert(text.clone());
+                                done.push(Beam {
+                                    score: pattern_score(
+                                        &text,
+                                        measured,
+                                        target_width,
+                                        weights,
+                                        model,
+                                        true,
+                                    ),
+                                    text: text.clone(),
+                                    width: measured,
+                                });
+                            }
+                        }
+
+                        let score = pattern_score(&text, width, width, weights, model, false);
+                        next_table
+                            .entry((next, bucket(width)))
+                            .or_default()
+                            .push(Beam { text, width, score });
+                    }
+                }
+            }
+        }
+        stats.add_evaluated(evaluated);
+        table = next_table;
+    }
+    done.into_sorted_vec()
+}
+
+/// How many texts match `pattern` within `tolerance` of `target_width`,
+/// counted without listing them (prefixes of equal position and width,
+/// to 1/64 px, are counted together; a text the pattern can split more
+/// than one way counts once per split). Shows how much a match list
+/// leaves out, and that a model is needed when it is large.
+pub fn count_pattern_matches(
+    face: &Face,
+    glyphs: &HashMap<char, f32>,
+    px_size: f32,
+    target_width: f32,
+    tolerance: f32,
+    pattern: &WidthPattern,
+) -> f64 {
+    let advances = pattern_advances(face, glyphs, px_size, pattern);
+    let key = |width: f32| (width * 64.0).round() as i64;
+
+    // (position, width key) -> (width, prefixes)
+    let mut table: BTreeMap<(Position, i64), (f32, f64)> = BTreeMap::new();
+    table.insert((Position { part: 0, count: 0 }, 0), (0.0, 1.0));
+    let mut total = 0.0;
+    while !table.is_empty() {
+        let mut next_table: BTreeMap<(Position, i64), (f32, f64)> = BTreeMap::new();
+        for ((pos, _), (width, count)) in table {
+            for (part, next) in pattern.steps(pos) {
+                let (least, most) = pattern.remaining_width(next, &advances);
+                for ch in &pattern.parts[part].chars {
+                    let Some(&adv) = advances.get(ch) else {
+                        continue;
+                    };
+                    let width = width + adv;
+                    if width + least > target_width + tolerance
+                        || width + most < target_width - tolerance
+                    {
+                        continue;
+                    }
+                    if pattern.accepts(next) && (width - target_width).abs() <= tolerance {
+                        total += count;
+                    }
+                    next_table
+                        .entry((next, key(width)))
+                        .or_insert((width, 0.0))
+                        .1 += count;
+                }
+            }
+        }
+        table = next_table;
+    }
+    total
+}
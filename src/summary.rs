@@ -0,0 +1,202 @@
+// ============================================
+// EXIT CODES AND THE RUN SUMMARY
+// ============================================
+
+use crate::feedback::AppliedFeedback;
+use crate::output::RestorationResults;
+use crate::watch::{EntryStatus, ManifestEntry};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::time::Duration;
+
+/// How a command ended. Every command exits with `exit_code()` and, last
+/// thing on stderr, prints its `RunSummary` as one JSON line, so batch
+/// wrappers can branch without parsing logs:
+///
+/// | code | outcome         | meaning                                          |
+/// |-----:|-----------------|--------------------------------------------------|
+/// |    0 | `resolved`      | every line resolved, or there was nothing to do  |
+/// |    1 | `error`         | failed after the input was read (I/O, ...)       |
+/// |    2 | `input_error`   | bad arguments, or input missing or malformed     |
+/// |    3 | `partial`       | some lines resolved, the others need review      |
+/// |    4 | `unrecoverable` | no line resolved                                 |
+///
+/// A line is resolved when it has a candidate and no diagnosis (see
+/// `diagnose_line`). `watch --once` counts files instead: a file resolves
+/// when it was processed without error. `width-solve` has one width, which
+/// resolves when a single text scores best and is partial when several tie.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Resolved,
+    Error,
+    InputError,
+    Partial,
+    Unrecoverable,
+}
+
+impl Outcome {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Outcome::Resolved => 0,
+            Outcome::Error => 1,
+            Outcome::InputError => 2,
+            Outcome::Partial => 3,
+            Outcome::Unrecoverable => 4,
+        }
+    }
+
+    /// Errors about what the user passed in are input errors; the rest are
+    /// not. The kind is read as where the error came from, so a missing or
+    /// unreadable input file is an input error, while writes go through
+    /// `output_error` first and a bad `--out` directory is an `Error`.
+    pub fn of_error(e: &io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::InvalidInput
+            | io::ErrorKind::InvalidData
+            | io::ErrorKind::NotFound
+            | io::ErrorKind::PermissionDenied
+            | io::ErrorKind::UnexpectedEof => Outcome::InputError,
+            _ => Outcome::Error,
+        }
+    }
+
+    fn of_counts(resolved: usize, total: usize) -> Self {
+        match resolved {
+            r if r == total => Outcome::Resolved,
+            0 => Outcome::Unrecoverable,
+            _ => Outcome::Partial,
+        }
+    }
+}
+
+/// Marks an error raised while writing output, whatever its kind, as a
+/// failed run rather than bad input.
+pub fn output_error(e: io::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RunSummary {
+    pub command: String,
+    pub outcome: Outcome,
+    pub exit_code: i32,
+    /// Lines, or files for `watch`.
+    pub items: usize,
+    pub resolved: usize,
+    pub unresolved: usize,
+    /// Failure mode label -> unresolved lines diagnosed with it.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub failure_modes: BTreeMap<String, usize>,
+    /// Review feedback `restore --feedback` applied.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub feedback: Option<AppliedFeedback>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub elapsed_ms: f64,
+}
+
+impl RunSummary {
+    fn new(command: &str, outcome: Outcome) -> Self {
+        RunSummary {
+            command: command.to_string(),
+            outcome,
+            exit_code: outcome.exit_code(),
+            items: 0,
+            resolved: 0,
+            unresolved: 0,
+            failure_modes: BTreeMap::new(),
+            feedback: None,
+            error: None,
+            elapsed_ms: 0.0,
+        }
+    }
+
+    fn counted(mut self, resolved: usize, total: usize) -> Self {
+        self.outcome = Outcome::of_counts(resolved, total);
+        self.exit_code = self.outcome.exit_code();
+        self.items = total;
+        self.resolved = resolved;
+        self.unresolved = total - resolved;
+        self
+    }
+
+    /// A command that has nothing to resolve and succeeded.
+    pub fn done(command: &str) -> Self {
+        Self::new(command, Outcome::Resolved)
+    }
+
+    pub fn from_error(command: &str, e: &io::Error) -> Self {
+        RunSummary {
+            error: Some(e.to_string()),
+            ..Self::new(command, Outcome::of_error(e))
+        }
+    }
+
+    pub fn from_results(command: &str, results: &RestorationResults) -> Self {
+        let mut summary = Self::new(command, Outcome::Resolved);
+        summary.feedback = results.feedback.clone();
+        let mut resolved = 0;
+        for line in &results.lines {
+            match (&line.diagnosis, line.candidates.is_empty()) {
+                (None, false) => resolved += 1,
+                (Some(d), _) => {
+                    *summary
+                        .failure_modes
+                        .entry(d.mode.label().to_string())
+                        .or_default() += 1
+                }
+                (None, true) => {
+                    *summary
+                        .failure_modes
+                        .entry("no candidates".to_string())
+                        .or_default() += 1
+                }
+            }
+        }
+        summary.counted(resolved, results.lines.len())
+    }
+
+    /// One width solved on its own (`width-solve`): resolved when one text
+    /// scores best, partial when the best scores tie, i.e. the width and
+    /// the pattern alone do not decide, unrecoverable when nothing matches.
+    pub fn from_matches(command: &str, matches: usize, tied: bool) -> Self {
+        let outcome = match (matches, tied) {
+            (0, _) => Outcome::Unrecoverable,
+            (_, true) => Outcome::Partial,
+            _ => Outcome::Resolved,
+        };
+        let mut summary = RunSummary {
+            items: 1,
+            resolved: (outcome == Outcome::Resolved) as usize,
+            unresolved: (outcome != Outcome::Resolved) as usize,
+            ..Self::new(command, outcome)
+        };
+        if tied {
+            summary
+                .failure_modes
+                .insert("tied best matches".to_string(), 1);
+        }
+        summary
+    }
+
+    /// Files handled by one pass over a drop folder.
+    pub fn from_entries(command: &str, entries: &[ManifestEntry]) -> Self {
+        let processed = entries
+            .iter()
+            .filter(|e| e.status == EntryStatus::Processed)
+            .count();
+        Self::new(command, Outcome::Resolved).counted(processed, entries.len())
+    }
+
+    pub fn with_elapsed(mut self, elapsed: Duration) -> Self {
+        self.elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+        self
+    }
+
+    /// One line of JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
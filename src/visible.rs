@@ -0,0 +1,207 @@
+// ============================================
+// NORMALIZING HARVESTED VISIBLE TEXT
+// ============================================
+
+use std::collections::{HashMap, HashSet};
+
+/// Lines at most this far from the top or bottom of a page are candidates
+/// for running headers and footers.
+const PAGE_EDGE_LINES: usize = 3;
+
+/// Without page breaks, a short line seen this many times is taken to be a
+/// repeated header rather than text.
+const MIN_REPEATS: usize = 3;
+
+/// Longer lines are body text even when they repeat.
+const MAX_HEADER_CHARS: usize = 80;
+
+/// The unredacted text of a document, cleaned up before it feeds
+/// dictionaries, language models or locale inference: running headers,
+/// footers and page numbers removed, words hyphenated across line breaks
+/// joined, and whitespace collapsed. Paragraphs (separated by blank lines
+/// in the input) come out one per line.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VisibleText {
+    pub text: String,
+    /// Words rejoined across a line break.
+    pub dehyphenated: usize,
+    /// Header, footer and page-number lines dropped.
+    pub headers_removed: usize,
+}
+
+/// Page numbers and dates differ from page to page: the same header with
+/// its digits masked.
+fn header_key(line: &str) -> String {
+    line.chars()
+        .map(|c| if c.is_ascii_digit() { '#' } else { c })
+        .collect()
+}
+
+/// "12", "- 12 -", "Page 3", "page 3 of 10", "iv".
+fn is_page_number(line: &str) -> bool {
+    let words: Vec<&str> = line
+        .split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|w| !w.is_empty())
+        .collect();
+    let number = |w: &str| {
+        w.chars().all(|c| c.is_ascii_digit())
+            || (w.len() <= 4 && w.chars().all(|c| "ivxlc".contains(c)))
+    };
+    match words.as_slice() {
+        [n] => number(n),
+        [p, n] => p.eq_ignore_ascii_case("page") && number(n),
+        [p, n, of, m] => {
+            p.eq_ignore_ascii_case("page")
+                && number(n)
+                && of.eq_ignore_ascii_case("of")
+                && number(m)
+        }
+        _ => false,
+    }
+}
+
+/// Tabs, no-break and other Unicode spaces to one space; soft hyphens and
+/// zero-width characters dropped.
+fn collapse_whitespace(line: &str) -> String {
+    let cleaned: String = line
+        .chars()
+        .filter(|c| !matches!(c, '\u{AD}' | '\u{200B}'..='\u{200D}' | '\u{FEFF}'))
+        .collect();
+    cleaned.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl VisibleText {
+    /// Pages are separated by form feeds, as `pdftotext` writes them. A
+    /// line near the top or bottom of a page that recurs, digits masked,
+    /// on more than half the pages (two at least) is a running header or
+    /// footer; so is a lone page number there. Without form feeds, short
+    /// lines that recur `MIN_REPEATS` times are dropped instead.
+    ///
+    /// A line ending in a letter and `-` is joined with the next line when
+    /// that starts lowercase. The hyphen goes unless the text also has the
+    /// hyphenated word on one line (`well-known`), i.e. it was a compound.
+    pub fn normalize(raw: &str) -> Self {
+        let pages: Vec<Vec<String>> = raw
+            .split('\u{C}')
+            .map(|page| page.lines().map(collapse_whitespace).collect::<Vec<_>>())
+            .filter(|page| page.iter().any(|l| !l.is_empty()))
+            .collect();
+        let single = pages.len() == 1;
+
+        // indices of the non-empty lines at a page's top and bottom
+        let edges = |page: &[String]| -> HashSet<usize> {
+            let filled: Vec<usize> = (0..page.len()).filter(|&i| !page[i].is_empty()).collect();
+            filled
+                .iter()
+                .take(PAGE_EDGE_LINES)
+                .chain(filled.iter().rev().take(PAGE_EDGE_LINES))
+                .copied()
+                .collect()
+        };
+        // pages a key is on, or occurrences on a single page
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for page in &pages {
+            let keys: Vec<String> = match single {
+                true => page
+                    .iter()
+                    .filter(|l| !l.is_empty() && l.len() <= MAX_HEADER_CHARS)
+                    .map(|l| header_key(l))
+                    .collect(),
+                false => edges(page)
+                    .into_iter()
+                    .map(|i| header_key(&page[i]))
+                    .collect::<HashSet<_>>()
+                    .into_iter()
+                    .collect(),
+            };
+            for key in keys {
+                *seen.entry(key).or_default() += 1;
+            }
+        }
+        let min_repeats = match single {
+            true => MIN_REPEATS,
+            false => (pages.len() / 2 + 1).max(2),
+        };
+
+        let mut out = VisibleText::default();
+        let mut lines: Vec<&str> = vec![];
+        for page in &pages {
+            let edge = edges(page);
+            for (i, line) in page.iter().enumerate() {
+                let running = seen
+                    .get(&header_key(line))
+                    .is_some_and(|&n| n >= min_repeats);
+                let at_edge = single || edge.contains(&i);
+                if at_edge && (running || (!single && is_page_number(line))) && !line.is_empty() {
+                    out.headers_removed += 1;
+                    continue;
+                }
+                lines.push(line);
+            }
+        }
+
+        let compounds: HashSet<&str> = lines
+            .iter()
+            .flat_map(|l| l.split(' '))
+            .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()))
+            .filter(|w| w.contains('-'))
+            .collect();
+        let breaks_word = |line: &str, next: Option<&&str>| {
+            let mut end = line.chars().rev();
+            end.next() == Some('-')
+                && end.next().is_some_and(char::is_alphabetic)
+                && next
+                    .and_then(|n| n.chars().next())
+                    .is_some_and(char::is_lowercase)
+        };
+
+        let mut paragraphs: Vec<String> = vec![];
+        let mut current = String::new();
+        let mut hyphenated = false;
+        for (i, line) in lines.iter().enumerate() {
+            if line.is_empty() {
+                if !current.is_empty() {
+                    paragraphs.push(std::mem::take(&mut current));
+                }
+                continue;
+            }
+            if hyphenated {
+                let head = current
+                    .rsplit(' ')
+                    .next()
+                    .unwrap_or("")
+                    .trim_start_matches(|c: char| !c.is_alphanumeric());
+                let tail = line
+                    .split(' ')
+                    .next()
+                    .unwrap_or("")
+                    .trim_end_matches(|c: char| !c.is_alphanumeric());
+                if !compounds.contains(format!("{}{}", head, tail).as_str()) {
+                    current.pop();
+                }
+                out.dehyphenated += 1;
+            } else if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(line);
+            hyphenated = breaks_word(line, lines.get(i + 1));
+        }
+        if !current.is_empty() {
+            paragraphs.push(current);
+        }
+        out.text = paragraphs.join("\n");
+        out
+    }
+
+    /// Distinct words, surrounding punctuation stripped, in order of first
+    /// appearance; for adding to a dictionary.
+    pub fn words(&self) -> Vec<&str> {
+        let mut seen = HashSet::new();
+        self.text
+            .split_whitespace()
+            .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()))
+            .filter(|w| !w.is_empty() && seen.insert(*w))
+            .collect()
+    }
+}
@@ -0,0 +1,268 @@
+// ============================================
+// DROP-FOLDER WATCHING AND THE BATCH MANIFEST
+// ============================================
+
+use crate::output::{OutputFormat, RestorationResults};
+use crate::pipeline::RestorePipeline;
+use crate::raster::{is_image_path, load_image_document, RasterOptions};
+use crate::redaction::{load_redactions, route_regions};
+use crate::summary::output_error;
+use crate::NGramModel;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// File name of the manifest kept in the watched folder.
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryStatus {
+    Processed,
+    Failed,
+}
+
+/// One input as last processed. `size` and `modified` identify the version
+/// of the file: a file replaced under the same name is processed again.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub file: String,
+    pub size: u64,
+    pub modified: u64,
+    pub status: EntryStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report: Option<String>,
+    #[serde(default)]
+    pub lines: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub processed_at: u64,
+}
+
+/// Every input of a batch and what became of it, sorted by file name.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BatchManifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl BatchManifest {
+    /// The manifest at `path`, or an empty one if there is none yet.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(data) => serde_json::from_str(&data)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Written to a temporary file and renamed, so a reader never sees a
+    /// half-written manifest.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("json.tmp");
+        fs::write(
+            &tmp,
+            serde_json::to_string_pretty(self).map_err(io::Error::other)?,
+        )?;
+        fs::rename(tmp, path)
+    }
+
+    pub fn get(&self, file: &str) -> Option<&ManifestEntry> {
+        self.entries.iter().find(|e| e.file == file)
+    }
+
+    /// Replaces the entry for the same file.
+    pub fn record(&mut self, entry: ManifestEntry) {
+        self.entries.retain(|e| e.file != entry.file);
+        self.entries.push(entry);
+        self.entries.sort_by(|a, b| a.file.cmp(&b.file));
+    }
+
+    pub fn count(&self, status: EntryStatus) -> usize {
+        self.entries.iter().filter(|e| e.status == status).count()
+    }
+}
+
+/// What processing one input produced.
+pub struct Processed {
+    pub report: PathBuf,
+    pub lines: usize,
+}
+
+/// Where the report for `pdf` goes: next to it, `<name>.restoration.<ext>`.
+pub fn report_path(pdf: &Path, format: OutputFormat) -> PathBuf {
+    pdf.with_extension(format!("restoration.{}", format.extension()))
+}
+
+/// Restores every redaction of the PDF, or the screenshot (see
+/// `is_image_path`), at `path` with `pipeline` and writes the top `top_k`
+/// candidates per region next to it.
+pub fn restore_pdf(
+    path: &Path,
+    pipeline: &mut RestorePipeline,
+    model: Option<&NGramModel>,
+    top_k: usize,
+    format: OutputFormat,
+) -> io::Result<Processed> {
+    let file = path.to_string_lossy();
+    let mut doc = match is_image_path(path) {
+        true => {
+            let options = RasterOptions::default();
+            load_image_document(&file, pipeline.face(), pipeline.config.px_size, &options)?.0
+        }
+        false => route_regions(&load_redactions(&file)?, pipeline.config.px_size),
+    };
+    let costs = pipeline.run(&mut doc)?;
+    let diagnoses = pipeline.diagnose(&doc);
+    let report = report_path(path, format);
+    RestorationResults::from_document(&doc, &pipeline.line_targets(&doc), model, top_k)
+        .with_costs(&costs)
+        .with_diagnoses(diagnoses)
+        .write(format, Some(&report.to_string_lossy()))?;
+    Ok(Processed {
+        report,
+        lines: doc.lines.len(),
+    })
+}
+
+/// Size and modification time (whole seconds) of a file.
+fn version(path: &Path) -> io::Result<(u64, u64)> {
+    let meta = fs::metadata(path)?;
+    let modified = meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    Ok((meta.len(), modified))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// A folder that PDFs and screenshots are dropped into. Each `poll`
+/// processes the files the manifest has no entry for at their current
+/// version. A file is only taken once its size and modification time held
+/// still between two polls, so a scanner or mail gateway still writing it
+/// is left alone.
+pub struct DropFolder {
+    pub dir: PathBuf,
+    pub manifest_path: PathBuf,
+    pub manifest: BatchManifest,
+    settle: bool,
+    last_seen: HashMap<String, (u64, u64)>,
+}
+
+impl DropFolder {
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        if !dir.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not a directory", dir.display()),
+            ));
+        }
+        let manifest_path = dir.join(MANIFEST_NAME);
+        Ok(DropFolder {
+            dir: dir.to_path_buf(),
+            manifest: BatchManifest::load(&manifest_path)?,
+            manifest_path,
+            settle: true,
+            last_seen: HashMap::new(),
+        })
+    }
+
+    /// Keeps the manifest at `path` instead of inside the folder.
+    pub fn with_manifest(mut self, path: &Path) -> io::Result<Self> {
+        self.manifest = BatchManifest::load(path)?;
+        self.manifest_path = path.to_path_buf();
+        Ok(self)
+    }
+
+    /// Takes files as soon as they are seen, for a one-off run over a
+    /// folder nothing is writing to.
+    pub fn without_settling(mut self) -> Self {
+        self.settle = false;
+        self
+    }
+
+    /// PDFs and screenshots ready to be processed, by file name.
+    fn ready(&mut self) -> io::Result<Vec<(String, (u64, u64))>> {
+        let mut seen = HashMap::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let is_input = is_image_path(&path)
+                || path
+                    .extension()
+                    .is_some_and(|e| e.eq_ignore_ascii_case("pdf"));
+            let Some(name) = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .filter(|_| is_input && path.is_file())
+            else {
+                continue;
+            };
+            // vanished between listing and stat: picked up next time if back
+            if let Ok(v) = version(&path) {
+                seen.insert(name.to_string(), v);
+            }
+        }
+
+        let mut ready: Vec<(String, (u64, u64))> = seen
+            .iter()
+            .filter(|(name, &(size, modified))| {
+                !self
+                    .manifest
+                    .get(name)
+                    .is_some_and(|e| e.size == size && e.modified == modified)
+            })
+            .filter(|(name, v)| !self.settle || self.last_seen.get(*name) == Some(v))
+            .map(|(name, &v)| (name.clone(), v))
+            .collect();
+        ready.sort();
+        self.last_seen = seen;
+        Ok(ready)
+    }
+
+    /// Processes every ready file with `process`, records the outcome in the
+    /// manifest and saves it after each file. A failure is recorded, not
+    /// returned; it is retried only once the file changes. Returns the new
+    /// entries.
+    pub fn poll<F>(&mut self, mut process: F) -> io::Result<Vec<ManifestEntry>>
+    where
+        F: FnMut(&Path) -> io::Result<Processed>,
+    {
+        let mut done = vec![];
+        for (name, (size, modified)) in self.ready()? {
+            let (status, report, lines, error) = match process(&self.dir.join(&name)) {
+                Ok(p) => (
+                    EntryStatus::Processed,
+                    Some(p.report.display().to_string()),
+                    p.lines,
+                    None,
+                ),
+                Err(e) => (EntryStatus::Failed, None, 0, Some(e.to_string())),
+            };
+            let entry = ManifestEntry {
+                file: name,
+                size,
+                modified,
+                status,
+                report,
+                lines,
+                error,
+                processed_at: now(),
+            };
+            self.manifest.record(entry.clone());
+            self.manifest
+                .save(&self.manifest_path)
+                .map_err(output_error)?;
+            done.push(entry);
+        }
+        Ok(done)
+    }
+}
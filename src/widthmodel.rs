@@ -0,0 +1,196 @@
+// ============================================
+// WIDTH PREDICTION FOR UNSEEN CHARACTERS
+// ============================================
+
+use crate::crossformat::{fit_bias, PipelineBias};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use ttf_parser::Face;
+
+/// Pairs a block needs before it gets its own fit; sparser blocks use the
+/// reference font's fit over all blocks.
+const MIN_BLOCK_PAIRS: usize = 5;
+
+/// Floor on a fit's residual spread, so a perfect fit over a handful of
+/// pairs does not claim an exact prediction.
+const MIN_STDERR_PX: f32 = 0.05;
+
+/// Coarse Unicode blocks. Advances relate to a reference font's the same way
+/// within a block (same designer, same proportions) more than across blocks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnicodeBlock {
+    BasicLatin,
+    Latin1Supplement,
+    LatinExtended,
+    Greek,
+    Cyrillic,
+    Punctuation,
+    Cjk,
+    Other,
+}
+
+impl UnicodeBlock {
+    pub fn of(ch: char) -> Self {
+        match ch as u32 {
+            0x0000..=0x007F => UnicodeBlock::BasicLatin,
+            0x0080..=0x00FF => UnicodeBlock::Latin1Supplement,
+            0x0100..=0x024F | 0x1E00..=0x1EFF => UnicodeBlock::LatinExtended,
+            0x0370..=0x03FF => UnicodeBlock::Greek,
+            0x0400..=0x052F => UnicodeBlock::Cyrillic,
+            0x2000..=0x206F => UnicodeBlock::Punctuation,
+            0x3000..=0x30FF | 0x4E00..=0x9FFF | 0xFF00..=0xFFEF => UnicodeBlock::Cjk,
+            _ => UnicodeBlock::Other,
+        }
+    }
+}
+
+/// A predicted advance and its standard error, both in px.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct WidthEstimate {
+    pub width: f32,
+    pub stderr: f32,
+    /// Reference fonts that mapped the character.
+    pub references: usize,
+}
+
+/// `target = scale * reference + offset` and the RMS residual of the fit.
+#[derive(Clone, Copy, Debug)]
+struct Regression {
+    bias: PipelineBias,
+    stderr: f32,
+}
+
+impl Regression {
+    fn fit(pairs: &[(f32, f32)]) -> Self {
+        let bias = fit_bias(pairs);
+        let sse: f32 = pairs
+            .iter()
+            .map(|&(x, y)| (y - bias.apply(x)).powi(2))
+            .sum();
+        let dof = pairs.len().saturating_sub(2).max(1) as f32;
+        Regression {
+            bias,
+            stderr: (sse / dof).sqrt().max(MIN_STDERR_PX),
+        }
+    }
+}
+
+struct ReferenceFit<'a> {
+    face: &'a Face<'a>,
+    overall: Option<Regression>,
+    blocks: HashMap<UnicodeBlock, Regression>,
+}
+
+fn advance(face: &Face, ch: char, px_size: f32) -> Option<f32> {
+    let scale = px_size / face.units_per_em() as f32;
+    face.glyph_index(ch)
+        .and_then(|g| face.glyph_hor_advance(g))
+        .map(|a| a as f32 * scale)
+}
+
+/// Predicts advances the width map lacks from fonts that have them: per
+/// reference font, a linear fit of the known advances against the
+/// reference's, one per Unicode block where the block has enough
+/// characters. Estimates from several references are combined by inverse
+/// variance.
+pub struct WidthPredictor<'a> {
+    px_size: f32,
+    fits: Vec<ReferenceFit<'a>>,
+}
+
+impl<'a> WidthPredictor<'a> {
+    pub fn fit(glyphs: &HashMap<char, f32>, references: &'a [Face<'a>], px_size: f32) -> Self {
+        let fits = references
+            .iter()
+            .map(|face| {
+                let mut by_block: HashMap<UnicodeBlock, Vec<(f32, f32)>> = HashMap::new();
+                for (&ch, &width) in glyphs {
+                    if let Some(reference) = advance(face, ch, px_size) {
+                        by_block
+                            .entry(UnicodeBlock::of(ch))
+                            .or_default()
+                            .push((reference, width));
+                    }
+                }
+                let all: Vec<(f32, f32)> = by_block.values().flatten().copied().collect();
+                ReferenceFit {
+                    face,
+                    overall: (!all.is_empty()).then(|| Regression::fit(&all)),
+                    blocks: by_block
+                        .into_iter()
+                        .filter(|(_, pairs)| pairs.len() >= MIN_BLOCK_PAIRS)
+                        .map(|(block, pairs)| (block, Regression::fit(&pairs)))
+                        .collect(),
+                }
+            })
+            .collect();
+        WidthPredictor { px_size, fits }
+    }
+
+    /// `None` when no reference font maps `ch` or none shares a character
+    /// with the width map.
+    pub fn predict(&self, ch: char) -> Option<WidthEstimate> {
+        let block = UnicodeBlock::of(ch);
+        let (mut weighted, mut precision, mut references) = (0.0, 0.0, 0);
+        for fit in &self.fits {
+            let Some(reference) = advance(fit.face, ch, self.px_size) else {
+                continue;
+            };
+            let Some(regression) = fit.blocks.get(&block).or(fit.overall.as_ref()) else {
+                continue;
+            };
+            let p = 1.0 / regression.stderr.powi(2);
+            weighted += regression.bias.apply(reference).max(0.0) * p;
+            precision += p;
+            references += 1;
+        }
+        (references > 0).then(|| WidthEstimate {
+            width: weighted / precision,
+            stderr: precision.sqrt().recip(),
+            references,
+        })
+    }
+
+    /// Adds an estimate to `glyphs` for every character of `chars` it lacks
+    /// and returns what was estimated.
+    pub fn fill(
+        &self,
+        glyphs: &mut HashMap<char, f32>,
+        chars: impl IntoIterator<Item = char>,
+    ) -> ApproximateWidths {
+        let mut approximate = ApproximateWidths::default();
+        for ch in chars {
+            if glyphs.contains_key(&ch) || approximate.estimates.contains_key(&ch) {
+                continue;
+            }
+            if let Some(estimate) = self.predict(ch) {
+                glyphs.insert(ch, estimate.width);
+                approximate.estimates.insert(ch, estimate);
+            }
+        }
+        approximate
+    }
+}
+
+/// Characters whose width-map entry is a prediction, not a measurement.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ApproximateWidths {
+    pub estimates: BTreeMap<char, WidthEstimate>,
+}
+
+impl ApproximateWidths {
+    pub fn is_empty(&self) -> bool {
+        self.estimates.is_empty()
+    }
+
+    /// Standard error of `text`'s width from its estimated characters,
+    /// treated as independent; 0 when every advance was measured.
+    pub fn uncertainty(&self, text: &str) -> f32 {
+        text.chars()
+            .filter_map(|ch| self.estimates.get(&ch))
+            .map(|e| e.stderr.powi(2))
+            .sum::<f32>()
+            .sqrt()
+    }
+}